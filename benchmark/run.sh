@@ -0,0 +1,33 @@
+#!/bin/sh
+# Times benchmark/fibo.pi on a release build and prints the median VM execution
+# time of 5 runs, as reported by -p.
+#
+#   benchmark/run.sh          the working tree
+#   benchmark/run.sh REV      any git revision, built in a temporary worktree
+#
+# The program always comes from the working tree. fibo.pi was first written with
+# `int32`/`int8`, which no revision's parser has ever taken (the types are
+# `i32`/`i8`), so revisions from before the commit that renamed them can't run
+# their own copy.
+set -e
+
+root=$(git rev-parse --show-toplevel)
+program="$root/benchmark/fibo.pi"
+runs=5
+
+if [ -n "$1" ]; then
+    tree=$(mktemp -d)
+    trap 'git -C "$root" worktree remove --force "$tree"' EXIT
+    git -C "$root" worktree add --detach --force "$tree" "$1" > /dev/null
+else
+    tree=$root
+fi
+
+cargo build --release --quiet --manifest-path "$tree/Cargo.toml"
+binary="$tree/target/release/pineapple"
+
+i=0
+while [ $i -lt $runs ]; do
+    "$binary" "$program" -p | awk '/^VM Execution/ { print $3 }'
+    i=$((i + 1))
+done | sort -n | awk -v runs=$runs 'NR == int(runs / 2) + 1 { print "VM Execution (median of " runs "): " $0 }'
@@ -0,0 +1,54 @@
+// Runs Pineapple code from a Rust program through pineapple_passes::Engine, the
+// same as the Embedding section of the readme: cargo run --example embed
+use std::error::Error;
+
+use pineapple_ir::{ValueTy, ValueWrapper};
+use pineapple_passes::Engine;
+use pineapple_session::NativeValue;
+
+const SOURCE: &str = "
+#fibo(n: i32): i32 {
+    if (n <= 1) {
+        return n;
+    }
+    return fibo(n - 1) + fibo(n - 2);
+}
+
+#greet(name: string): string {
+    print(\"greeting {name}\");
+    return \"hello \" + name;
+}
+
+#twice_fibo(n: i32): i32 {
+    return double(fibo(n));
+}
+";
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let engine = Engine::new().optimize(true).register_native(
+        "double",
+        vec![ValueTy::I32],
+        ValueTy::I32,
+        |args, _| match args[0] {
+            NativeValue::Value(ValueWrapper::I32(x)) => Ok(ValueWrapper::I32(x * 2).into()),
+            _ => Err("expected an i32".to_string()),
+        },
+    );
+
+    let mut program = engine.compile(SOURCE)?;
+    program.set_output(Box::new(std::io::stdout()));
+
+    let result = program.call("fibo", &[ValueWrapper::I32(20).into()])?;
+    println!("fibo(20) = {:?}", result);
+
+    let result = program.call("twice_fibo", &[ValueWrapper::I32(10).into()])?;
+    println!("twice_fibo(10) = {:?}", result);
+
+    let result = program.call("greet", &[NativeValue::Str("pineapple".to_string())])?;
+    println!("greet(\"pineapple\") = {:?}", result);
+
+    // Errors are Debug and std::error::Error, so they work with ? and unwrap
+    let error = program.call("fibo", &[]).unwrap_err();
+    println!("fibo() = {}", error);
+    Ok(())
+}
@@ -8,26 +8,39 @@ type Condition = Box<Expr>;
 type Body = Box<Stmt>;
 type Args = Vec<(Identifier, Type)>;
 type ReturnType = Type;
+type Line = usize;
+// Line and column of a literal, so errors about it can point at it
+type Position = (usize, usize);
+
+// The `///` comments right above a declaration, one line each
+type Docs = Vec<String>;
 
 #[derive(Debug, Clone)]
 pub enum Expr {
-    Value(Value),
+    Value(Value, Position),
     Variable(Identifier),
     Assign(Identifier, Option<Type>, Box<Expr>),
-    Binary(Box<Expr>, BinOp, Box<Expr>),
-    Logical(Box<Expr>, RelOp, Box<Expr>),
+    // The operand type is filled in by the typechecker
+    Binary(Box<Expr>, BinOp, Box<Expr>, Option<Type>),
+    Logical(Box<Expr>, RelOp, Box<Expr>, Option<Type>),
+    // `-x` of anything but a literal, which is folded by the parser instead
+    Negate(Box<Expr>, Option<Type>),
     Grouping(Box<Expr>),
     CastAs(Box<Expr>, Type),
     Call(Box<Expr>, Vec<Expr>),
+    // The parts of an interpolated string, the typechecker fills in their types
+    Interpolation(Vec<(Expr, Option<Type>)>),
 }
 
+// Every statement apart from blocks has the line of its first token, it ends up
+// in the debug line table of the module
 #[derive(Debug, Clone)]
 pub enum Stmt {
     Block(Vec<Stmt>),
-    If(Condition, Body, Option<Box<Stmt>>),
-    While(Condition, Body),
-    Expression(Box<Expr>),
-    Print(Vec<Expr>),
-    Return(Option<Box<Expr>>),
-    Function(Identifier, Args, ReturnType, Box<Stmt>),
+    If(Condition, Body, Option<Box<Stmt>>, Line),
+    While(Condition, Body, Line),
+    Expression(Box<Expr>, Line),
+    Print(Vec<Expr>, Line),
+    Return(Option<Box<Expr>>, Line),
+    Function(Identifier, Args, ReturnType, Box<Stmt>, Docs, Line),
 }
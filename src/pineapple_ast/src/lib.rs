@@ -1,5 +1,6 @@
 use pineapple_error::ParseError;
 use pineapple_ir::hir::token::Token;
+use pineapple_session::Interner;
 
 use crate::ast::Stmt;
 use crate::parser::Parser;
@@ -7,7 +8,7 @@ use crate::parser::Parser;
 pub mod ast;
 mod parser;
 
-pub fn parse(tokens: Vec<Token>) -> Result<Vec<Stmt>, ParseError> {
-    let mut parser = Parser::new(tokens);
+pub fn parse(tokens: Vec<Token>, interner: &mut Interner) -> Result<Vec<Stmt>, ParseError> {
+    let mut parser = Parser::new(tokens, interner);
     parser.parse()
 }
@@ -1,11 +1,14 @@
 use pineapple_error::ParseError;
 use pineapple_ir::op::BinOp;
 use pineapple_ir::op::RelOp;
-use pineapple_ir::Value;
+use pineapple_ir::value::ValueContainer;
 use pineapple_ir::{
-    hir::token::{Token, TokenKind},
+    hir::token::{StrPart, Token, TokenKind},
     ValueTy,
 };
+use pineapple_ir::{Value, ValueWrapper};
+use pineapple_session::Interner;
+use std::convert::TryFrom;
 
 use crate::ast::{Expr, Stmt};
 
@@ -51,15 +54,31 @@ impl From<&Token> for Precedence {
     }
 }
 
-pub struct Parser {
+pub struct Parser<'a> {
     tokens: Vec<Token>,
+
+    interner: &'a mut Interner,
 }
 
-impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        let mut tokens = tokens;
-        tokens.reverse();
-        Parser { tokens }
+impl<'a> Parser<'a> {
+    pub fn new(tokens: Vec<Token>, interner: &'a mut Interner) -> Self {
+        // A `///` that isn't right above a function (or above more of them that
+        // are) is a plain comment, anywhere else it gets dropped here
+        let mut documented = false;
+        let mut reversed = Vec::with_capacity(tokens.len());
+        for token in tokens.into_iter().rev() {
+            match token.kind {
+                TokenKind::DocComment(_) if !documented => continue,
+                TokenKind::DocComment(_) => (),
+                TokenKind::Fun => documented = true,
+                _ => documented = false,
+            }
+            reversed.push(token);
+        }
+        Parser {
+            tokens: reversed,
+            interner,
+        }
     }
 
     fn peek(&self) -> Result<&Token, ParseError> {
@@ -116,12 +135,26 @@ impl Parser {
 
     fn parse_declaration(&mut self) -> Result<Stmt, ParseError> {
         match self.peek()?.kind {
-            TokenKind::Fun => self.parse_function(),
+            TokenKind::DocComment(_) => self.parse_documented(),
+            TokenKind::Fun => self.parse_function(vec![]),
             _ => self.parse_statement(),
         }
     }
 
-    fn parse_function(&mut self) -> Result<Stmt, ParseError> {
+    // Doc comments belong to the function right after them, the only place
+    // `new` leaves them
+    fn parse_documented(&mut self) -> Result<Stmt, ParseError> {
+        let mut docs = vec![];
+        while let TokenKind::DocComment(_) = self.peek()?.kind {
+            if let TokenKind::DocComment(doc) = self.next()?.kind {
+                docs.push(doc);
+            }
+        }
+        self.parse_function(docs)
+    }
+
+    fn parse_function(&mut self, docs: Vec<String>) -> Result<Stmt, ParseError> {
+        let line = self.peek()?.line;
         self.consume(TokenKind::Fun)?;
 
         // Get Function Name
@@ -148,7 +181,14 @@ impl Parser {
 
         // Compile Body
         let body = Box::new(self.parse_block_statement()?);
-        Ok(Stmt::Function(function_name, parameters, return_type, body))
+        Ok(Stmt::Function(
+            function_name,
+            parameters,
+            return_type,
+            body,
+            docs,
+            line,
+        ))
     }
 
     fn parse_identifier_list(&mut self) -> Result<Vec<(Ident, ValueTy)>, ParseError> {
@@ -198,6 +238,7 @@ impl Parser {
     }
 
     fn parse_while_statement(&mut self) -> Result<Stmt, ParseError> {
+        let line = self.peek()?.line;
         self.consume(TokenKind::While)?;
         self.consume(TokenKind::LeftParen)?;
         let while_condition = self.parse_expression(Precedence::None)?;
@@ -208,6 +249,7 @@ impl Parser {
         Ok(Stmt::While(
             Box::new(while_condition),
             Box::new(while_block),
+            line,
         ))
     }
 
@@ -224,6 +266,7 @@ impl Parser {
     }
 
     fn parse_if_statement(&mut self) -> Result<Stmt, ParseError> {
+        let line = self.peek()?.line;
         self.consume(TokenKind::If)?;
         self.consume(TokenKind::LeftParen)?;
         let if_condition = self.parse_expression(Precedence::None)?;
@@ -232,11 +275,17 @@ impl Parser {
         let if_block = self.parse_block_statement()?;
         let rest = self.parse_elif_statement()?;
 
-        Ok(Stmt::If(Box::new(if_condition), Box::new(if_block), rest))
+        Ok(Stmt::If(
+            Box::new(if_condition),
+            Box::new(if_block),
+            rest,
+            line,
+        ))
     }
 
     fn parse_elif_statement(&mut self) -> Result<Option<Box<Stmt>>, ParseError> {
         if self.peek()?.kind == TokenKind::Elif {
+            let line = self.peek()?.line;
             self.consume(TokenKind::Elif)?;
             self.consume(TokenKind::LeftParen)?;
 
@@ -248,6 +297,7 @@ impl Parser {
                 Box::new(elif_condition),
                 Box::new(elif_block),
                 self.parse_elif_statement()?,
+                line,
             ))))
         } else {
             self.parse_else_statement()
@@ -266,6 +316,7 @@ impl Parser {
     }
 
     fn parse_print_statement(&mut self) -> Result<Stmt, ParseError> {
+        let line = self.peek()?.line;
         self.consume(TokenKind::Print)?;
         self.consume(TokenKind::LeftParen)?;
 
@@ -274,10 +325,11 @@ impl Parser {
         self.consume(TokenKind::RightParen)?;
         self.consume(TokenKind::Semicolon)?;
 
-        Ok(Stmt::Print(expr_list))
+        Ok(Stmt::Print(expr_list, line))
     }
 
     fn parse_return_statement(&mut self) -> Result<Stmt, ParseError> {
+        let line = self.peek()?.line;
         self.consume(TokenKind::Return)?;
         let expr = if self.peek()?.kind != TokenKind::Semicolon {
             Some(Box::new(self.parse_expression(Precedence::None)?))
@@ -285,7 +337,7 @@ impl Parser {
             None
         };
         self.consume(TokenKind::Semicolon)?;
-        Ok(Stmt::Return(expr))
+        Ok(Stmt::Return(expr, line))
     }
 
     fn parse_expression_list(&mut self) -> Result<Vec<Expr>, ParseError> {
@@ -304,9 +356,10 @@ impl Parser {
     }
 
     fn parse_expression_statement(&mut self) -> Result<Stmt, ParseError> {
+        let line = self.peek()?.line;
         let expr = self.parse_expression(Precedence::None)?;
         self.consume(TokenKind::Semicolon)?;
-        Ok(Stmt::Expression(Box::new(expr)))
+        Ok(Stmt::Expression(Box::new(expr), line))
     }
 
     fn infix(&mut self, left: &mut Expr) -> Result<Expr, ParseError> {
@@ -328,6 +381,7 @@ impl Parser {
             TokenKind::Equal | TokenKind::Colon => self.parse_assign(left),
             TokenKind::As => self.parse_cast(left),
             TokenKind::LeftParen => self.parse_call(left),
+            TokenKind::LeftSquare => self.parse_index(left),
             _ => Err(ParseError::UnexpectedInfixOperator(self.peek()?.clone())),
         }
     }
@@ -336,15 +390,18 @@ impl Parser {
         match self.peek()?.kind {
             TokenKind::IntLit(_)
             | TokenKind::FloatLit(_)
+            | TokenKind::TypedLit(_)
             | TokenKind::Ident(_)
             | TokenKind::StrLit(_) => self.parse_primary(),
+            TokenKind::InterpolatedStr(_) => self.parse_interpolation(),
+            TokenKind::Minus => self.parse_negative(),
             TokenKind::True => {
-                self.next()?;
-                Ok(Expr::Value(Value::from(true)))
+                let token = self.next()?;
+                Ok(Expr::Value(Value::from(true), (token.line, token.col)))
             }
             TokenKind::False => {
-                self.next()?;
-                Ok(Expr::Value(Value::from(false)))
+                let token = self.next()?;
+                Ok(Expr::Value(Value::from(false), (token.line, token.col)))
             }
             TokenKind::LeftParen => self.parse_grouping(),
             _ => Err(ParseError::UnexpectedPrefixOperator(self.peek()?.clone())),
@@ -377,6 +434,16 @@ impl Parser {
         Ok(Expr::Call(Box::new(left.clone()), args))
     }
 
+    // `s[i]` is a call to the `[]` native, which nothing else can be named
+    fn parse_index(&mut self, left: &mut Expr) -> Result<Expr, ParseError> {
+        self.consume(TokenKind::LeftSquare)?;
+        let index = self.parse_expression(Precedence::None)?;
+        self.consume(TokenKind::RightSquare)?;
+
+        let callee = Expr::Variable(self.interner.intern("[]"));
+        Ok(Expr::Call(Box::new(callee), vec![left.clone(), index]))
+    }
+
     fn parse_assign(&mut self, left: &mut Expr) -> Result<Expr, ParseError> {
         let mut expected_type = None;
 
@@ -423,6 +490,7 @@ impl Parser {
             Box::new(left.clone()),
             BinOp::from(&op),
             Box::new(right),
+            None,
         ))
     }
 
@@ -448,6 +516,7 @@ impl Parser {
             Box::new(left.clone()),
             RelOp::from(&op),
             Box::new(right),
+            None,
         ))
     }
 
@@ -458,13 +527,89 @@ impl Parser {
         Ok(Expr::Grouping(Box::new(expr)))
     }
 
+    // A `-` in front of a number literal is folded into it. Negating a literal
+    // without a suffix gives an i32 (an i64 if it doesn't fit) the same way a
+    // positive one is a u32, one with an unsigned suffix can't be negated.
+    // Anything else is negated at runtime, and binds tighter than any operator
+    // but indexing and calls (`-x ^ 2` is `(-x) ^ 2`, the same as `-2 ^ 2`)
+    fn parse_negative(&mut self) -> Result<Expr, ParseError> {
+        let minus = self.peek()?;
+        let position = (minus.line, minus.col);
+        self.consume(TokenKind::Minus)?;
+        match self.peek()?.kind {
+            TokenKind::IntLit(_) | TokenKind::FloatLit(_) | TokenKind::TypedLit(_) => (),
+            _ => {
+                let operand = self.parse_expression(Precedence::Unary)?;
+                return Ok(Expr::Negate(Box::new(operand), None));
+            }
+        }
+        let token = self.next()?;
+
+        let value = match &token.kind {
+            TokenKind::IntLit(value) => match value.into_inner() {
+                ValueWrapper::U32(v) => match i32::try_from(-(v as i64)) {
+                    Ok(v) => Some(Value::from(v)),
+                    Err(_) => Some(Value::from(-(v as i64))),
+                },
+                ValueWrapper::U64(v) => i64::try_from(-(v as i128)).ok().map(Value::from),
+                _ => None,
+            },
+            TokenKind::FloatLit(value) | TokenKind::TypedLit(value) => match value.into_inner() {
+                ValueWrapper::F64(v) => Some(Value::from(-v)),
+                ValueWrapper::F32(v) => Some(Value::from(-v)),
+                // The min is the only negative a literal token holds, see TypedLit
+                ValueWrapper::I8(v) => Some(Value::from(v.wrapping_neg())),
+                ValueWrapper::I16(v) => Some(Value::from(v.wrapping_neg())),
+                ValueWrapper::I32(v) => Some(Value::from(v.wrapping_neg())),
+                ValueWrapper::I64(v) => Some(Value::from(v.wrapping_neg())),
+                _ => None,
+            },
+            _ => return Err(ParseError::ExpectedLiteral(token)),
+        };
+
+        match value {
+            Some(value) => Ok(Expr::Value(value, position)),
+            None => Err(ParseError::InvalidNegation(token)),
+        }
+    }
+
+    // Each embedded expression was tokenized by the lexer, and gets a parser of its own
+    fn parse_interpolation(&mut self) -> Result<Expr, ParseError> {
+        let token = self.next()?;
+        let position = (token.line, token.col);
+        let parts = match token.kind {
+            TokenKind::InterpolatedStr(parts) => parts,
+            _ => return Err(ParseError::ExpectedLiteral(token)),
+        };
+
+        let mut exprs = Vec::new();
+        for part in parts {
+            let expr = match part {
+                StrPart::Lit(sym) => Expr::Value(Value::from(sym), position),
+                StrPart::Expr(tokens) => {
+                    let mut parser = Parser::new(tokens, &mut *self.interner);
+                    let expr = parser.parse_expression(Precedence::None)?;
+                    parser.consume(TokenKind::Eof)?;
+                    expr
+                }
+            };
+            exprs.push((expr, None));
+        }
+        Ok(Expr::Interpolation(exprs))
+    }
+
     fn parse_primary(&mut self) -> Result<Expr, ParseError> {
         let token = self.next()?;
+        let position = (token.line, token.col);
         match token.kind {
-            TokenKind::IntLit(value) => Ok(Expr::Value(value)),
-            TokenKind::FloatLit(value) => Ok(Expr::Value(value)),
+            TokenKind::IntLit(value) => Ok(Expr::Value(value, position)),
+            TokenKind::FloatLit(value) => Ok(Expr::Value(value, position)),
+            // Only a `-` in front makes the magnitude of a signed min fit, it's the one
+            // typed literal token that's negative (see TypedLit)
+            TokenKind::TypedLit(value) if value.into_inner().fits(ValueTy::U64) => Ok(Expr::Value(value, position)),
+            TokenKind::TypedLit(_) => Err(ParseError::LiteralOutOfRange(token)),
             TokenKind::Ident(sym) => Ok(Expr::Variable(sym)),
-            TokenKind::StrLit(sym) => Ok(Expr::Value(Value::from(sym))),
+            TokenKind::StrLit(sym) => Ok(Expr::Value(Value::from(sym), position)),
             _ => Err(ParseError::ExpectedLiteral(token)),
         }
     }
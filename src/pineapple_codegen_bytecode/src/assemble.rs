@@ -0,0 +1,337 @@
+use pineapple_error::AssembleError;
+use pineapple_ir::mir::{parse_type, parse_value, Label};
+use pineapple_ir::ValueTy;
+
+use crate::bytecode::{Instruction, IR, OR};
+use crate::module::Module;
+
+/*
+    Assembler for hand written bytecode, using the same operand syntax as the
+    disassembler. One instruction per line, `;` starts a comment.
+
+    @main:                      ; a named label starts a new chunk
+        MOV     r0, 0i32        ; constants are written inline and pooled
+    L0:
+        LT_I32  r1, r0, 5i32    ; arithmetic and comparisons name their operand type
+        BF      r1, L1
+        PUSH    r0
+        CALL    @print/1
+        ADD_I32 r0, r0, 1i32
+        JUMP    L0
+    L1:
+        RETURN  none
+
+    label   ::= "L" NUMBER | "@" NAME
+    line    ::= label ":" | "LABEL" label | MNEMONIC ("_" TYPE)? (operand ("," operand)*)?
+    output  ::= "r" NUMBER | "sp[-" NUMBER "]"
+    input   ::= output | "pop" | "rv" | value
+    call    ::= "@" NAME "/" NUMBER
+
+    Values and types are written the same way as in the textual MIR format, types
+    on a mnemonic can be in either case.
+*/
+const TYPED: [&str; 12] = [
+    "ADD", "SUB", "MUL", "DIV", "MOD", "POW", "LT", "LTE", "GT", "GTE", "EQ", "NEQ",
+];
+
+type Line = usize;
+type Intern<'a> = &'a mut dyn FnMut(&str) -> usize;
+
+struct Assembler<'a> {
+    module: Module,
+    intern: Intern<'a>,
+    line: Line,
+}
+
+pub fn assemble(buf: &str, intern: Intern) -> Result<Module, AssembleError> {
+    let mut assembler = Assembler {
+        module: Module::default(),
+        intern,
+        line: 0,
+    };
+
+    for (index, line) in buf.lines().enumerate() {
+        assembler.line = index + 1;
+
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(text) = line.strip_suffix(':') {
+            let label = assembler.label(text.trim())?;
+            assembler.add_label(label, text.trim())?;
+            continue;
+        }
+
+        let (mnemonic, rest) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        };
+        let operands = split_operands(rest);
+
+        if mnemonic.eq_ignore_ascii_case("LABEL") {
+            assembler.arity(mnemonic, &operands, 1)?;
+            let label = assembler.label(operands[0])?;
+            assembler.add_label(label, operands[0])?;
+            continue;
+        }
+
+        if assembler.module.chunks.is_empty() {
+            return Err(AssembleError::InstructionOutsideChunk(assembler.line));
+        }
+
+        let instruction = assembler.instruction(mnemonic, &operands)?;
+        // Runtime errors point back at the .pasm line
+        assembler.module.add_instruction(instruction, Some(assembler.line));
+    }
+
+    Ok(assembler.module)
+}
+
+// Comments start at a `;` that isn't inside of a string literal
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '\\' if in_string => {
+                escaped = !escaped;
+                continue;
+            }
+            '"' if !escaped => in_string = !in_string,
+            ';' if !in_string => return &line[..i],
+            _ => (),
+        }
+        escaped = false;
+    }
+    line
+}
+
+fn split_operands(rest: &str) -> Vec<&str> {
+    if rest.is_empty() {
+        return vec![];
+    }
+
+    let mut operands = vec![];
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '\\' if in_string => {
+                escaped = !escaped;
+                continue;
+            }
+            '"' if !escaped => in_string = !in_string,
+            ',' if !in_string => {
+                operands.push(rest[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+        escaped = false;
+    }
+    operands.push(rest[start..].trim());
+    operands
+}
+
+fn number(text: &str, prefix: &str, suffix: &str) -> Option<usize> {
+    text.strip_prefix(prefix)?
+        .strip_suffix(suffix)?
+        .parse::<usize>()
+        .ok()
+}
+
+impl<'a> Assembler<'a> {
+    fn invalid(&self, found: &str, expected: &'static str) -> AssembleError {
+        AssembleError::InvalidOperand(self.line, found.to_string(), expected)
+    }
+
+    fn arity(&self, mnemonic: &str, operands: &[&str], n: usize) -> Result<(), AssembleError> {
+        if operands.len() == n {
+            Ok(())
+        } else {
+            Err(AssembleError::WrongOperandCount(
+                self.line,
+                mnemonic.to_string(),
+                n,
+                operands.len(),
+            ))
+        }
+    }
+
+    fn add_label(&mut self, label: Label, text: &str) -> Result<(), AssembleError> {
+        if self.module.labels.contains_key(&label) {
+            return Err(AssembleError::DuplicateLabel(self.line, text.to_string()));
+        }
+
+        match label {
+            Label::Named(_) => {
+                self.module.add_chunk(label);
+                self.module.add_label(&label, Some(self.line));
+            }
+            Label::Marker(_) if self.module.chunks.is_empty() => {
+                return Err(AssembleError::InstructionOutsideChunk(self.line))
+            }
+            Label::Marker(_) => self.module.add_label(&label, Some(self.line)),
+        }
+        Ok(())
+    }
+
+    fn label(&mut self, text: &str) -> Result<Label, AssembleError> {
+        if let Some(name) = text.strip_prefix('@') {
+            if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                return Ok(Label::Named((self.intern)(name)));
+            }
+        } else if let Some(marker) = number(text, "L", "") {
+            return Ok(Label::Marker(marker));
+        }
+        Err(self.invalid(text, "a label"))
+    }
+
+    fn or(&mut self, text: &str) -> Result<OR, AssembleError> {
+        if let Some(reg) = number(text, "r", "") {
+            Ok(OR::REG(reg))
+        } else if let Some(offset) = number(text, "sp[-", "]") {
+            Ok(OR::STACK(offset))
+        } else {
+            Err(self.invalid(text, "a register or stack offset"))
+        }
+    }
+
+    fn ir(&mut self, text: &str) -> Result<IR, AssembleError> {
+        if let Some(reg) = number(text, "r", "") {
+            Ok(IR::REG(reg))
+        } else if let Some(offset) = number(text, "sp[-", "]") {
+            Ok(IR::STACK(offset))
+        } else if text == "pop" {
+            Ok(IR::STACKPOP)
+        } else if text == "rv" {
+            Ok(IR::RETVAL)
+        } else {
+            match parse_value(text, self.intern) {
+                Some(value) => Ok(IR::VALUE(self.module.add_value(value))),
+                None => Err(self.invalid(text, "an operand")),
+            }
+        }
+    }
+
+    fn call(&mut self, text: &str) -> Result<(usize, usize), AssembleError> {
+        let target = text
+            .strip_prefix('@')
+            .and_then(|target| target.split_once('/'))
+            .and_then(|(name, arity)| Some((name, arity.parse::<usize>().ok()?)));
+
+        match target {
+            Some((name, arity)) if !name.is_empty() => Ok(((self.intern)(name), arity)),
+            _ => Err(self.invalid(text, "a call target (i.e. `@fibo/1`)")),
+        }
+    }
+
+    fn instruction(&mut self, mnemonic: &str, ops: &[&str]) -> Result<Instruction, AssembleError> {
+        let name = mnemonic.to_ascii_uppercase();
+        let (name, ty) = match name.split_once('_') {
+            Some((name, ty)) => match parse_type(&ty.to_ascii_lowercase()) {
+                Some(ty) => (name, Some(ty)),
+                None => {
+                    return Err(AssembleError::UnknownInstruction(
+                        self.line,
+                        mnemonic.to_string(),
+                    ))
+                }
+            },
+            None => (name.as_str(), None),
+        };
+
+        let binary = |asm: &mut Self, make: fn(ValueTy, OR, IR, IR) -> Instruction| {
+            let ty = match ty {
+                Some(ty) => ty,
+                None => {
+                    return Err(AssembleError::MissingOperandType(
+                        asm.line,
+                        mnemonic.to_string(),
+                    ))
+                }
+            };
+            asm.arity(mnemonic, ops, 3)?;
+            Ok(make(ty, asm.or(ops[0])?, asm.ir(ops[1])?, asm.ir(ops[2])?))
+        };
+
+        // Only the typed instructions take a type on their mnemonic
+        if ty.is_some() && !TYPED.contains(&name) {
+            return Err(AssembleError::UnknownInstruction(
+                self.line,
+                mnemonic.to_string(),
+            ));
+        }
+
+        match name {
+            "MOV" => {
+                self.arity(mnemonic, ops, 2)?;
+                Ok(Instruction::MOV(self.or(ops[0])?, self.ir(ops[1])?))
+            }
+            "CAST" => {
+                self.arity(mnemonic, ops, 2)?;
+                let or = self.or(ops[0])?;
+                match parse_type(ops[1]) {
+                    Some(ty) => Ok(Instruction::CAST(or, ty)),
+                    None => Err(self.invalid(ops[1], "a type")),
+                }
+            }
+            "ADD" => binary(self, Instruction::ADD),
+            "SUB" => binary(self, Instruction::SUB),
+            "MUL" => binary(self, Instruction::MUL),
+            "DIV" => binary(self, Instruction::DIV),
+            "MOD" => binary(self, Instruction::MOD),
+            "POW" => binary(self, Instruction::POW),
+            "LT" => binary(self, Instruction::LT),
+            "LTE" => binary(self, Instruction::LTE),
+            "GT" => binary(self, Instruction::GT),
+            "GTE" => binary(self, Instruction::GTE),
+            "EQ" => binary(self, Instruction::EQ),
+            "NEQ" => binary(self, Instruction::NEQ),
+            "PUSH" => {
+                self.arity(mnemonic, ops, 1)?;
+                Ok(Instruction::PUSH(self.ir(ops[0])?))
+            }
+            "POP" => {
+                self.arity(mnemonic, ops, 1)?;
+                Ok(Instruction::POP(self.or(ops[0])?))
+            }
+            "JUMP" => {
+                self.arity(mnemonic, ops, 1)?;
+                Ok(Instruction::JUMP(self.label(ops[0])?))
+            }
+            "NOP" => {
+                self.arity(mnemonic, ops, 0)?;
+                Ok(Instruction::NOP)
+            }
+            "BT" => {
+                self.arity(mnemonic, ops, 2)?;
+                Ok(Instruction::BT(self.ir(ops[0])?, self.label(ops[1])?))
+            }
+            "BF" => {
+                self.arity(mnemonic, ops, 2)?;
+                Ok(Instruction::BF(self.ir(ops[0])?, self.label(ops[1])?))
+            }
+            "CALL" => {
+                self.arity(mnemonic, ops, 1)?;
+                let (sym, arity) = self.call(ops[0])?;
+                Ok(Instruction::CALL(sym, arity))
+            }
+            "RETURN" => {
+                self.arity(mnemonic, ops, 1)?;
+                Ok(Instruction::RETURN(self.ir(ops[0])?))
+            }
+            "HLT" => {
+                self.arity(mnemonic, ops, 0)?;
+                Ok(Instruction::HLT)
+            }
+            _ => Err(AssembleError::UnknownInstruction(
+                self.line,
+                mnemonic.to_string(),
+            )),
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use pineapple_ir::mir::Label;
 use pineapple_ir::ValueTy;
 
+pub use pineapple_codegen_ssa::NUM_REGISTERS;
+
 type InternIndex = usize;
 type Arity = usize;
 type MemoryLocation = usize;
@@ -32,20 +34,22 @@ pub enum Instruction {
 
     CAST(OR, ValueTy),
 
-    ADD(OR, IR, IR),
-    SUB(OR, IR, IR),
-    MUL(OR, IR, IR),
-    DIV(OR, IR, IR),
-    MOD(OR, IR, IR),
-    POW(OR, IR, IR),
-    // AND(OR, IR, IR),
-    // OR(OR, IR, IR),
-    LT(OR, IR, IR),
-    LTE(OR, IR, IR),
-    GT(OR, IR, IR),
-    GTE(OR, IR, IR),
-    EQ(OR, IR, IR),
-    NEQ(OR, IR, IR),
+    // Arithmetic and comparisons are typed, both operands have to be of the
+    // instruction's type (i.e. ADD_I32) which the compiler picks from the MIR
+    ADD(ValueTy, OR, IR, IR),
+    SUB(ValueTy, OR, IR, IR),
+    MUL(ValueTy, OR, IR, IR),
+    DIV(ValueTy, OR, IR, IR),
+    MOD(ValueTy, OR, IR, IR),
+    POW(ValueTy, OR, IR, IR),
+    // AND(ValueTy, OR, IR, IR),
+    // OR(ValueTy, OR, IR, IR),
+    LT(ValueTy, OR, IR, IR),
+    LTE(ValueTy, OR, IR, IR),
+    GT(ValueTy, OR, IR, IR),
+    GTE(ValueTy, OR, IR, IR),
+    EQ(ValueTy, OR, IR, IR),
+    NEQ(ValueTy, OR, IR, IR),
 
     PUSH(IR),
     POP(OR),
@@ -62,3 +66,24 @@ pub enum Instruction {
 
     HLT,
 }
+
+impl Instruction {
+    // The type both operands of a typed instruction have to be
+    pub fn operand_ty(&self) -> Option<ValueTy> {
+        match self {
+            Instruction::ADD(ty, _, _, _)
+            | Instruction::SUB(ty, _, _, _)
+            | Instruction::MUL(ty, _, _, _)
+            | Instruction::DIV(ty, _, _, _)
+            | Instruction::MOD(ty, _, _, _)
+            | Instruction::POW(ty, _, _, _)
+            | Instruction::LT(ty, _, _, _)
+            | Instruction::LTE(ty, _, _, _)
+            | Instruction::GT(ty, _, _, _)
+            | Instruction::GTE(ty, _, _, _)
+            | Instruction::EQ(ty, _, _, _)
+            | Instruction::NEQ(ty, _, _, _) => Some(*ty),
+            _ => None,
+        }
+    }
+}
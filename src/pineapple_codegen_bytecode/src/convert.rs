@@ -6,14 +6,24 @@ use pineapple_ir::mir::Oper;
 use pineapple_ir::mir::Stmt;
 use pineapple_ir::op::RelOp;
 use pineapple_ir::{mir::Expr, op::BinOp};
-use pineapple_ir::{NoneTy, Value};
+use pineapple_ir::{NoneTy, Value, ValueTy};
+use std::rc::Rc;
+
+type Line = usize;
 
 #[derive(Default)]
 pub struct Compiler {
     module: Module,
+
+    // Source line of the instructions emitted from here on, from the last Stmt::Line
+    line: Option<Line>,
 }
 
 impl Compiler {
+    fn add_instruction(&mut self, instruction: Instruction) {
+        self.module.add_instruction(instruction, self.line);
+    }
+
     fn operand_to_or(&mut self, operand: &Oper) -> OR {
         match operand {
             Oper::Register(r) => OR::REG(*r),
@@ -38,6 +48,7 @@ impl Compiler {
 
     pub fn compile_program(mut self, cfgs: Vec<CFG>) -> Module {
         for cfg in cfgs {
+            self.line = None;
             self.module.add_chunk(cfg.entry_label);
             self.compile_cfg(cfg)
         }
@@ -46,6 +57,7 @@ impl Compiler {
     }
 
     fn compile_cfg(&mut self, cfg: CFG) {
+        let locals = cfg.locals;
         for block in cfg.blocks {
             match block.entry {
                 BlockEntry::Entry(statement) => self.compile_statement(&*statement.borrow()),
@@ -53,7 +65,12 @@ impl Compiler {
             }
 
             for statement in block.statements {
-                self.compile_statement(&*statement.borrow())
+                self.compile_statement(&*statement.borrow());
+
+                for local in locals.iter().filter(|l| Rc::ptr_eq(&l.statement, &statement)) {
+                    let location = self.operand_to_or(&local.location);
+                    self.module.add_local(local.sym, location);
+                }
             }
 
             match block.exit {
@@ -71,14 +88,15 @@ impl Compiler {
             Stmt::CJump(cond, jumpto) => self.compile_cjump(cond, jumpto),
             Stmt::CastAs(oper, ty) => {
                 let or = self.operand_to_or(oper);
-                self.module.add_instruction(Instruction::CAST(or, *ty));
+                self.add_instruction(Instruction::CAST(or, *ty));
             }
             Stmt::Call(sym, arity) => self.compile_call(sym, arity),
             Stmt::StackPush(oper) => {
                 let or = self.operand_to_ir(oper);
-                self.module.add_instruction(Instruction::PUSH(or))
+                self.add_instruction(Instruction::PUSH(or))
             }
             Stmt::Return(retval) => self.compile_return(retval),
+            Stmt::Line(line) => self.line = Some(*line),
             _ => unimplemented!(""),
         }
     }
@@ -89,11 +107,11 @@ impl Compiler {
     }
 
     fn compile_label(&mut self, label: &Label) {
-        self.module.add_label(label);
+        self.module.add_label(label, self.line);
     }
 
     fn compile_jump(&mut self, label: &Label) {
-        self.module.add_instruction(Instruction::JUMP(*label));
+        self.add_instruction(Instruction::JUMP(*label));
     }
 
     fn compile_cjump(&mut self, cond: &Expr, jumpto: &Label) {
@@ -102,65 +120,65 @@ impl Compiler {
             _ => unimplemented!(),
         };
 
-        self.module.add_instruction(Instruction::BT(res, *jumpto));
+        self.add_instruction(Instruction::BT(res, *jumpto));
     }
 
     fn compile_call(&mut self, sym: &usize, arity: &usize) {
-        self.module.add_instruction(Instruction::CALL(*sym, *arity));
+        self.add_instruction(Instruction::CALL(*sym, *arity));
 
         // This noop may actually not be needed, check this out...
-        self.module.add_instruction(Instruction::NOP);
+        self.add_instruction(Instruction::NOP);
     }
 
     fn compile_return(&mut self, retval: &Option<Oper>) {
         if let Some(retval) = retval {
             let retval = self.operand_to_ir(retval);
-            self.module.add_instruction(Instruction::RETURN(retval));
+            self.add_instruction(Instruction::RETURN(retval));
         } else {
             let retval = Value::from(NoneTy::None);
             let retval = self.operand_to_ir(&Oper::Value(retval));
-            self.module.add_instruction(Instruction::RETURN(retval));
+            self.add_instruction(Instruction::RETURN(retval));
         }
     }
 
     fn compile_expression(&mut self, or: OR, expr: &Expr) {
         match expr {
-            Expr::Binary(left, op, right) => self.compile_binary(or, left, op, right),
-            Expr::Logical(left, op, right) => self.compile_logical(or, left, op, right),
+            Expr::Binary(left, op, right, ty) => self.compile_binary(or, left, op, right, *ty),
+            Expr::Logical(left, op, right, ty) => self.compile_logical(or, left, op, right, *ty),
             Expr::Oper(oper) => {
                 let rval = self.operand_to_ir(oper);
-                self.module.add_instruction(Instruction::MOV(or, rval));
+                self.add_instruction(Instruction::MOV(or, rval));
             }
             _ => unimplemented!("{:?}", expr),
         }
     }
 
-    fn compile_binary(&mut self, or: OR, left: &Oper, op: &BinOp, right: &Oper) {
+    fn compile_binary(&mut self, or: OR, left: &Oper, op: &BinOp, right: &Oper, ty: ValueTy) {
         let a = self.operand_to_ir(left);
         let b = self.operand_to_ir(right);
 
         match op {
-            BinOp::Plus => self.module.add_instruction(Instruction::ADD(or, a, b)),
-            BinOp::Minus => self.module.add_instruction(Instruction::SUB(or, a, b)),
-            BinOp::Star => self.module.add_instruction(Instruction::MUL(or, a, b)),
-            BinOp::Slash => self.module.add_instruction(Instruction::DIV(or, a, b)),
-            BinOp::Modulo => self.module.add_instruction(Instruction::MOD(or, a, b)),
-            BinOp::Carat => self.module.add_instruction(Instruction::POW(or, a, b)),
+            BinOp::Plus => self.add_instruction(Instruction::ADD(ty, or, a, b)),
+            BinOp::Minus => self.add_instruction(Instruction::SUB(ty, or, a, b)),
+            BinOp::Star => self.add_instruction(Instruction::MUL(ty, or, a, b)),
+            BinOp::Slash => self.add_instruction(Instruction::DIV(ty, or, a, b)),
+            BinOp::Modulo => self.add_instruction(Instruction::MOD(ty, or, a, b)),
+            BinOp::Carat => self.add_instruction(Instruction::POW(ty, or, a, b)),
             _ => unimplemented!(),
         }
     }
 
-    fn compile_logical(&mut self, or: OR, left: &Oper, op: &RelOp, right: &Oper) {
+    fn compile_logical(&mut self, or: OR, left: &Oper, op: &RelOp, right: &Oper, ty: ValueTy) {
         let a = self.operand_to_ir(left);
         let b = self.operand_to_ir(right);
 
         match op {
-            RelOp::NotEqual => self.module.add_instruction(Instruction::NEQ(or, a, b)),
-            RelOp::EqualEqual => self.module.add_instruction(Instruction::EQ(or, a, b)),
-            RelOp::Less => self.module.add_instruction(Instruction::LT(or, a, b)),
-            RelOp::LessEqual => self.module.add_instruction(Instruction::LTE(or, a, b)),
-            RelOp::Greater => self.module.add_instruction(Instruction::GT(or, a, b)),
-            RelOp::GreaterEqual => self.module.add_instruction(Instruction::GTE(or, a, b)),
+            RelOp::NotEqual => self.add_instruction(Instruction::NEQ(ty, or, a, b)),
+            RelOp::EqualEqual => self.add_instruction(Instruction::EQ(ty, or, a, b)),
+            RelOp::Less => self.add_instruction(Instruction::LT(ty, or, a, b)),
+            RelOp::LessEqual => self.add_instruction(Instruction::LTE(ty, or, a, b)),
+            RelOp::Greater => self.add_instruction(Instruction::GT(ty, or, a, b)),
+            RelOp::GreaterEqual => self.add_instruction(Instruction::GTE(ty, or, a, b)),
         }
     }
 }
@@ -0,0 +1,163 @@
+use std::fmt::Write;
+
+use pineapple_ir::mir::{print_label, print_value, type_name, Label};
+
+use crate::bytecode::{Instruction, IR, OR};
+use crate::module::Module;
+
+type NameOf<'a> = &'a dyn Fn(usize) -> String;
+
+/*
+    Listing format, one block per chunk:
+
+    == fibo (chunk 0) ==
+    0000        LABEL      @fibo
+    0001     1  MOV        r0, pop
+    0002     2  GT_I32     r1, r0, 1i32
+    0003     |  BT         r1, L0                  ; -> 0007
+    0004     |  LABEL      L1
+    0005     3  RETURN     r0
+
+    The second column is the source line from the chunk's line table, `|` when
+    it's the same as the instruction above and blank when there is none.
+
+    Operands are r<n> for registers, sp[-n] for stack offsets, mem[n] for memory,
+    pop for a stack pop and rv for the return value. Constants are pulled out of
+    the module and printed inline, jump targets get their resolved location.
+    Typed instructions have their operand type on the mnemonic (i.e. ADD_I32).
+*/
+pub fn disassemble(module: &Module, name_of: NameOf) -> String {
+    let mut out = String::new();
+
+    writeln!(out, "== constants ==").unwrap();
+    for (i, value) in module.values.iter().enumerate() {
+        writeln!(out, "#{:<4} {}", i, print_value(value, name_of)).unwrap();
+    }
+
+    for (c, chunk) in module.chunks.iter().enumerate() {
+        writeln!(out).unwrap();
+        writeln!(out, "== {} (chunk {}) ==", label_name(&chunk.label, name_of), c).unwrap();
+
+        for offset in 0..chunk.instructions.len() {
+            let source_line = match chunk.line(offset) {
+                Some(_) if offset > 0 && chunk.line(offset - 1) == chunk.line(offset) => {
+                    "|".to_string()
+                }
+                Some(line) => line.to_string(),
+                None => String::new(),
+            };
+            let instruction = disassemble_instruction(module, c, offset, name_of);
+            writeln!(out, "{:04}  {:>4}  {}", offset, source_line, instruction).unwrap();
+        }
+    }
+
+    out
+}
+
+// A single instruction without its offset, i.e. `BT         r1, L0    ; -> 0007`
+pub fn disassemble_instruction(
+    module: &Module,
+    chunk: usize,
+    offset: usize,
+    name_of: NameOf,
+) -> String {
+    let instruction = &module.chunks[chunk].instructions[offset];
+    let (mnemonic, operands) = instruction_parts(module, instruction, name_of);
+    let mnemonic = match instruction.operand_ty() {
+        Some(ty) => format!("{}_{}", mnemonic, type_name(ty).to_uppercase()),
+        None => mnemonic.to_string(),
+    };
+    let text = format!("{:<10} {}", mnemonic, operands);
+
+    match target(instruction).map(|label| resolve(module, chunk, &label)) {
+        Some(target) => format!("{:<34} ; -> {}", text, target),
+        None => text.trim_end().to_string(),
+    }
+}
+
+fn label_name(label: &Label, name_of: NameOf) -> String {
+    match label {
+        Label::Named(sym) => name_of(*sym),
+        Label::Marker(_) => print_label(label, name_of),
+    }
+}
+
+fn target(instruction: &Instruction) -> Option<Label> {
+    match instruction {
+        Instruction::JUMP(label) | Instruction::BT(_, label) | Instruction::BF(_, label) => {
+            Some(*label)
+        }
+        _ => None,
+    }
+}
+
+// Jumps within the same chunk just get the offset, anything else says which chunk
+fn resolve(module: &Module, chunk: usize, label: &Label) -> String {
+    match module.labels.get(label) {
+        Some(location) if location.chunk_index == chunk => {
+            format!("{:04}", location.instruction_index)
+        }
+        Some(location) => format!(
+            "chunk {} {:04}",
+            location.chunk_index, location.instruction_index
+        ),
+        None => "undefined".to_string(),
+    }
+}
+
+pub fn print_or(or: &OR) -> String {
+    match or {
+        OR::REG(reg) => format!("r{}", reg),
+        OR::STACK(offset) => format!("sp[-{}]", offset),
+    }
+}
+
+pub fn print_ir(module: &Module, ir: &IR, name_of: NameOf) -> String {
+    match ir {
+        IR::REG(reg) => format!("r{}", reg),
+        IR::VALUE(index) => match module.values.get(*index) {
+            Some(value) => print_value(value, name_of),
+            None => format!("#{}", index),
+        },
+        IR::STACK(offset) => format!("sp[-{}]", offset),
+        IR::MEMLOC(loc) => format!("mem[{}]", loc),
+        IR::STACKPOP => "pop".to_string(),
+        IR::RETVAL => "rv".to_string(),
+    }
+}
+
+fn instruction_parts(
+    module: &Module,
+    instruction: &Instruction,
+    name_of: NameOf,
+) -> (&'static str, String) {
+    let ir = |ir: &IR| print_ir(module, ir, name_of);
+    let binary = |or: &OR, l: &IR, r: &IR| format!("{}, {}, {}", print_or(or), ir(l), ir(r));
+
+    match instruction {
+        Instruction::LABEL(label) => ("LABEL", print_label(label, name_of)),
+        Instruction::MOV(or, i) => ("MOV", format!("{}, {}", print_or(or), ir(i))),
+        Instruction::CAST(or, ty) => ("CAST", format!("{}, {}", print_or(or), type_name(*ty))),
+        Instruction::ADD(_, or, l, r) => ("ADD", binary(or, l, r)),
+        Instruction::SUB(_, or, l, r) => ("SUB", binary(or, l, r)),
+        Instruction::MUL(_, or, l, r) => ("MUL", binary(or, l, r)),
+        Instruction::DIV(_, or, l, r) => ("DIV", binary(or, l, r)),
+        Instruction::MOD(_, or, l, r) => ("MOD", binary(or, l, r)),
+        Instruction::POW(_, or, l, r) => ("POW", binary(or, l, r)),
+        Instruction::LT(_, or, l, r) => ("LT", binary(or, l, r)),
+        Instruction::LTE(_, or, l, r) => ("LTE", binary(or, l, r)),
+        Instruction::GT(_, or, l, r) => ("GT", binary(or, l, r)),
+        Instruction::GTE(_, or, l, r) => ("GTE", binary(or, l, r)),
+        Instruction::EQ(_, or, l, r) => ("EQ", binary(or, l, r)),
+        Instruction::NEQ(_, or, l, r) => ("NEQ", binary(or, l, r)),
+        Instruction::PUSH(i) => ("PUSH", ir(i)),
+        Instruction::POP(or) => ("POP", print_or(or)),
+        Instruction::JUMP(label) => ("JUMP", print_label(label, name_of)),
+        Instruction::NOP => ("NOP", String::new()),
+        Instruction::BT(i, label) => ("BT", format!("{}, {}", ir(i), print_label(label, name_of))),
+        Instruction::BF(i, label) => ("BF", format!("{}, {}", ir(i), print_label(label, name_of))),
+        Instruction::CALL(sym, arity) => ("CALL", format!("@{}/{}", name_of(*sym), arity)),
+        Instruction::RETURN(i) => ("RETURN", ir(i)),
+        Instruction::HLT => ("HLT", String::new()),
+    }
+}
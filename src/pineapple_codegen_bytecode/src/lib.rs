@@ -1,12 +1,59 @@
 use crate::module::Module;
 use convert::Compiler;
 use pineapple_codegen_ssa::analysis::cfg::CFG;
+use pineapple_error::{AssembleError, BytecodeError, LoadError};
 
+mod assemble;
 pub mod bytecode;
 mod convert;
+mod disassemble;
 pub mod module;
+mod serialize;
+mod verify;
+
+pub use serialize::VERSION as PBC_VERSION;
 
 pub fn compile_cfgs_to_bytecode(cfgs: Vec<CFG>) -> Module {
     let compiler = Compiler::default();
     compiler.compile_program(cfgs)
 }
+
+pub fn write_pbc(module: &Module, name_of: &dyn Fn(usize) -> String) -> Vec<u8> {
+    serialize::write_module(module, name_of)
+}
+
+pub fn load_pbc(buf: &[u8], intern: &mut dyn FnMut(&str) -> usize) -> Result<Module, LoadError> {
+    serialize::load_module(buf, intern)
+}
+
+pub fn disassemble_module(module: &Module, name_of: &dyn Fn(usize) -> String) -> String {
+    disassemble::disassemble(module, name_of)
+}
+
+pub fn disassemble_instruction(
+    module: &Module,
+    chunk: usize,
+    offset: usize,
+    name_of: &dyn Fn(usize) -> String,
+) -> String {
+    disassemble::disassemble_instruction(module, chunk, offset, name_of)
+}
+
+pub fn assemble_module(
+    buf: &str,
+    intern: &mut dyn FnMut(&str) -> usize,
+) -> Result<Module, AssembleError> {
+    assemble::assemble(buf, intern)
+}
+
+pub fn verify_module(
+    module: &Module,
+    native_arity: &dyn Fn(usize) -> Option<Option<usize>>,
+    name_of: &dyn Fn(usize) -> String,
+) -> Result<(), BytecodeError> {
+    verify::verify(module, native_arity, name_of)
+}
+
+pub fn verify_main(module: &Module, name_of: &dyn Fn(usize) -> String) -> Result<(), BytecodeError> {
+    verify::verify_main(module, name_of)
+}
@@ -1,12 +1,30 @@
-use crate::bytecode::Instruction;
+use crate::bytecode::{Instruction, OR};
 use pineapple_ir::mir::Label;
 use pineapple_ir::Value;
 use std::collections::HashMap;
 
+type Line = usize;
+type Sym = usize;
+
+// A source variable that lives in `location` from `offset` on, until the next
+// local of the same name (if any)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Local {
+    pub name: Sym,
+    pub location: OR,
+    pub offset: usize,
+}
+
 #[derive(Debug, Clone)]
 pub struct Chunk {
     pub label: Label,
     pub instructions: Vec<Instruction>,
+
+    // Debug line table, the source line each instruction came from (if any)
+    pub lines: Vec<Option<Line>>,
+
+    // Debug variable table, in the order the definitions were emitted
+    pub locals: Vec<Local>,
 }
 
 impl Chunk {
@@ -14,11 +32,30 @@ impl Chunk {
         Chunk {
             label,
             instructions: vec![],
+            lines: vec![],
+            locals: vec![],
         }
     }
 
-    pub fn add_instruction(&mut self, instruction: Instruction) {
+    pub fn add_instruction(&mut self, instruction: Instruction, line: Option<Line>) {
         self.instructions.push(instruction);
+        self.lines.push(line);
+    }
+
+    pub fn line(&self, offset: usize) -> Option<Line> {
+        self.lines.get(offset).copied().flatten()
+    }
+
+    // Where every variable defined before `offset` was last put
+    pub fn locals_at(&self, offset: usize) -> Vec<Local> {
+        let mut locals: Vec<Local> = vec![];
+        for local in self.locals.iter().filter(|local| local.offset <= offset) {
+            match locals.iter_mut().find(|other| other.name == local.name) {
+                Some(other) => *other = *local,
+                None => locals.push(*local),
+            }
+        }
+        locals
     }
 }
 
@@ -49,16 +86,27 @@ impl Module {
         self.chunks.push(Chunk::new(label));
     }
 
-    pub fn add_label(&mut self, label: &Label) {
+    pub fn add_label(&mut self, label: &Label, line: Option<Line>) {
         let c = self.chunks.len() - 1;
         let s = self.chunks.last().unwrap().instructions.len();
 
         self.labels.insert(*label, LabelLocation::new(c, s));
-        self.add_instruction(Instruction::LABEL(*label));
+        self.add_instruction(Instruction::LABEL(*label), line);
+    }
+
+    pub fn add_instruction(&mut self, instruction: Instruction, line: Option<Line>) {
+        self.chunks.last_mut().unwrap().add_instruction(instruction, line);
     }
 
-    pub fn add_instruction(&mut self, instruction: Instruction) {
-        self.chunks.last_mut().unwrap().add_instruction(instruction);
+    // The variable is in its location once the last added instruction has run
+    pub fn add_local(&mut self, name: Sym, location: OR) {
+        let chunk = self.chunks.last_mut().unwrap();
+        let offset = chunk.instructions.len();
+        chunk.locals.push(Local {
+            name,
+            location,
+            offset,
+        });
     }
 
     pub fn add_value(&mut self, value: Value) -> usize {
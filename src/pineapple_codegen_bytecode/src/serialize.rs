@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+
+use pineapple_error::LoadError;
+use pineapple_ir::mir::Label;
+use pineapple_ir::value::ValueContainer;
+use pineapple_ir::{NoneTy, Value, ValueTy, ValueWrapper};
+
+use crate::bytecode::{Instruction, IR, OR};
+use crate::module::{Chunk, LabelLocation, Local, Module};
+
+/*
+    Layout of a .pbc file, everything little endian:
+
+    header          b"PBC\0" u16:version
+    string table    u32:count { u32:len u8[len] }
+    constant pool   u32:count { u8:ValueTy payload }
+    chunks          u32:count { label u32:count { u8:opcode operands u32:line }
+                                u32:count { u32:name output u32:offset } }
+                    (arithmetic and comparisons have a u8:ValueTy before their operands,
+                    line 0 means the instruction has no source line, the second list
+                    is the chunk's local variables)
+    label table     u32:count { label u32:chunk u32:instruction }
+
+    Interned symbols (function names, string constants) are only meaningful to the
+    session that created them, so they get written out as indices into the string
+    table and interned again when the file is loaded.
+*/
+const MAGIC: &[u8; 4] = b"PBC\0";
+pub const VERSION: u16 = 4;
+
+type NameOf<'a> = &'a dyn Fn(usize) -> String;
+type Intern<'a> = &'a mut dyn FnMut(&str) -> usize;
+
+struct Writer<'a> {
+    buf: Vec<u8>,
+    strings: Vec<String>,
+    string_indices: HashMap<usize, u32>,
+    name_of: NameOf<'a>,
+}
+
+impl<'a> Writer<'a> {
+    fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    fn u32(&mut self, v: usize) {
+        self.buf.extend_from_slice(&(v as u32).to_le_bytes());
+    }
+
+    fn string(&mut self, sym: usize) {
+        let index = match self.string_indices.get(&sym) {
+            Some(index) => *index,
+            None => {
+                let index = self.strings.len() as u32;
+                self.strings.push((self.name_of)(sym));
+                self.string_indices.insert(sym, index);
+                index
+            }
+        };
+        self.u32(index as usize);
+    }
+
+    fn label(&mut self, label: &Label) {
+        match label {
+            Label::Marker(marker) => {
+                self.u8(0);
+                self.u32(*marker);
+            }
+            Label::Named(sym) => {
+                self.u8(1);
+                self.string(*sym);
+            }
+        }
+    }
+
+    fn value(&mut self, value: &Value) {
+        self.u8(value.fetch_ty() as u16 as u8);
+        match value.into_inner() {
+            ValueWrapper::F64(v) => self.buf.extend_from_slice(&v.to_le_bytes()),
+            ValueWrapper::F32(v) => self.buf.extend_from_slice(&v.to_le_bytes()),
+            ValueWrapper::I8(v) => self.buf.extend_from_slice(&v.to_le_bytes()),
+            ValueWrapper::I16(v) => self.buf.extend_from_slice(&v.to_le_bytes()),
+            ValueWrapper::I32(v) => self.buf.extend_from_slice(&v.to_le_bytes()),
+            ValueWrapper::I64(v) => self.buf.extend_from_slice(&v.to_le_bytes()),
+            ValueWrapper::U8(v) => self.buf.extend_from_slice(&v.to_le_bytes()),
+            ValueWrapper::U16(v) => self.buf.extend_from_slice(&v.to_le_bytes()),
+            ValueWrapper::U32(v) => self.buf.extend_from_slice(&v.to_le_bytes()),
+            ValueWrapper::U64(v) => self.buf.extend_from_slice(&v.to_le_bytes()),
+            ValueWrapper::BOOL(v) => self.u8(v as u8),
+            ValueWrapper::STR(sym) => self.string(sym),
+            ValueWrapper::NONE(_) => (),
+        }
+    }
+
+    fn or(&mut self, or: &OR) {
+        match or {
+            OR::REG(reg) => {
+                self.u8(0);
+                self.u32(*reg);
+            }
+            OR::STACK(offset) => {
+                self.u8(1);
+                self.u32(*offset);
+            }
+        }
+    }
+
+    fn ir(&mut self, ir: &IR) {
+        match ir {
+            IR::REG(reg) => {
+                self.u8(0);
+                self.u32(*reg);
+            }
+            IR::VALUE(index) => {
+                self.u8(1);
+                self.u32(*index);
+            }
+            IR::STACK(offset) => {
+                self.u8(2);
+                self.u32(*offset);
+            }
+            IR::MEMLOC(loc) => {
+                self.u8(3);
+                self.u32(*loc);
+            }
+            IR::STACKPOP => self.u8(4),
+            IR::RETVAL => self.u8(5),
+        }
+    }
+
+    fn binary(&mut self, opcode: u8, ty: &ValueTy, or: &OR, l: &IR, r: &IR) {
+        self.u8(opcode);
+        self.u8(*ty as u16 as u8);
+        self.or(or);
+        self.ir(l);
+        self.ir(r);
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) {
+        match instruction {
+            Instruction::LABEL(label) => {
+                self.u8(0);
+                self.label(label);
+            }
+            Instruction::MOV(or, ir) => {
+                self.u8(1);
+                self.or(or);
+                self.ir(ir);
+            }
+            Instruction::CAST(or, ty) => {
+                self.u8(2);
+                self.or(or);
+                self.u8(*ty as u16 as u8);
+            }
+            Instruction::ADD(ty, or, l, r) => self.binary(3, ty, or, l, r),
+            Instruction::SUB(ty, or, l, r) => self.binary(4, ty, or, l, r),
+            Instruction::MUL(ty, or, l, r) => self.binary(5, ty, or, l, r),
+            Instruction::DIV(ty, or, l, r) => self.binary(6, ty, or, l, r),
+            Instruction::MOD(ty, or, l, r) => self.binary(7, ty, or, l, r),
+            Instruction::POW(ty, or, l, r) => self.binary(8, ty, or, l, r),
+            Instruction::LT(ty, or, l, r) => self.binary(9, ty, or, l, r),
+            Instruction::LTE(ty, or, l, r) => self.binary(10, ty, or, l, r),
+            Instruction::GT(ty, or, l, r) => self.binary(11, ty, or, l, r),
+            Instruction::GTE(ty, or, l, r) => self.binary(12, ty, or, l, r),
+            Instruction::EQ(ty, or, l, r) => self.binary(13, ty, or, l, r),
+            Instruction::NEQ(ty, or, l, r) => self.binary(14, ty, or, l, r),
+            Instruction::PUSH(ir) => {
+                self.u8(15);
+                self.ir(ir);
+            }
+            Instruction::POP(or) => {
+                self.u8(16);
+                self.or(or);
+            }
+            Instruction::JUMP(label) => {
+                self.u8(17);
+                self.label(label);
+            }
+            Instruction::NOP => self.u8(18),
+            Instruction::BT(ir, label) => {
+                self.u8(19);
+                self.ir(ir);
+                self.label(label);
+            }
+            Instruction::BF(ir, label) => {
+                self.u8(20);
+                self.ir(ir);
+                self.label(label);
+            }
+            Instruction::CALL(sym, arity) => {
+                self.u8(21);
+                self.string(*sym);
+                self.u32(*arity);
+            }
+            Instruction::RETURN(ir) => {
+                self.u8(22);
+                self.ir(ir);
+            }
+            Instruction::HLT => self.u8(23),
+        }
+    }
+}
+
+pub fn write_module(module: &Module, name_of: NameOf) -> Vec<u8> {
+    let mut body = Writer {
+        buf: vec![],
+        strings: vec![],
+        string_indices: HashMap::new(),
+        name_of,
+    };
+
+    body.u32(module.values.len());
+    for value in &module.values {
+        body.value(value);
+    }
+
+    body.u32(module.chunks.len());
+    for chunk in &module.chunks {
+        body.label(&chunk.label);
+        body.u32(chunk.instructions.len());
+        for (offset, instruction) in chunk.instructions.iter().enumerate() {
+            body.instruction(instruction);
+            body.u32(chunk.line(offset).unwrap_or(0));
+        }
+        body.u32(chunk.locals.len());
+        for local in &chunk.locals {
+            body.string(local.name);
+            body.or(&local.location);
+            body.u32(local.offset);
+        }
+    }
+
+    // Sorted so that the same module always serializes to the same bytes
+    let mut labels: Vec<(&Label, &LabelLocation)> = module.labels.iter().collect();
+    labels.sort_by_key(|(_, location)| (location.chunk_index, location.instruction_index));
+
+    body.u32(labels.len());
+    for (label, location) in labels {
+        body.label(label);
+        body.u32(location.chunk_index);
+        body.u32(location.instruction_index);
+    }
+
+    // The string table has to come first, but it's only complete once the body is written
+    let mut file: Vec<u8> = vec![];
+    file.extend_from_slice(MAGIC);
+    file.extend_from_slice(&VERSION.to_le_bytes());
+    file.extend_from_slice(&(body.strings.len() as u32).to_le_bytes());
+    for s in &body.strings {
+        file.extend_from_slice(&(s.len() as u32).to_le_bytes());
+        file.extend_from_slice(s.as_bytes());
+    }
+    file.extend_from_slice(&body.buf);
+    file
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    strings: Vec<usize>,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], LoadError> {
+        if self.pos + n > self.buf.len() {
+            return Err(LoadError::UnexpectedEndOfFile(self.buf.len()));
+        }
+        let bytes = &self.buf[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], LoadError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, LoadError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, LoadError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn usize(&mut self) -> Result<usize, LoadError> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn invalid(&self, what: &'static str, tag: u8) -> LoadError {
+        LoadError::InvalidTag(what, tag, self.pos - 1)
+    }
+
+    fn string(&mut self) -> Result<usize, LoadError> {
+        let index = self.u32()?;
+        match self.strings.get(index as usize) {
+            Some(sym) => Ok(*sym),
+            None => Err(LoadError::InvalidStringIndex(index)),
+        }
+    }
+
+    fn label(&mut self) -> Result<Label, LoadError> {
+        match self.u8()? {
+            0 => Ok(Label::Marker(self.usize()?)),
+            1 => Ok(Label::Named(self.string()?)),
+            tag => Err(self.invalid("label", tag)),
+        }
+    }
+
+    fn ty(&mut self) -> Result<ValueTy, LoadError> {
+        let tag = self.u8()?;
+        if tag > ValueTy::NONE as u16 as u8 {
+            return Err(self.invalid("type", tag));
+        }
+        Ok(ValueTy::from(tag as u16))
+    }
+
+    fn value(&mut self) -> Result<Value, LoadError> {
+        let value = match self.ty()? {
+            ValueTy::F64 => Value::from(f64::from_le_bytes(self.array()?)),
+            ValueTy::F32 => Value::from(f32::from_le_bytes(self.array()?)),
+            ValueTy::I8 => Value::from(i8::from_le_bytes(self.array()?)),
+            ValueTy::I16 => Value::from(i16::from_le_bytes(self.array()?)),
+            ValueTy::I32 => Value::from(i32::from_le_bytes(self.array()?)),
+            ValueTy::I64 => Value::from(i64::from_le_bytes(self.array()?)),
+            ValueTy::U8 => Value::from(u8::from_le_bytes(self.array()?)),
+            ValueTy::U16 => Value::from(u16::from_le_bytes(self.array()?)),
+            ValueTy::U32 => Value::from(u32::from_le_bytes(self.array()?)),
+            ValueTy::U64 => Value::from(u64::from_le_bytes(self.array()?)),
+            ValueTy::BOOL => match self.u8()? {
+                0 => Value::from(false),
+                1 => Value::from(true),
+                tag => return Err(self.invalid("bool", tag)),
+            },
+            ValueTy::STR => Value::from(ValueWrapper::STR(self.string()?)),
+            ValueTy::NONE => Value::from(NoneTy::None),
+        };
+        Ok(value)
+    }
+
+    fn or(&mut self) -> Result<OR, LoadError> {
+        match self.u8()? {
+            0 => Ok(OR::REG(self.usize()?)),
+            1 => Ok(OR::STACK(self.usize()?)),
+            tag => Err(self.invalid("output operand", tag)),
+        }
+    }
+
+    fn ir(&mut self) -> Result<IR, LoadError> {
+        match self.u8()? {
+            0 => Ok(IR::REG(self.usize()?)),
+            1 => Ok(IR::VALUE(self.usize()?)),
+            2 => Ok(IR::STACK(self.usize()?)),
+            3 => Ok(IR::MEMLOC(self.usize()?)),
+            4 => Ok(IR::STACKPOP),
+            5 => Ok(IR::RETVAL),
+            tag => Err(self.invalid("input operand", tag)),
+        }
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, LoadError> {
+        let instruction = match self.u8()? {
+            0 => Instruction::LABEL(self.label()?),
+            1 => Instruction::MOV(self.or()?, self.ir()?),
+            2 => Instruction::CAST(self.or()?, self.ty()?),
+            3 => Instruction::ADD(self.ty()?, self.or()?, self.ir()?, self.ir()?),
+            4 => Instruction::SUB(self.ty()?, self.or()?, self.ir()?, self.ir()?),
+            5 => Instruction::MUL(self.ty()?, self.or()?, self.ir()?, self.ir()?),
+            6 => Instruction::DIV(self.ty()?, self.or()?, self.ir()?, self.ir()?),
+            7 => Instruction::MOD(self.ty()?, self.or()?, self.ir()?, self.ir()?),
+            8 => Instruction::POW(self.ty()?, self.or()?, self.ir()?, self.ir()?),
+            9 => Instruction::LT(self.ty()?, self.or()?, self.ir()?, self.ir()?),
+            10 => Instruction::LTE(self.ty()?, self.or()?, self.ir()?, self.ir()?),
+            11 => Instruction::GT(self.ty()?, self.or()?, self.ir()?, self.ir()?),
+            12 => Instruction::GTE(self.ty()?, self.or()?, self.ir()?, self.ir()?),
+            13 => Instruction::EQ(self.ty()?, self.or()?, self.ir()?, self.ir()?),
+            14 => Instruction::NEQ(self.ty()?, self.or()?, self.ir()?, self.ir()?),
+            15 => Instruction::PUSH(self.ir()?),
+            16 => Instruction::POP(self.or()?),
+            17 => Instruction::JUMP(self.label()?),
+            18 => Instruction::NOP,
+            19 => Instruction::BT(self.ir()?, self.label()?),
+            20 => Instruction::BF(self.ir()?, self.label()?),
+            21 => Instruction::CALL(self.string()?, self.usize()?),
+            22 => Instruction::RETURN(self.ir()?),
+            23 => Instruction::HLT,
+            tag => return Err(self.invalid("opcode", tag)),
+        };
+        Ok(instruction)
+    }
+}
+
+pub fn load_module(buf: &[u8], intern: Intern) -> Result<Module, LoadError> {
+    let mut reader = Reader {
+        buf,
+        pos: 0,
+        strings: vec![],
+    };
+
+    if reader.bytes(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+        return Err(LoadError::NotBytecode);
+    }
+
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(LoadError::UnsupportedVersion(version));
+    }
+
+    for _ in 0..reader.u32()? {
+        let len = reader.usize()?;
+        let start = reader.pos;
+        match std::str::from_utf8(reader.bytes(len)?) {
+            Ok(s) => reader.strings.push(intern(s)),
+            Err(_) => return Err(LoadError::InvalidString(start)),
+        }
+    }
+
+    let mut module = Module::default();
+
+    for _ in 0..reader.u32()? {
+        let value = reader.value()?;
+        module.values.push(value);
+    }
+
+    for _ in 0..reader.u32()? {
+        let mut chunk = Chunk::new(reader.label()?);
+        for _ in 0..reader.u32()? {
+            let instruction = reader.instruction()?;
+            let line = match reader.usize()? {
+                0 => None,
+                line => Some(line),
+            };
+            chunk.add_instruction(instruction, line);
+        }
+        for _ in 0..reader.u32()? {
+            let local = Local {
+                name: reader.string()?,
+                location: reader.or()?,
+                offset: reader.usize()?,
+            };
+            chunk.locals.push(local);
+        }
+        module.chunks.push(chunk);
+    }
+
+    for _ in 0..reader.u32()? {
+        let label = reader.label()?;
+        let location = LabelLocation::new(reader.usize()?, reader.usize()?);
+        module.labels.insert(label, location);
+    }
+
+    if reader.pos != buf.len() {
+        return Err(LoadError::TrailingBytes(reader.pos));
+    }
+
+    Ok(module)
+}
@@ -0,0 +1,387 @@
+use pineapple_error::BytecodeError;
+use pineapple_ir::mir::{print_label, Label};
+
+use crate::bytecode::{Instruction, IR, NUM_REGISTERS, OR};
+use crate::module::{Chunk, Module};
+
+type NameOf<'a> = &'a dyn Fn(usize) -> String;
+
+// The arity of the native function with a name, Some(None) for a variadic one
+type NativeArity<'a> = &'a dyn Fn(usize) -> Option<Option<usize>>;
+
+/*
+    Static checks on a module before it's handed to the VM, so that a malformed
+    module (hand written, loaded from disk or a compiler bug) is rejected with an
+    error instead of panicking somewhere in the middle of a run.
+
+    - registers (including the ones in the variable table) are below
+      NUM_REGISTERS and constants are in the pool
+    - there are no mem[n] operands, the heap gives out (and reuses) its slots at
+      runtime so no index is valid ahead of time
+    - constants given to a typed instruction are of the instruction's type
+    - every label in the table points at its LABEL instruction, and jumps stay
+      inside of their chunk
+    - calls go to a chunk, with the same number of arguments as the chunk pops
+      in its prologue, or to a native function with as many as it takes
+    - the stack depth is the same along every path into an instruction, never
+      goes below zero and is back to zero when the chunk returns
+*/
+pub fn verify(
+    module: &Module,
+    native_arity: NativeArity,
+    name_of: NameOf,
+) -> Result<(), BytecodeError> {
+    for (label, location) in module.labels.iter() {
+        let instruction = module
+            .chunks
+            .get(location.chunk_index)
+            .and_then(|chunk| chunk.instructions.get(location.instruction_index));
+
+        // Named labels start their chunk
+        let misplaced = matches!(label, Label::Named(_)) && location.instruction_index != 0;
+        if misplaced || instruction != Some(&Instruction::LABEL(*label)) {
+            return Err(BytecodeError::MisplacedLabel(print_label(label, name_of)));
+        }
+    }
+
+    for (c, chunk) in module.chunks.iter().enumerate() {
+        let verifier = ChunkVerifier {
+            module,
+            chunk,
+            chunk_index: c,
+            native_arity,
+            name_of,
+        };
+        verifier.verify()?;
+    }
+
+    Ok(())
+}
+
+// Only a module that's going to be run as a program needs a main chunk, one that
+// a host calls into (see pineapple_passes::Engine) can do without
+pub fn verify_main(module: &Module, name_of: NameOf) -> Result<(), BytecodeError> {
+    let has_main = module.chunks.iter().any(|chunk| match chunk.label {
+        Label::Named(sym) => name_of(sym) == "main",
+        Label::Marker(_) => false,
+    });
+    if !has_main {
+        return Err(BytecodeError::MissingMain);
+    }
+    Ok(())
+}
+
+// The number of arguments a chunk takes, i.e. how many values its prologue pops
+fn arity(chunk: &Chunk) -> usize {
+    chunk
+        .instructions
+        .iter()
+        .skip_while(|instruction| matches!(instruction, Instruction::LABEL(_)))
+        .map(|instruction| pops(instruction))
+        .take_while(|pops| *pops > 0)
+        .sum()
+}
+
+fn inputs(instruction: &Instruction) -> Vec<IR> {
+    match instruction {
+        Instruction::MOV(_, ir)
+        | Instruction::PUSH(ir)
+        | Instruction::BT(ir, _)
+        | Instruction::BF(ir, _)
+        | Instruction::RETURN(ir) => vec![*ir],
+        Instruction::ADD(_, _, l, r)
+        | Instruction::SUB(_, _, l, r)
+        | Instruction::MUL(_, _, l, r)
+        | Instruction::DIV(_, _, l, r)
+        | Instruction::MOD(_, _, l, r)
+        | Instruction::POW(_, _, l, r)
+        | Instruction::LT(_, _, l, r)
+        | Instruction::LTE(_, _, l, r)
+        | Instruction::GT(_, _, l, r)
+        | Instruction::GTE(_, _, l, r)
+        | Instruction::EQ(_, _, l, r)
+        | Instruction::NEQ(_, _, l, r) => vec![*l, *r],
+        _ => vec![],
+    }
+}
+
+fn output(instruction: &Instruction) -> Option<OR> {
+    match instruction {
+        Instruction::MOV(or, _)
+        | Instruction::CAST(or, _)
+        | Instruction::ADD(_, or, _, _)
+        | Instruction::SUB(_, or, _, _)
+        | Instruction::MUL(_, or, _, _)
+        | Instruction::DIV(_, or, _, _)
+        | Instruction::MOD(_, or, _, _)
+        | Instruction::POW(_, or, _, _)
+        | Instruction::LT(_, or, _, _)
+        | Instruction::LTE(_, or, _, _)
+        | Instruction::GT(_, or, _, _)
+        | Instruction::GTE(_, or, _, _)
+        | Instruction::EQ(_, or, _, _)
+        | Instruction::NEQ(_, or, _, _)
+        | Instruction::POP(or) => Some(*or),
+        _ => None,
+    }
+}
+
+// Values taken off the stack by an instruction, calls pop their arguments
+// whether it's a native function doing it or the callee's prologue
+fn pops(instruction: &Instruction) -> usize {
+    let operands = inputs(instruction)
+        .iter()
+        .filter(|ir| **ir == IR::STACKPOP)
+        .count();
+
+    match instruction {
+        Instruction::POP(_) => operands + 1,
+        Instruction::CALL(_, arity) => operands + arity,
+        _ => operands,
+    }
+}
+
+struct ChunkVerifier<'a> {
+    module: &'a Module,
+    chunk: &'a Chunk,
+    chunk_index: usize,
+    native_arity: NativeArity<'a>,
+    name_of: NameOf<'a>,
+}
+
+impl<'a> ChunkVerifier<'a> {
+    // How errors refer to a label (i.e. the chunk they're in)
+    fn name(&self, label: &Label) -> String {
+        print_label(label, self.name_of)
+    }
+
+    fn verify(&self) -> Result<(), BytecodeError> {
+        for (offset, instruction) in self.chunk.instructions.iter().enumerate() {
+            self.verify_operands(offset, instruction)?;
+        }
+
+        // The debugger reads variables straight out of their registers
+        for local in &self.chunk.locals {
+            if let OR::REG(reg) = local.location {
+                if reg >= NUM_REGISTERS {
+                    return Err(BytecodeError::RegisterOutOfRange(
+                        self.name(&self.chunk.label),
+                        local.offset,
+                        reg,
+                        NUM_REGISTERS,
+                    ));
+                }
+            }
+        }
+        self.verify_stack()
+    }
+
+    fn verify_operands(
+        &self,
+        offset: usize,
+        instruction: &Instruction,
+    ) -> Result<(), BytecodeError> {
+        let chunk = || self.name(&self.chunk.label);
+
+        let register = |reg: usize| {
+            if reg < NUM_REGISTERS {
+                Ok(())
+            } else {
+                Err(BytecodeError::RegisterOutOfRange(
+                    chunk(),
+                    offset,
+                    reg,
+                    NUM_REGISTERS,
+                ))
+            }
+        };
+
+        for ir in inputs(instruction) {
+            match ir {
+                IR::REG(reg) => register(reg)?,
+                IR::VALUE(index) if index >= self.module.values.len() => {
+                    return Err(BytecodeError::ValueOutOfRange(chunk(), offset, index))
+                }
+                IR::MEMLOC(loc) => return Err(BytecodeError::MemoryOperand(chunk(), offset, loc)),
+                IR::VALUE(index) => {
+                    let value_ty = self.module.values[index].fetch_ty();
+                    match instruction.operand_ty() {
+                        Some(ty) if ty != value_ty => {
+                            return Err(BytecodeError::ConstantTypeMismatch(
+                                chunk(),
+                                offset,
+                                ty,
+                                value_ty,
+                            ))
+                        }
+                        _ => (),
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        if let Some(OR::REG(reg)) = output(instruction) {
+            register(reg)?;
+        }
+
+        match instruction {
+            Instruction::JUMP(label) | Instruction::BT(_, label) | Instruction::BF(_, label) => {
+                self.target(offset, label)?;
+            }
+            Instruction::CALL(sym, arity) => self.verify_call(offset, *sym, *arity)?,
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    fn verify_call(&self, offset: usize, sym: usize, arity: usize) -> Result<(), BytecodeError> {
+        // Functions of the module shadow natives with the same name
+        let chunk = self.name(&self.chunk.label);
+        let expected = match self.module.labels.get(&Label::Named(sym)) {
+            Some(location) => self::arity(&self.module.chunks[location.chunk_index]),
+            None => match (self.native_arity)(sym) {
+                Some(Some(expected)) => expected,
+                Some(None) => return Ok(()),
+                None => {
+                    return Err(BytecodeError::UndefinedFunction(
+                        chunk,
+                        offset,
+                        (self.name_of)(sym),
+                    ))
+                }
+            },
+        };
+
+        if expected != arity {
+            return Err(BytecodeError::ArityMismatch(
+                chunk,
+                offset,
+                (self.name_of)(sym),
+                expected,
+                arity,
+            ));
+        }
+        Ok(())
+    }
+
+    // The VM only follows marker labels inside of the current chunk
+    fn target(&self, offset: usize, label: &Label) -> Result<usize, BytecodeError> {
+        let chunk = self.name(&self.chunk.label);
+        match self.module.labels.get(label) {
+            None => Err(BytecodeError::UndefinedLabel(
+                chunk,
+                offset,
+                self.name(label),
+            )),
+            Some(location) => match label {
+                Label::Marker(_) if location.chunk_index == self.chunk_index => {
+                    Ok(location.instruction_index)
+                }
+                _ => Err(BytecodeError::JumpOutOfChunk(
+                    chunk,
+                    offset,
+                    self.name(label),
+                )),
+            },
+        }
+    }
+
+    fn successors(
+        &self,
+        offset: usize,
+        instruction: &Instruction,
+    ) -> Result<Vec<usize>, BytecodeError> {
+        let next = offset + 1;
+        let successors = match instruction {
+            Instruction::RETURN(_) | Instruction::HLT => vec![],
+            Instruction::JUMP(label) => vec![self.target(offset, label)?],
+            Instruction::BT(_, label) | Instruction::BF(_, label) => {
+                vec![next, self.target(offset, label)?]
+            }
+            _ => vec![next],
+        };
+
+        if successors.contains(&self.chunk.instructions.len()) {
+            return Err(BytecodeError::FallsOffEnd(self.name(&self.chunk.label)));
+        }
+        Ok(successors)
+    }
+
+    // Walks every path through the chunk tracking how many values are on the
+    // stack, starting from the arguments the caller pushed
+    fn verify_stack(&self) -> Result<(), BytecodeError> {
+        let chunk = || self.name(&self.chunk.label);
+        let instructions = &self.chunk.instructions;
+        if instructions.is_empty() {
+            return Err(BytecodeError::FallsOffEnd(chunk()));
+        }
+
+        let mut depths: Vec<Option<usize>> = vec![None; instructions.len()];
+        depths[0] = Some(arity(self.chunk));
+        let mut worklist = vec![0];
+
+        while let Some(offset) = worklist.pop() {
+            let instruction = &instructions[offset];
+            let depth = depths[offset].unwrap();
+
+            let mut stack_offsets: Vec<usize> = inputs(instruction)
+                .iter()
+                .filter_map(|ir| match ir {
+                    IR::STACK(n) => Some(*n),
+                    _ => None,
+                })
+                .collect();
+            if let Some(OR::STACK(n)) = output(instruction) {
+                stack_offsets.push(n);
+            }
+
+            for n in stack_offsets {
+                if n == 0 || n > depth {
+                    return Err(BytecodeError::StackOffsetOutOfRange(
+                        chunk(),
+                        offset,
+                        n,
+                        depth,
+                    ));
+                }
+            }
+
+            let depth = match depth.checked_sub(pops(instruction)) {
+                Some(depth) => depth,
+                None => return Err(BytecodeError::StackUnderflow(chunk(), offset)),
+            };
+            let depth = match instruction {
+                Instruction::PUSH(_) => depth + 1,
+                _ => depth,
+            };
+
+            if let Instruction::RETURN(_) = instruction {
+                if depth != 0 {
+                    return Err(BytecodeError::UnbalancedReturn(chunk(), offset, depth));
+                }
+            }
+
+            for successor in self.successors(offset, instruction)? {
+                match depths[successor] {
+                    None => {
+                        depths[successor] = Some(depth);
+                        worklist.push(successor);
+                    }
+                    Some(other) if other != depth => {
+                        return Err(BytecodeError::InconsistentStack(
+                            chunk(),
+                            successor,
+                            other,
+                            depth,
+                        ))
+                    }
+                    Some(_) => (),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,12 +1,13 @@
-use crate::analysis::cfg::CFG;
+use crate::analysis::cfg::{LocalVariable, CFG};
 use indexmap::IndexSet;
-use pineapple_ir::mir::Oper;
+use pineapple_ir::mir::{Oper, SSA};
 use std::{
     collections::{hash_map::Entry, HashMap},
     fmt,
+    rc::Rc,
 };
 
-const NUM_REGISTERS: usize = 16;
+pub const NUM_REGISTERS: usize = 16;
 
 #[derive(Copy, Clone, Hash, PartialEq, Eq)]
 pub struct Interval {
@@ -161,9 +162,31 @@ fn linear_scan_register_allocation(cfg: &CFG) -> AllocState {
     state
 }
 
+// The statements defining source variables, found before their operands get
+// replaced with registers and stack locations
+fn variable_definitions(cfg: &CFG) -> Vec<LocalVariable> {
+    let mut locals = vec![];
+    for bb in &cfg.blocks {
+        for statement in &bb.statements {
+            for def in statement.borrow().oper_defined() {
+                if let Oper::SSA(SSA::Var(sym, _)) = def {
+                    locals.push(LocalVariable {
+                        statement: Rc::clone(statement),
+                        sym,
+                        location: def,
+                    });
+                }
+            }
+        }
+    }
+    locals
+}
+
 pub fn register_allocation(cfg: &mut CFG) {
     reset_stack_loc();
     let mut state = linear_scan_register_allocation(cfg);
+    let mut locals = variable_definitions(cfg);
+    let mut assigned: HashMap<Oper, Oper> = HashMap::new();
 
     for (oper, reg) in state.register {
         let register = Oper::Register(reg);
@@ -171,11 +194,19 @@ pub fn register_allocation(cfg: &mut CFG) {
         // This line may not be needed, investigate.
         state.location.remove(&oper);
         cfg.replace_all_operand_with(&oper, &register);
+        assigned.insert(oper, register);
     }
 
     for (oper, _) in state.location {
         let loc = new_stack_loc();
         let stackloc = Oper::StackLocation(loc);
         cfg.replace_all_operand_with(&oper, &stackloc);
+        assigned.insert(oper, stackloc);
+    }
+
+    locals.retain(|local| assigned.contains_key(&local.location));
+    for local in &mut locals {
+        local.location = assigned[&local.location];
     }
+    cfg.locals = locals;
 }
@@ -15,6 +15,15 @@ type Statement = Rc<RefCell<Stmt>>;
 type StatementIndex = usize;
 type BlockIndex = usize;
 
+// Where a source variable ends up after register allocation, starting from the
+// statement that defines it. Only kept around for the debugger.
+#[derive(Debug, Clone)]
+pub struct LocalVariable {
+    pub statement: Statement,
+    pub sym: usize,
+    pub location: Oper,
+}
+
 #[derive(Clone)]
 pub struct CFG {
     pub entry_label: Label,
@@ -25,6 +34,8 @@ pub struct CFG {
 
     pub graph: Graph,
     pub dominator: DominatorContext,
+
+    pub locals: Vec<LocalVariable>,
 }
 
 impl CFG {
@@ -41,7 +52,7 @@ impl CFG {
     pub fn remove_statement(&mut self, statement: Statement) {
         for block in &mut self.blocks {
             for i in 0..block.statements.len() {
-                if block.statements[i] == statement {
+                if Rc::ptr_eq(&block.statements[i], &statement) {
                     block.statements.remove(i);
                     break;
                 }
@@ -175,6 +186,7 @@ impl From<Vec<Stmt>> for CFG {
             defined,
             used,
             dominator: DominatorContext::default(),
+            locals: vec![],
         };
         crate::analysis::dominator::compute_dominator_context(&mut cfg);
         cfg
@@ -190,15 +190,17 @@ fn convert_to_conventional_ssa(cfg: &mut CFG) {
         let b0 = &cfg.blocks[b0_ind];
         for statement in b0.statements.iter() {
             if let Stmt::Tac(a0, Expr::Phi(args)) = &mut *statement.borrow_mut() {
+                // The args get copied into the phi's own variable rather than their own, since
+                // after copy propagation an arg isn't necessarily a version of that variable.
+                let ai_prime = match a0 {
+                    Oper::SSA(SSA::Var(value, _)) => Oper::SSA(SSA::Var(*value, 0)),
+                    Oper::SSA(SSA::Temp(value, _)) => Oper::SSA(SSA::Temp(*value, 0)),
+                    _ => panic!("Expected var in phi function"),
+                };
+
                 for (ai, bi) in args.iter_mut() {
                     let pci = cfg.blocks[*bi].statements.last().unwrap();
 
-                    let ai_prime = match ai {
-                        Oper::SSA(SSA::Var(value, _)) => Oper::SSA(SSA::Var(*value, 0)),
-                        // Oper::SSA(SSA::Temp(value, _)) => Oper::SSA(SSA::Temp(*value, 0)),
-                        _ => panic!("Expected var in phi function"),
-                    };
-
                     match &mut *pci.borrow_mut() {
                         Stmt::ParallelCopy(pcopy) => {
                             let statement =
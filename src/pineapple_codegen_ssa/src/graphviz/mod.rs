@@ -0,0 +1,140 @@
+use std::fmt::Write;
+
+use pineapple_ir::mir::{print_stmt, Label, Stmt};
+
+use crate::analysis::basic_block::{BlockEntry, BlockExit};
+use crate::analysis::cfg::CFG;
+
+type BlockIndex = usize;
+
+// Symbols are interned in pineapple_session, so the caller resolves them for us
+type NameOf<'a> = &'a dyn Fn(usize) -> String;
+
+// Graphviz wants quotes and backslashes escaped inside of a label, and "\l" to
+// left justify each line (which makes the statements a lot easier to read).
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn block_label(cfg: &CFG, b: BlockIndex, name_of: NameOf) -> String {
+    let block = &cfg.blocks[b];
+    let mut label = format!("B{}\\l", b);
+
+    if let BlockEntry::Entry(s) = &block.entry {
+        label.push_str(&escape(&print_stmt(&*s.borrow(), name_of)));
+        label.push_str("\\l");
+    }
+
+    for s in &block.statements {
+        label.push_str(&escape(&print_stmt(&*s.borrow(), name_of)));
+        label.push_str("\\l");
+    }
+
+    if let BlockExit::Exit(s) = &block.exit {
+        label.push_str(&escape(&print_stmt(&*s.borrow(), name_of)));
+        label.push_str("\\l");
+    }
+
+    label
+}
+
+fn find_block_with_label(cfg: &CFG, label: &Label) -> Option<BlockIndex> {
+    cfg.blocks.iter().position(|block| match &block.entry {
+        BlockEntry::Entry(s) => *s.borrow() == Stmt::Label(*label),
+        BlockEntry::None => false,
+    })
+}
+
+pub fn cfg_to_dot(cfg: &CFG, name: &str, name_of: NameOf) -> String {
+    let mut dot = String::new();
+
+    writeln!(dot, "digraph \"{}\" {{", escape(name)).unwrap();
+    writeln!(dot, "    label=\"CFG: {}\";", escape(name)).unwrap();
+    writeln!(dot, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+
+    for b in 0..cfg.blocks.len() {
+        writeln!(dot, "    B{} [label=\"{}\"];", b, block_label(cfg, b, name_of)).unwrap();
+    }
+
+    for (a, successors) in cfg.graph.edges.iter().enumerate() {
+        // For conditional jumps we tag which edge is taken, the fallthrough is always the next block
+        let taken = match &cfg.blocks.get(a).map(|block| &block.exit) {
+            Some(BlockExit::Exit(s)) => match &*s.borrow() {
+                Stmt::CJump(_, label) => find_block_with_label(cfg, label),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        for b in successors {
+            match taken {
+                Some(taken) if taken == *b => {
+                    writeln!(dot, "    B{} -> B{} [label=\"true\"];", a, b).unwrap()
+                }
+                Some(_) => writeln!(dot, "    B{} -> B{} [label=\"false\"];", a, b).unwrap(),
+                None => writeln!(dot, "    B{} -> B{};", a, b).unwrap(),
+            }
+        }
+    }
+
+    writeln!(dot, "}}").unwrap();
+    dot
+}
+
+pub fn domtree_to_dot(cfg: &CFG, name: &str, name_of: NameOf) -> String {
+    let ctx = &cfg.dominator;
+    let mut dot = String::new();
+
+    writeln!(dot, "digraph \"{}\" {{", escape(name)).unwrap();
+    writeln!(dot, "    label=\"Dominator Tree: {}\";", escape(name)).unwrap();
+    writeln!(dot, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+
+    for b in 0..cfg.blocks.len() {
+        let entry = match &cfg.blocks[b].entry {
+            BlockEntry::Entry(s) => print_stmt(&*s.borrow(), name_of),
+            BlockEntry::None => String::new(),
+        };
+
+        let mut frontier: Vec<&BlockIndex> = ctx.domf[b].iter().collect();
+        frontier.sort();
+        let frontier = frontier
+            .iter()
+            .map(|f| format!("B{}", f))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        writeln!(
+            dot,
+            "    B{} [label=\"B{} {}\\lDF: {{{}}}\\l\"];",
+            b,
+            b,
+            escape(&entry),
+            frontier
+        )
+        .unwrap();
+    }
+
+    // Solid edges make up the tree itself (idom -> node)
+    for (n, idom) in ctx.idom.iter().enumerate() {
+        if let Some(idom) = idom {
+            writeln!(dot, "    B{} -> B{};", idom, n).unwrap();
+        }
+    }
+
+    // Dashed edges are the dominance frontier, kept out of the layout so the tree stays a tree
+    for (n, frontier) in ctx.domf.iter().enumerate() {
+        let mut frontier: Vec<&BlockIndex> = frontier.iter().collect();
+        frontier.sort();
+        for f in frontier {
+            writeln!(
+                dot,
+                "    B{} -> B{} [style=dashed, color=gray, constraint=false];",
+                n, f
+            )
+            .unwrap();
+        }
+    }
+
+    writeln!(dot, "}}").unwrap();
+    dot
+}
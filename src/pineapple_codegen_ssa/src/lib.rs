@@ -1,9 +1,15 @@
 use analysis::cfg::CFG;
+use pineapple_error::SSAError;
 
 mod allocation;
 pub mod analysis;
 mod convert;
+mod graphviz;
 mod optimization;
+mod verification;
+
+// Shared with the VM so register allocation never hands out a register it doesn't have
+pub use allocation::NUM_REGISTERS;
 
 pub fn convert_cfg_to_ssa_form(cfg: &mut CFG) {
     convert::construct_ssa(cfg);
@@ -11,9 +17,32 @@ pub fn convert_cfg_to_ssa_form(cfg: &mut CFG) {
 
 pub fn destruct_cfg_from_ssa_form(cfg: &mut CFG) {
     convert::destruct_ssa(cfg);
-    // optimization::constant_optimization(cfg);
+}
+
+pub fn copy_propagation(cfg: &mut CFG) {
+    optimization::constant_optimization(cfg);
+}
+
+pub fn dead_code_elimination(cfg: &mut CFG) {
+    optimization::dead_code_elimination(cfg);
 }
 
 pub fn register_allocation(cfg: &mut CFG) {
     allocation::register_allocation(cfg);
 }
+
+pub fn verify_ssa_form(cfg: &CFG) -> Result<(), SSAError> {
+    verification::verify_ssa(cfg)
+}
+
+pub fn verify_destructed_form(cfg: &CFG) -> Result<(), SSAError> {
+    verification::verify_destructed(cfg)
+}
+
+pub fn cfg_to_dot(cfg: &CFG, name: &str, name_of: &dyn Fn(usize) -> String) -> String {
+    graphviz::cfg_to_dot(cfg, name, name_of)
+}
+
+pub fn domtree_to_dot(cfg: &CFG, name: &str, name_of: &dyn Fn(usize) -> String) -> String {
+    graphviz::domtree_to_dot(cfg, name, name_of)
+}
@@ -1,33 +1,104 @@
+use std::collections::HashSet;
 use std::rc::Rc;
 
 use pineapple_ir::mir::{Expr, Oper, Stmt};
+use pineapple_ir::op::BinOp;
 
+use crate::analysis::basic_block::BlockExit;
 use crate::CFG;
 
+// CastAs converts its operand in place, so anything that gets cast can't be swapped
+// out for (or with) a copy of itself without the cast leaking into the other name.
+fn casted_opers(cfg: &CFG) -> HashSet<Oper> {
+    let mut casted: HashSet<Oper> = HashSet::new();
+    for statement in cfg.active_statements() {
+        if let Stmt::CastAs(oper, _) = &*statement.borrow() {
+            casted.insert(*oper);
+        }
+    }
+    casted
+}
+
 pub fn constant_optimization(cfg: &mut CFG) {
+    let casted = casted_opers(cfg);
+
     let mut w = cfg.active_statements();
     w.reverse();
 
-    while !w.is_empty() {
-        let statement = w.pop().unwrap();
-
+    while let Some(statement) = w.pop() {
         // Copy Propagation
-        if let Stmt::Tac(lval, Expr::Oper(oper)) = &*statement.borrow() {
-            if let Oper::SSA(_) = oper {
-                for t in cfg.get_statements_using_oper(lval) {
-                    match t.try_borrow_mut() {
-                        Ok(mut refmut) => {
-                            refmut.replace_all_oper_use_with(lval, oper);
-                            if !w.contains(&t) {
-                                w.push(Rc::clone(&t));
-                            }
-                        }
-                        _ => (),
-                    }
-                    
+        let copy = match &*statement.borrow() {
+            Stmt::Tac(lval, Expr::Oper(oper @ Oper::SSA(_))) => Some((*lval, *oper)),
+            _ => None,
+        };
+
+        if let Some((lval, oper)) = copy {
+            if casted.contains(&lval) || casted.contains(&oper) {
+                continue;
+            }
+
+            for t in cfg.get_statements_using_oper(&lval) {
+                t.borrow_mut().replace_all_oper_use_with(&lval, &oper);
+                if !w.iter().any(|s| Rc::ptr_eq(s, &t)) {
+                    w.push(Rc::clone(&t));
                 }
-                cfg.remove_statement(Rc::clone(&statement));
             }
-        };
+            cfg.remove_statement(Rc::clone(&statement));
+        }
+    }
+}
+
+// Popping the stack has to happen no matter what, and a division (or an integer raised
+// to a negative power) might be the thing that (correctly) blows up at runtime, so
+// none of those are ever considered dead.
+fn has_side_effects(rval: &Expr) -> bool {
+    match rval {
+        Expr::Oper(oper) => *oper == Oper::StackPop,
+        Expr::Binary(l, op, r, _) => {
+            *l == Oper::StackPop
+                || *r == Oper::StackPop
+                || *op == BinOp::Slash
+                || *op == BinOp::Modulo
+                || *op == BinOp::Carat
+        }
+        Expr::Logical(l, _, r, _) => *l == Oper::StackPop || *r == Oper::StackPop,
+        Expr::Phi(_) => false,
+    }
+}
+
+pub fn dead_code_elimination(cfg: &mut CFG) {
+    loop {
+        let mut used: HashSet<Oper> = HashSet::new();
+        for block in &cfg.blocks {
+            for statement in &block.statements {
+                match &*statement.borrow() {
+                    Stmt::Tac(_, Expr::Phi(args)) => used.extend(args.iter().map(|(arg, _)| *arg)),
+                    statement => used.extend(statement.oper_used()),
+                }
+            }
+
+            if let BlockExit::Exit(statement) = &block.exit {
+                used.extend(statement.borrow().oper_used());
+            }
+        }
+
+        let dead: Vec<_> = cfg
+            .active_statements()
+            .into_iter()
+            .filter(|statement| match &*statement.borrow() {
+                Stmt::Tac(lval @ Oper::SSA(_), rval) => {
+                    !used.contains(lval) && !has_side_effects(rval)
+                }
+                _ => false,
+            })
+            .collect();
+
+        if dead.is_empty() {
+            break;
+        }
+
+        for statement in dead {
+            cfg.remove_statement(statement);
+        }
     }
 }
@@ -0,0 +1,213 @@
+use std::collections::{HashMap, HashSet};
+
+use pineapple_error::SSAError;
+use pineapple_ir::mir::{Expr, Oper, Stmt};
+
+use crate::analysis::basic_block::BlockExit;
+use crate::analysis::cfg::CFG;
+
+type BlockIndex = usize;
+type StatementIndex = usize;
+
+// Where an operand lives inside of the cfg. Block exits get an index one past the
+// last statement so that uses in a cjump are "after" everything else in the block.
+#[derive(Copy, Clone)]
+struct Location {
+    block: BlockIndex,
+    statement: StatementIndex,
+}
+
+fn dominates(cfg: &CFG, def: &Location, use_: &Location) -> bool {
+    if def.block == use_.block {
+        def.statement < use_.statement
+    } else {
+        cfg.dominator.strictly_dominates(&def.block, &use_.block)
+    }
+}
+
+fn collect_definitions(cfg: &CFG) -> Result<HashMap<Oper, Location>, SSAError> {
+    let mut definitions: HashMap<Oper, Location> = HashMap::new();
+
+    for (b, block) in cfg.blocks.iter().enumerate() {
+        for (s, statement) in block.statements.iter().enumerate() {
+            for def in statement.borrow().oper_defined() {
+                if let Some(first) = definitions.get(&def) {
+                    return Err(SSAError::MultipleDefinitions(def, first.block, b));
+                }
+                definitions.insert(
+                    def,
+                    Location {
+                        block: b,
+                        statement: s,
+                    },
+                );
+            }
+        }
+    }
+
+    Ok(definitions)
+}
+
+fn check_use(
+    cfg: &CFG,
+    definitions: &HashMap<Oper, Location>,
+    oper: Oper,
+    location: Location,
+) -> Result<(), SSAError> {
+    match definitions.get(&oper) {
+        Some(def) => {
+            if dominates(cfg, def, &location) {
+                Ok(())
+            } else {
+                Err(SSAError::UseNotDominated(oper, def.block, location.block))
+            }
+        }
+        None => Err(SSAError::UndefinedOperand(oper, location.block)),
+    }
+}
+
+fn check_phi(
+    cfg: &CFG,
+    definitions: &HashMap<Oper, Location>,
+    lval: Oper,
+    args: &[(Oper, BlockIndex)],
+    block: BlockIndex,
+) -> Result<(), SSAError> {
+    let predecessors = cfg.graph.predecessors(block);
+    if args.len() != predecessors.len() {
+        return Err(SSAError::PhiArityMismatch(
+            lval,
+            block,
+            args.len(),
+            predecessors.len(),
+        ));
+    }
+
+    // With the arity matching, every predecessor showing up at most once means
+    // every predecessor shows up exactly once
+    let mut seen: HashSet<BlockIndex> = HashSet::new();
+    for (oper, pred) in args {
+        if !predecessors.contains(pred) {
+            return Err(SSAError::PhiArgumentNotPredecessor(lval, block, *pred));
+        }
+        if !seen.insert(*pred) {
+            return Err(SSAError::PhiDuplicatePredecessor(lval, block, *pred));
+        }
+
+        // A phi arg is "used" at the very end of the predecessor it flows in from
+        if let Oper::SSA(_) = oper {
+            let location = Location {
+                block: *pred,
+                statement: cfg.blocks[*pred].statements.len() + 1,
+            };
+            check_use(cfg, definitions, *oper, location)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Checks the invariants that should hold between SSA construction and destruction:
+//  - every SSA operand is defined exactly once
+//  - every use is dominated by its definition
+//  - phi functions sit at the top of their block and have exactly one arg per predecessor
+pub fn verify_ssa(cfg: &CFG) -> Result<(), SSAError> {
+    let definitions = collect_definitions(cfg)?;
+
+    for (b, block) in cfg.blocks.iter().enumerate() {
+        let mut seen_non_phi = false;
+
+        for (s, statement) in block.statements.iter().enumerate() {
+            let statement = &*statement.borrow();
+
+            if let Stmt::Tac(lval, Expr::Phi(args)) = statement {
+                if seen_non_phi {
+                    return Err(SSAError::PhiNotAtBlockStart(*lval, b));
+                }
+                check_phi(cfg, &definitions, *lval, args, b)?;
+                continue;
+            }
+            seen_non_phi = true;
+
+            for oper in statement.oper_used() {
+                let location = Location {
+                    block: b,
+                    statement: s,
+                };
+                check_use(cfg, &definitions, oper, location)?;
+            }
+        }
+
+        if let BlockExit::Exit(statement) = &block.exit {
+            for oper in statement.borrow().oper_used() {
+                let location = Location {
+                    block: b,
+                    statement: block.statements.len(),
+                };
+                check_use(cfg, &definitions, oper, location)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// After destruction the cfg should be back to plain old copies, so nothing that
+// only makes sense in SSA form should be hanging around.
+pub fn verify_destructed(cfg: &CFG) -> Result<(), SSAError> {
+    for (b, block) in cfg.blocks.iter().enumerate() {
+        for statement in &block.statements {
+            match &*statement.borrow() {
+                Stmt::ParallelCopy(_) => return Err(SSAError::SurvivingParallelCopy(b)),
+                Stmt::Tac(lval, Expr::Phi(_)) => return Err(SSAError::SurvivingPhi(*lval, b)),
+                _ => (),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pineapple_ir::mir::parse_mir;
+
+    use super::*;
+
+    // Blocks only end at jumps, so every arm needs its goto for B3 to join B1 and B2
+    fn join(phi: &str) -> CFG {
+        let mir = format!(
+            "@f:
+                _v0.0 = pop
+                if _v0.0 goto L0
+            L1:
+                _v1.0 = 1i32
+                goto L2
+            L0:
+                _v1.1 = 2i32
+                goto L2
+            L2:
+                _v1.2 = {}
+                ret _v1.2",
+            phi
+        );
+        let mut linear_code = parse_mir(&mir, &mut |_| 0).unwrap();
+        CFG::from(linear_code.remove(0))
+    }
+
+    #[test]
+    fn phi_with_one_arg_per_predecessor() {
+        let cfg = join("phi(B1: _v1.0, B2: _v1.1)");
+        assert!(verify_ssa(&cfg).is_ok());
+    }
+
+    #[test]
+    fn phi_repeating_a_predecessor() {
+        let cfg = join("phi(B1: _v1.0, B1: _v1.0)");
+        match verify_ssa(&cfg) {
+            Err(SSAError::PhiDuplicatePredecessor(_, 3, 1)) => (),
+            Err(e) => panic!("expected a duplicate predecessor, found: {}", e),
+            Ok(()) => panic!("expected a duplicate predecessor"),
+        }
+    }
+}
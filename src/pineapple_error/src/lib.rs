@@ -1,15 +1,26 @@
 use std::fmt;
 
 use pineapple_ir::hir::token::{Token, TokenKind};
+use pineapple_ir::mir::{type_name, Label, Oper};
+use pineapple_ir::op::BinOp;
 use pineapple_ir::{Value, ValueTy};
 
-type Ident = usize;
+// Names are resolved before they go into an error, so that it can be shown
+// without the interner of the program it came from
+type Name = String;
 type Type = ValueTy;
+type BlockIndex = usize;
 
+#[derive(Debug)]
 pub enum ScanError {
     InputStreamEmpty,
     UnterminatedString(usize, usize),
     InvalidNumeric(usize, usize),
+    InvalidInterpolation(usize, usize),
+    InvalidEscape(usize, usize),
+    InvalidUnicodeEscape(usize, usize),
+    NumericOutOfRange(usize, usize),
+    UnterminatedComment(usize, usize),
 }
 
 impl fmt::Display for ScanError {
@@ -24,6 +35,21 @@ impl fmt::Display for ScanError {
             ScanError::InvalidNumeric(line, col) => {
                 write!(f, "At {}:{}. Encountered a invalid numeric while scanning.", line, col)
             }
+            ScanError::InvalidInterpolation(line, col) => {
+                write!(f, "At {}:{}. Encountered an empty or unterminated {{...}} in a string while scanning.", line, col)
+            }
+            ScanError::InvalidEscape(line, col) => {
+                write!(f, "At {}:{}. Encountered an invalid escape sequence while scanning.", line, col)
+            }
+            ScanError::InvalidUnicodeEscape(line, col) => {
+                write!(f, "At {}:{}. Encountered an invalid unicode escape while scanning, expected \\u{{...}} with 1 to 6 hex digits of a unicode scalar value.", line, col)
+            }
+            ScanError::NumericOutOfRange(line, col) => {
+                write!(f, "At {}:{}. Encountered a numeric that doesn't fit in its type while scanning.", line, col)
+            }
+            ScanError::UnterminatedComment(line, col) => {
+                write!(f, "At {}:{}. Encountered an unterminated block comment while scanning.", line, col)
+            }
         }
     }
 }
@@ -40,7 +66,9 @@ pub enum ParseError {
     ExpectedLiteral(Token),
     ExpectedVariableTy(Token),
     ExpectedLValue,
-    UndefinedVariable(usize),
+    UndefinedVariable(Name),
+    InvalidNegation(Token),
+    LiteralOutOfRange(Token),
 }
 
 impl fmt::Display for ParseError {
@@ -93,8 +121,22 @@ impl fmt::Display for ParseError {
                 write!(f, "Expected Lvalue in assign")
             }
             ParseError::UndefinedVariable(sym) => {
-                write!(f, "Undefined Variable {:?}", pineapple_session::get_string(*sym))
+                write!(f, "Undefined Variable {:?}", sym)
             }
+            ParseError::InvalidNegation(token) => match &token.kind {
+                TokenKind::IntLit(value) | TokenKind::FloatLit(value) | TokenKind::TypedLit(value) => {
+                    write!(f, "At {}:{}. Can't negate the {} literal {:?}.", token.line, token.col, type_name(value.fetch_ty()), value)
+                }
+                _ => write!(f, "At {}:{}. Can't negate `{:?}`.", token.line, token.col, token.kind),
+            },
+            ParseError::LiteralOutOfRange(token) => match &token.kind {
+                TokenKind::TypedLit(value) => {
+                    let ty = type_name(value.fetch_ty());
+                    let magnitude = format!("{:?}", value).trim_start_matches('-').to_string();
+                    write!(f, "At {}:{}. {}{} doesn't fit in {}, only -{}{} does.", token.line, token.col, magnitude, ty, ty, magnitude, ty)
+                }
+                _ => write!(f, "At {}:{}. The literal doesn't fit in its type.", token.line, token.col),
+            },
         }
     }
 }
@@ -111,15 +153,20 @@ impl fmt::Display for BindingError {
     }
 }
 
+#[derive(Debug)]
 pub enum TypeError {
     InvalidValueType(Value, Type, Type),
-    InvalidVariableType(Ident, Type, Type),
+    InvalidVariableType(Name, Type, Type),
     InvalidReturnType(Type, Type),
     InvalidExprType(Type, Type),
     ExpectedNestedType,
-    UndefinedVariable(Ident),
-    UndefinedFunction(Ident),
-    FunctionArityMismatch(Ident, usize, usize),
+    UndefinedVariable(Name),
+    UndefinedFunction(Name),
+    FunctionArityMismatch(Name, usize, usize),
+    NoMatchingOverload(Name, Vec<Type>),
+    UnsupportedOperator(BinOp, Type),
+    NotConvertibleToString(Type),
+    LiteralOutOfRange(Value, Type, usize, usize),
 }
 
 impl fmt::Display for TypeError {
@@ -129,7 +176,7 @@ impl fmt::Display for TypeError {
                 write!(f, "Invalid Value Type for {:?}. Expected {:?} but got {:?} instead.", value, expected_type, actual_type)
             }
             TypeError::InvalidVariableType(value, actual_type, expected_type) => {
-                write!(f, "Invalid type for {:?}. Expected {:?} but got {:?}", pineapple_session::get_string(*value), actual_type, expected_type)
+                write!(f, "Invalid type for {:?}. Expected {:?} but got {:?}", value, actual_type, expected_type)
             }
             TypeError::InvalidReturnType(actual_type, expected_type) => {
                 write!(f, "Invalid return type. Expected {:?} but got {:?}", actual_type, expected_type)
@@ -141,14 +188,435 @@ impl fmt::Display for TypeError {
                 write!(f, "Expected expr to have a type. This is an internal error and you should flame the compiler engineer.")
             }
             TypeError::UndefinedVariable(sym) => {
-                write!(f, "Undefined Variable '{}'", pineapple_session::get_string(*sym))
+                write!(f, "Undefined Variable '{}'", sym)
             }
             TypeError::UndefinedFunction(sym) => {
-                write!(f, "Undefined Function '{}'", pineapple_session::get_string(*sym))
+                write!(f, "Undefined Function '{}'", sym)
             }
             TypeError::FunctionArityMismatch(sym, a, b) => {
-                write!(f, "Function '{}' expected {} args, but got {}.", pineapple_session::get_string(*sym), a, b)
+                write!(f, "Function '{}' expected {} args, but got {}.", sym, a, b)
+            }
+            TypeError::NoMatchingOverload(sym, arg_tys) => {
+                let arg_tys: Vec<String> = arg_tys.iter().map(|ty| format!("{:?}", ty)).collect();
+                write!(f, "No overload of '{}' takes ({}).", sym, arg_tys.join(", "))
+            }
+            TypeError::UnsupportedOperator(op, ty) => {
+                write!(f, "Operator '{:?}' isn't defined for {}.", op, type_name(*ty))
+            }
+            TypeError::LiteralOutOfRange(value, ty, line, col) => {
+                write!(f, "At {}:{}. The literal {:?} doesn't fit in {:?}.", line, col, value, ty)
+            }
+            TypeError::NotConvertibleToString(ty) => {
+                write!(f, "Can't interpolate a {:?} into a string, there's no to_string for it.", ty)
             }
         }
     }
 }
+
+// Anything that stops source code from compiling, see pineapple_passes::Engine
+#[derive(Debug)]
+pub enum CompileError {
+    Scan(ScanError),
+    Parse(ParseError),
+    Type(TypeError),
+    Malformed(BytecodeError),
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            CompileError::Scan(e) => write!(f, "{}", e),
+            CompileError::Parse(e) => write!(f, "{}", e),
+            CompileError::Type(e) => write!(f, "{}", e),
+            CompileError::Malformed(e) => {
+                write!(f, "Compiled to a malformed module, this is a bug in the compiler. {}", e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+pub enum SSAError {
+    MultipleDefinitions(Oper, BlockIndex, BlockIndex),
+    UndefinedOperand(Oper, BlockIndex),
+    UseNotDominated(Oper, BlockIndex, BlockIndex),
+    PhiArityMismatch(Oper, BlockIndex, usize, usize),
+    PhiNotAtBlockStart(Oper, BlockIndex),
+    PhiArgumentNotPredecessor(Oper, BlockIndex, BlockIndex),
+    PhiDuplicatePredecessor(Oper, BlockIndex, BlockIndex),
+    SurvivingPhi(Oper, BlockIndex),
+    SurvivingParallelCopy(BlockIndex),
+}
+
+impl fmt::Display for SSAError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            SSAError::MultipleDefinitions(oper, first, second) => {
+                write!(f, "{:?} is defined more than once, in B{} and again in B{}.", oper, first, second)
+            }
+            SSAError::UndefinedOperand(oper, block) => {
+                write!(f, "{:?} is used in B{} but is never defined.", oper, block)
+            }
+            SSAError::UseNotDominated(oper, def_block, use_block) => {
+                write!(f, "{:?} is defined in B{} which does not dominate its use in B{}.", oper, def_block, use_block)
+            }
+            SSAError::PhiArityMismatch(oper, block, args, preds) => {
+                write!(f, "Φ for {:?} in B{} has {} args, but the block has {} predecessors.", oper, block, args, preds)
+            }
+            SSAError::PhiNotAtBlockStart(oper, block) => {
+                write!(f, "Φ for {:?} in B{} appears after a non-Φ statement.", oper, block)
+            }
+            SSAError::PhiArgumentNotPredecessor(oper, block, arg_block) => {
+                write!(f, "Φ for {:?} in B{} has an arg from B{}, which is not a predecessor.", oper, block, arg_block)
+            }
+            SSAError::PhiDuplicatePredecessor(oper, block, arg_block) => {
+                write!(f, "Φ for {:?} in B{} has more than one arg from B{}.", oper, block, arg_block)
+            }
+            SSAError::SurvivingPhi(oper, block) => {
+                write!(f, "Φ for {:?} in B{} survived SSA destruction.", oper, block)
+            }
+            SSAError::SurvivingParallelCopy(block) => {
+                write!(f, "A parallel copy in B{} survived SSA destruction.", block)
+            }
+        }
+    }
+}
+
+pub enum InterpretError {
+    MissingMain,
+    UndefinedOperand(Oper),
+    UndefinedFunction(Name),
+    UndefinedLabel(Label),
+    StackUnderflow,
+    MissingPhiArgument(Oper, BlockIndex),
+    ExpectedBool(Value),
+    DivisionByZero,
+    NegativeExponent,
+    InvalidOperation(String),
+    InvalidCast(Value, Type),
+}
+
+impl fmt::Display for InterpretError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            InterpretError::MissingMain => write!(f, "There is no 'main' function to run."),
+            InterpretError::UndefinedOperand(oper) => {
+                write!(f, "{:?} was read before it was ever written.", oper)
+            }
+            InterpretError::UndefinedFunction(sym) => {
+                write!(f, "Undefined Function '{}'", sym)
+            }
+            InterpretError::UndefinedLabel(label) => write!(f, "Undefined label {:?}.", label),
+            InterpretError::StackUnderflow => write!(f, "Attempted to pop from an empty stack."),
+            InterpretError::MissingPhiArgument(oper, block) => {
+                write!(f, "Φ for {:?} has no arg for the predecessor B{}.", oper, block)
+            }
+            InterpretError::ExpectedBool(value) => {
+                write!(f, "Expected a bool for a conditional jump but got {:?}.", value)
+            }
+            InterpretError::DivisionByZero => write!(f, "Attempted to divide by zero."),
+            InterpretError::NegativeExponent => write!(f, "Attempted to raise an integer to a negative power."),
+            InterpretError::InvalidOperation(op) => write!(f, "Invalid operation: {}.", op),
+            InterpretError::InvalidCast(value, ty) => {
+                write!(f, "Unable to cast {:?} to {:?}.", value, ty)
+            }
+        }
+    }
+}
+
+pub enum LoadError {
+    NotBytecode,
+    UnsupportedVersion(u16),
+    UnexpectedEndOfFile(usize),
+    InvalidTag(&'static str, u8, usize),
+    InvalidString(usize),
+    InvalidStringIndex(u32),
+    TrailingBytes(usize),
+    Malformed(BytecodeError),
+    Runtime(RuntimeError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            LoadError::NotBytecode => write!(f, "This is not a Pineapple bytecode file."),
+            LoadError::UnsupportedVersion(version) => {
+                write!(f, "Bytecode format version {} is not supported.", version)
+            }
+            LoadError::UnexpectedEndOfFile(offset) => {
+                write!(f, "At byte {}. Unexpected end of file.", offset)
+            }
+            LoadError::InvalidTag(what, tag, offset) => {
+                write!(f, "At byte {}. {} is not a valid {} tag.", offset, tag, what)
+            }
+            LoadError::InvalidString(offset) => {
+                write!(f, "At byte {}. String table entry is not valid UTF-8.", offset)
+            }
+            LoadError::InvalidStringIndex(index) => {
+                write!(f, "String table index {} is out of range.", index)
+            }
+            LoadError::TrailingBytes(offset) => {
+                write!(f, "At byte {}. Unexpected data after the label table.", offset)
+            }
+            LoadError::Malformed(e) => write!(f, "Malformed module. {}", e),
+            LoadError::Runtime(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+type Line = usize;
+
+pub enum AssembleError {
+    UnknownInstruction(Line, String),
+    MissingOperandType(Line, String),
+    InvalidOperand(Line, String, &'static str),
+    WrongOperandCount(Line, String, usize, usize),
+    InstructionOutsideChunk(Line),
+    DuplicateLabel(Line, String),
+    Malformed(BytecodeError),
+    Runtime(RuntimeError),
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            AssembleError::UnknownInstruction(line, name) => {
+                write!(f, "At line {}. Unknown instruction `{}`.", line, name)
+            }
+            AssembleError::MissingOperandType(line, name) => write!(
+                f,
+                "At line {}. `{}` needs the type of its operands (i.e. `{}_I32`).",
+                line, name, name
+            ),
+            AssembleError::InvalidOperand(line, found, expected) => write!(
+                f,
+                "At line {}. Expected {} but instead found `{}`.",
+                line, expected, found
+            ),
+            AssembleError::WrongOperandCount(line, name, expected, found) => write!(
+                f,
+                "At line {}. `{}` takes {} operand(s) but was given {}.",
+                line, name, expected, found
+            ),
+            AssembleError::InstructionOutsideChunk(line) => write!(
+                f,
+                "At line {}. Instructions have to come after a named label (i.e. `@main:`).",
+                line
+            ),
+            AssembleError::DuplicateLabel(line, label) => {
+                write!(f, "At line {}. The label `{}` is defined more than once.", line, label)
+            }
+            AssembleError::Malformed(e) => write!(f, "{}", e),
+            AssembleError::Runtime(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+type Offset = usize;
+
+// A label as it's printed, i.e. `@main` or `_L3`
+type Chunk = String;
+
+#[derive(Debug)]
+pub enum BytecodeError {
+    MissingMain,
+    MisplacedLabel(Chunk),
+    RegisterOutOfRange(Chunk, Offset, usize, usize),
+    ValueOutOfRange(Chunk, Offset, usize),
+    MemoryOperand(Chunk, Offset, usize),
+    ConstantTypeMismatch(Chunk, Offset, ValueTy, ValueTy),
+    UndefinedLabel(Chunk, Offset, Chunk),
+    JumpOutOfChunk(Chunk, Offset, Chunk),
+    UndefinedFunction(Chunk, Offset, Name),
+    ArityMismatch(Chunk, Offset, Name, usize, usize),
+    StackUnderflow(Chunk, Offset),
+    StackOffsetOutOfRange(Chunk, Offset, usize, usize),
+    InconsistentStack(Chunk, Offset, usize, usize),
+    UnbalancedReturn(Chunk, Offset, usize),
+    FallsOffEnd(Chunk),
+}
+
+impl fmt::Display for BytecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            BytecodeError::MissingMain => write!(f, "There is no 'main' chunk to run."),
+            BytecodeError::MisplacedLabel(label) => write!(
+                f,
+                "The label table entry for {} does not point at its LABEL instruction.",
+                label
+            ),
+            BytecodeError::RegisterOutOfRange(chunk, offset, reg, num) => write!(
+                f,
+                "In {} at {:04}. Register r{} is out of range, there are only {} registers.",
+                chunk, offset, reg, num
+            ),
+            BytecodeError::ValueOutOfRange(chunk, offset, index) => write!(
+                f,
+                "In {} at {:04}. Constant #{} is not in the constant pool.",
+                chunk, offset, index
+            ),
+            BytecodeError::MemoryOperand(chunk, offset, loc) => write!(
+                f,
+                "In {} at {:04}. mem[{}] can't be an operand, heap slots only exist while the program runs.",
+                chunk, offset, loc
+            ),
+            BytecodeError::ConstantTypeMismatch(chunk, offset, expected, found) => write!(
+                f,
+                "In {} at {:04}. Expected an operand of type {} but found a constant of type {}.",
+                chunk, offset, type_name(*expected), type_name(*found)
+            ),
+            BytecodeError::UndefinedLabel(chunk, offset, label) => write!(
+                f,
+                "In {} at {:04}. Jump to the undefined label {}.",
+                chunk, offset, label
+            ),
+            BytecodeError::JumpOutOfChunk(chunk, offset, label) => write!(
+                f,
+                "In {} at {:04}. Jump to {}, which is outside of the chunk.",
+                chunk, offset, label
+            ),
+            BytecodeError::UndefinedFunction(chunk, offset, sym) => write!(
+                f,
+                "In {} at {:04}. Call to the undefined function '{}'.",
+                chunk, offset, sym
+            ),
+            BytecodeError::ArityMismatch(chunk, offset, sym, expected, found) => write!(
+                f,
+                "In {} at {:04}. '{}' takes {} argument(s) but was called with {}.",
+                chunk, offset, sym, expected, found
+            ),
+            BytecodeError::StackUnderflow(chunk, offset) => write!(
+                f,
+                "In {} at {:04}. Pops from an empty stack.",
+                chunk, offset
+            ),
+            BytecodeError::StackOffsetOutOfRange(chunk, offset, stack_offset, depth) => write!(
+                f,
+                "In {} at {:04}. sp[-{}] is out of range, the stack only holds {} value(s) here.",
+                chunk, offset, stack_offset, depth
+            ),
+            BytecodeError::InconsistentStack(chunk, offset, a, b) => write!(
+                f,
+                "In {} at {:04}. Reached with both {} and {} value(s) on the stack.",
+                chunk, offset, a, b
+            ),
+            BytecodeError::UnbalancedReturn(chunk, offset, depth) => write!(
+                f,
+                "In {} at {:04}. Returns with {} value(s) left on the stack.",
+                chunk, offset, depth
+            ),
+            BytecodeError::FallsOffEnd(chunk) => write!(
+                f,
+                "In {}. Execution can run past the last instruction without a RETURN or HLT.",
+                chunk
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RuntimeErrorKind {
+    DivisionByZero,
+    NegativeExponent,
+    StackUnderflow,
+    StackOutOfBounds(usize, usize),
+    MemoryOutOfBounds(usize),
+    InvalidCast(Type, Type),
+    OperandTypeMismatch(Type, Type),
+    UninitializedValue,
+    UnexpectedString,
+    UndefinedFunction(Name),
+    ArityMismatch(Name, usize, usize),
+    ArgumentTypeMismatch(Name, usize, Type, Type),
+    NativeError(Name, String),
+    Output(String),
+}
+
+impl fmt::Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            RuntimeErrorKind::DivisionByZero => write!(f, "Division by zero."),
+            RuntimeErrorKind::NegativeExponent => write!(f, "Integer raised to a negative power."),
+            RuntimeErrorKind::StackUnderflow => write!(f, "Popped from an empty stack."),
+            RuntimeErrorKind::StackOutOfBounds(offset, depth) => write!(
+                f,
+                "sp[-{}] is out of bounds, the stack only holds {} value(s).",
+                offset, depth
+            ),
+            RuntimeErrorKind::MemoryOutOfBounds(loc) => {
+                write!(f, "mem[{}] is out of bounds or has been freed.", loc)
+            }
+            RuntimeErrorKind::InvalidCast(from, to) => {
+                write!(f, "Cannot cast a {} to {}.", type_name(*from), type_name(*to))
+            }
+            RuntimeErrorKind::OperandTypeMismatch(expected, found) => write!(
+                f,
+                "Expected an operand of type {} but found a value of type {}.",
+                type_name(*expected),
+                type_name(*found)
+            ),
+            RuntimeErrorKind::UninitializedValue => write!(f, "Read from an uninitialized register."),
+            RuntimeErrorKind::UnexpectedString => write!(f, "Expected a primitive value but found a string."),
+            RuntimeErrorKind::UndefinedFunction(sym) => write!(
+                f,
+                "Call to the undefined function '{}'.",
+                sym
+            ),
+            RuntimeErrorKind::ArityMismatch(sym, expected, found) => write!(
+                f,
+                "'{}' takes {} argument(s) but was called with {}.",
+                sym,
+                expected,
+                found
+            ),
+            RuntimeErrorKind::ArgumentTypeMismatch(sym, i, expected, found) => write!(
+                f,
+                "Argument {} of '{}' should be a {} but is a {}.",
+                i,
+                sym,
+                type_name(*expected),
+                type_name(*found)
+            ),
+            RuntimeErrorKind::NativeError(sym, message) => write!(
+                f,
+                "Native function '{}' failed: {}",
+                sym,
+                message
+            ),
+            RuntimeErrorKind::Output(message) => write!(f, "Unable to write output: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeErrorKind {}
+
+// A call frame in a runtime error's stack trace, the instruction is the one the
+// frame was executing (i.e. the CALL for every frame but the innermost)
+#[derive(Debug)]
+pub struct StackFrame {
+    pub chunk: Chunk,
+    pub offset: Offset,
+    pub line: Option<Line>,
+}
+
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub trace: Vec<StackFrame>,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "Runtime error. {}", self.kind)?;
+        for frame in &self.trace {
+            match frame.line {
+                Some(line) => write!(f, "\n    at {} (line {})", frame.chunk, line)?,
+                None => write!(f, "\n    at {} ({:04})", frame.chunk, frame.offset)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RuntimeError {}
@@ -0,0 +1,541 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use pineapple_codegen_ssa::analysis::basic_block::{BlockEntry, BlockExit};
+use pineapple_codegen_ssa::analysis::cfg::CFG;
+use pineapple_error::InterpretError;
+use pineapple_ir::mir::{Expr, Label, Oper, Stmt};
+use pineapple_ir::op::{BinOp, RelOp};
+use pineapple_ir::value::ValueContainer;
+use pineapple_ir::{NoneTy, Value, ValueTy, ValueWrapper};
+use pineapple_session::{Interner, NativeValue, Natives};
+use std::cmp::Ordering;
+
+type Interned = usize;
+type BlockIndex = usize;
+type Env = HashMap<Oper, Value>;
+
+// What the driver (linear or cfg) should do after a statement has been executed.
+// Everything that doesn't move control around is handled directly by exec().
+enum Control {
+    Next,
+    Jump(Label),
+    Call(Interned, usize),
+    Return(Value),
+}
+
+// Every frame gets its own environment, registers and stack locations included. The
+// VM shares registers between frames, so this is (on purpose) the "obviously correct"
+// version of the semantics that the bytecode is supposed to implement.
+pub struct Interpreter<'a> {
+    stack: Vec<Value>,
+    ret: Value,
+    natives: &'a Natives,
+    // Strings are interned here as they're made (unlike in the VM), so this
+    // should be a copy of the program's interner that's dropped after the run
+    interner: &'a mut Interner,
+    out: &'a mut dyn Write,
+}
+
+struct LinearFrame {
+    function: usize,
+    pc: usize,
+    env: Env,
+}
+
+struct BlockFrame {
+    cfg: usize,
+    block: BlockIndex,
+    statement: usize,
+    env: Env,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(natives: &'a Natives, interner: &'a mut Interner, out: &'a mut dyn Write) -> Self {
+        Interpreter {
+            stack: vec![],
+            ret: Value::from(NoneTy::None),
+            natives,
+            interner,
+            out,
+        }
+    }
+
+    pub fn run_linear_code(&mut self, functions: &[Vec<Stmt>]) -> Result<(), InterpretError> {
+        let mut entries: HashMap<Interned, usize> = HashMap::new();
+        let mut labels: Vec<HashMap<Label, usize>> = vec![];
+
+        for (f, function) in functions.iter().enumerate() {
+            let mut function_labels = HashMap::new();
+            for (pc, stmt) in function.iter().enumerate() {
+                if let Stmt::Label(label) = stmt {
+                    function_labels.insert(*label, pc);
+                    if let Label::Named(sym) = label {
+                        entries.insert(*sym, f);
+                    }
+                }
+            }
+            labels.push(function_labels);
+        }
+
+        let main = match self.interner.lookup("main").and_then(|main| entries.get(&main)) {
+            Some(main) => *main,
+            None => return Err(InterpretError::MissingMain),
+        };
+        let mut frames = vec![LinearFrame {
+            function: main,
+            pc: 0,
+            env: Env::new(),
+        }];
+
+        while let Some(frame) = frames.last_mut() {
+            let control = match functions[frame.function].get(frame.pc) {
+                Some(stmt) => {
+                    frame.pc += 1;
+                    self.exec(&mut frame.env, stmt)?
+                }
+                None => Control::Return(Value::from(NoneTy::None)),
+            };
+
+            match control {
+                Control::Next => (),
+                Control::Jump(label) => match labels[frame.function].get(&label) {
+                    Some(pc) => frame.pc = *pc,
+                    None => return Err(InterpretError::UndefinedLabel(label)),
+                },
+                Control::Call(sym, arity) => match entries.get(&sym) {
+                    Some(function) => frames.push(LinearFrame {
+                        function: *function,
+                        pc: 0,
+                        env: Env::new(),
+                    }),
+                    None => self.call_native(sym, arity)?,
+                },
+                Control::Return(value) => {
+                    frames.pop();
+                    self.ret = value;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn run_cfgs(&mut self, cfgs: &[CFG]) -> Result<(), InterpretError> {
+        let mut entries: HashMap<Interned, usize> = HashMap::new();
+        let mut labels: Vec<HashMap<Label, BlockIndex>> = vec![];
+
+        for (c, cfg) in cfgs.iter().enumerate() {
+            if let Label::Named(sym) = cfg.entry_label {
+                entries.insert(sym, c);
+            }
+
+            let mut cfg_labels = HashMap::new();
+            for (b, block) in cfg.blocks.iter().enumerate() {
+                if let BlockEntry::Entry(stmt) = &block.entry {
+                    if let Stmt::Label(label) = &*stmt.borrow() {
+                        cfg_labels.insert(*label, b);
+                    }
+                }
+            }
+            labels.push(cfg_labels);
+        }
+
+        let main = match self.interner.lookup("main").and_then(|main| entries.get(&main)) {
+            Some(main) => *main,
+            None => return Err(InterpretError::MissingMain),
+        };
+        let mut frames = vec![BlockFrame {
+            cfg: main,
+            block: 0,
+            statement: 0,
+            env: Env::new(),
+        }];
+
+        while let Some(frame) = frames.last_mut() {
+            let cfg = &cfgs[frame.cfg];
+            let block = match cfg.blocks.get(frame.block) {
+                Some(block) => block,
+                None => {
+                    // Falling off the end of the function without a return
+                    frames.pop();
+                    self.ret = Value::from(NoneTy::None);
+                    continue;
+                }
+            };
+
+            let control = if frame.statement < block.statements.len() {
+                let stmt = &block.statements[frame.statement];
+                frame.statement += 1;
+                self.exec(&mut frame.env, &*stmt.borrow())?
+            } else if frame.statement == block.statements.len() {
+                frame.statement += 1;
+                match &block.exit {
+                    BlockExit::Exit(stmt) => self.exec(&mut frame.env, &*stmt.borrow())?,
+                    BlockExit::None => Control::Next,
+                }
+            } else {
+                // The exit didn't jump anywhere, so we fall through to the next block
+                let next = frame.block + 1;
+                self.enter_block(cfg, frame, next)?;
+                continue;
+            };
+
+            match control {
+                Control::Next => (),
+                Control::Jump(label) => match labels[frame.cfg].get(&label) {
+                    Some(target) => self.enter_block(cfg, frame, *target)?,
+                    None => return Err(InterpretError::UndefinedLabel(label)),
+                },
+                Control::Call(sym, arity) => match entries.get(&sym) {
+                    Some(callee) => frames.push(BlockFrame {
+                        cfg: *callee,
+                        block: 0,
+                        statement: 0,
+                        env: Env::new(),
+                    }),
+                    None => self.call_native(sym, arity)?,
+                },
+                Control::Return(value) => {
+                    frames.pop();
+                    self.ret = value;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Phis at the top of the target block are all evaluated "at once" on the edge we
+    // came in on, so every arg is read before any of the phis are written.
+    fn enter_block(
+        &mut self,
+        cfg: &CFG,
+        frame: &mut BlockFrame,
+        target: BlockIndex,
+    ) -> Result<(), InterpretError> {
+        let pred = frame.block;
+        frame.block = target;
+        frame.statement = 0;
+
+        let block = match cfg.blocks.get(target) {
+            Some(block) => block,
+            None => return Ok(()),
+        };
+
+        let mut writes: Vec<(Oper, Value)> = vec![];
+        for stmt in &block.statements {
+            if let Stmt::Tac(lval, Expr::Phi(args)) = &*stmt.borrow() {
+                let arg = match args.iter().find(|(_, b)| *b == pred) {
+                    Some((arg, _)) => arg,
+                    None => return Err(InterpretError::MissingPhiArgument(*lval, pred)),
+                };
+                writes.push((*lval, self.load(&frame.env, arg)?));
+                frame.statement += 1;
+            } else {
+                break;
+            }
+        }
+
+        for (lval, value) in writes {
+            frame.env.insert(lval, value);
+        }
+        Ok(())
+    }
+
+    fn exec(&mut self, env: &mut Env, stmt: &Stmt) -> Result<Control, InterpretError> {
+        match stmt {
+            Stmt::Tac(lval, rval) => {
+                let value = self.eval(env, rval)?;
+                env.insert(*lval, value);
+                Ok(Control::Next)
+            }
+            Stmt::Label(_) | Stmt::Line(_) => Ok(Control::Next),
+            Stmt::Jump(label) => Ok(Control::Jump(*label)),
+            Stmt::CJump(cond, label) => match self.eval(env, cond)?.into_inner() {
+                ValueWrapper::BOOL(true) => Ok(Control::Jump(*label)),
+                ValueWrapper::BOOL(false) => Ok(Control::Next),
+                _ => Err(InterpretError::ExpectedBool(self.eval(env, cond)?)),
+            },
+            Stmt::CastAs(oper, ty) => {
+                let mut value = self.load(env, oper)?;
+                if value.try_explicit_cast(*ty).is_err() {
+                    return Err(InterpretError::InvalidCast(value, *ty));
+                }
+                env.insert(*oper, value);
+                Ok(Control::Next)
+            }
+            Stmt::Call(sym, arity) => Ok(Control::Call(*sym, *arity)),
+            Stmt::StackPush(oper) => {
+                let value = self.load(env, oper)?;
+                self.stack.push(value);
+                Ok(Control::Next)
+            }
+            Stmt::Return(Some(oper)) => Ok(Control::Return(self.load(env, oper)?)),
+            Stmt::Return(None) => Ok(Control::Return(Value::from(NoneTy::None))),
+            Stmt::ParallelCopy(copies) => {
+                let mut writes: Vec<(Oper, Value)> = vec![];
+                for copy in copies {
+                    if let Stmt::Tac(lval, rval) = &*copy.borrow() {
+                        writes.push((*lval, self.eval(env, rval)?));
+                    }
+                }
+                for (lval, value) in writes {
+                    env.insert(lval, value);
+                }
+                Ok(Control::Next)
+            }
+        }
+    }
+
+    // Functions of the program shadow natives, so this is only reached when
+    // there's no function with the name. Args in the order they were pushed
+    fn call_native(&mut self, sym: Interned, arity: usize) -> Result<(), InterpretError> {
+        let name = self.interner.get_string(sym);
+        let native = match self.natives.lookup(&name) {
+            Some(index) => self.natives.get(index),
+            None => return Err(InterpretError::UndefinedFunction(name)),
+        };
+
+        if self.stack.len() < arity {
+            return Err(InterpretError::StackUnderflow);
+        }
+        let args: Vec<NativeValue> = self
+            .stack
+            .split_off(self.stack.len() - arity)
+            .iter()
+            .map(|arg| match arg.into_inner() {
+                ValueWrapper::STR(sym) => NativeValue::Str(self.interner.get_string(sym)),
+                value => NativeValue::Value(value),
+            })
+            .collect();
+
+        match (native.function)(&args, &mut *self.out) {
+            Ok(NativeValue::Value(ValueWrapper::NONE(_))) => Ok(()),
+            Ok(NativeValue::Value(value)) => {
+                self.ret = Value::from(value);
+                Ok(())
+            }
+            Ok(NativeValue::Str(s)) => {
+                self.ret = Value::from(ValueWrapper::STR(self.interner.intern(&s)));
+                Ok(())
+            }
+            Err(message) => Err(InterpretError::InvalidOperation(format!(
+                "Native function '{}' failed: {}",
+                name, message
+            ))),
+        }
+    }
+
+    fn load(&mut self, env: &Env, oper: &Oper) -> Result<Value, InterpretError> {
+        match oper {
+            Oper::Value(value) => Ok(*value),
+            Oper::StackPop => match self.stack.pop() {
+                Some(value) => Ok(value),
+                None => Err(InterpretError::StackUnderflow),
+            },
+            Oper::ReturnValue => Ok(self.ret),
+            _ => match env.get(oper) {
+                Some(value) => Ok(*value),
+                None => Err(InterpretError::UndefinedOperand(*oper)),
+            },
+        }
+    }
+
+    fn eval(&mut self, env: &Env, expr: &Expr) -> Result<Value, InterpretError> {
+        match expr {
+            Expr::Oper(oper) => self.load(env, oper),
+            Expr::Binary(l, op, r, _) => {
+                let l = self.load(env, l)?;
+                let r = self.load(env, r)?;
+                binary(self.interner, l, *op, r)
+            }
+            Expr::Logical(l, op, r, _) => {
+                let l = self.load(env, l)?;
+                let r = self.load(env, r)?;
+                logical(self.interner, l, *op, r)
+            }
+            Expr::Phi(_) => Err(InterpretError::InvalidOperation(
+                "Φ outside of the top of a block".to_string(),
+            )),
+        }
+    }
+}
+
+fn is_numeric(ty: ValueTy) -> bool {
+    !matches!(ty, ValueTy::BOOL | ValueTy::STR | ValueTy::NONE)
+}
+
+fn is_float(ty: ValueTy) -> bool {
+    matches!(ty, ValueTy::F32 | ValueTy::F64)
+}
+
+fn is_zero(value: ValueWrapper) -> bool {
+    match value {
+        ValueWrapper::I8(v) => v == 0,
+        ValueWrapper::I16(v) => v == 0,
+        ValueWrapper::I32(v) => v == 0,
+        ValueWrapper::I64(v) => v == 0,
+        ValueWrapper::U8(v) => v == 0,
+        ValueWrapper::U16(v) => v == 0,
+        ValueWrapper::U32(v) => v == 0,
+        ValueWrapper::U64(v) => v == 0,
+        _ => false,
+    }
+}
+
+fn integer(value: ValueWrapper) -> Option<i128> {
+    match value {
+        ValueWrapper::I8(v) => Some(v as i128),
+        ValueWrapper::I16(v) => Some(v as i128),
+        ValueWrapper::I32(v) => Some(v as i128),
+        ValueWrapper::I64(v) => Some(v as i128),
+        ValueWrapper::U8(v) => Some(v as i128),
+        ValueWrapper::U16(v) => Some(v as i128),
+        ValueWrapper::U32(v) => Some(v as i128),
+        ValueWrapper::U64(v) => Some(v as i128),
+        _ => None,
+    }
+}
+
+// Wrapping around in the operands' type is the same as working in u128 and
+// truncating the result, so integers don't need a version per type
+fn power(base: ValueWrapper, exponent: ValueWrapper) -> Result<ValueWrapper, InterpretError> {
+    match (base, exponent) {
+        (ValueWrapper::F64(b), ValueWrapper::F64(e)) => return Ok(ValueWrapper::F64(b.powf(e))),
+        (ValueWrapper::F32(b), ValueWrapper::F32(e)) => return Ok(ValueWrapper::F32(b.powf(e))),
+        _ => (),
+    }
+
+    let (b, e) = match (integer(base), integer(exponent)) {
+        (Some(b), Some(e)) => (b, e),
+        _ => {
+            return Err(InterpretError::InvalidOperation(format!(
+                "{:?} ^ {:?}",
+                base, exponent
+            )))
+        }
+    };
+    if e < 0 {
+        return Err(InterpretError::NegativeExponent);
+    }
+
+    let (mut b, mut e, mut result) = (b as u128, e as u128, 1u128);
+    while e > 0 {
+        if e % 2 == 1 {
+            result = result.wrapping_mul(b);
+        }
+        b = b.wrapping_mul(b);
+        e /= 2;
+    }
+
+    Ok(truncate(base, result))
+}
+
+// The same goes for +, -, * (and / and % of i32::MIN by -1), the VM wraps around
+// in every build so the interpreter can't use the operators of Value, which
+// panic on overflow in a debug build
+fn integer_arithmetic(l: ValueWrapper, op: BinOp, r: ValueWrapper) -> Option<ValueWrapper> {
+    let (a, b) = (integer(l)?, integer(r)?);
+    let result = match op {
+        BinOp::Plus => a.wrapping_add(b),
+        BinOp::Minus => a.wrapping_sub(b),
+        BinOp::Star => a.wrapping_mul(b),
+        BinOp::Slash => a / b,
+        BinOp::Modulo => a % b,
+        _ => return None,
+    };
+    Some(truncate(l, result as u128))
+}
+
+// The low bits of the result as a value of the same type as `like`
+fn truncate(like: ValueWrapper, result: u128) -> ValueWrapper {
+    match like {
+        ValueWrapper::I8(_) => ValueWrapper::I8(result as i8),
+        ValueWrapper::I16(_) => ValueWrapper::I16(result as i16),
+        ValueWrapper::I32(_) => ValueWrapper::I32(result as i32),
+        ValueWrapper::I64(_) => ValueWrapper::I64(result as i64),
+        ValueWrapper::U8(_) => ValueWrapper::U8(result as u8),
+        ValueWrapper::U16(_) => ValueWrapper::U16(result as u16),
+        ValueWrapper::U32(_) => ValueWrapper::U32(result as u32),
+        _ => ValueWrapper::U64(result as u64),
+    }
+}
+
+fn binary(interner: &mut Interner, l: Value, op: BinOp, r: Value) -> Result<Value, InterpretError> {
+    let invalid = || {
+        InterpretError::InvalidOperation(format!(
+            "{:?} {:?} {:?}",
+            l.into_inner(),
+            op,
+            r.into_inner()
+        ))
+    };
+
+    match (op, l.into_inner(), r.into_inner()) {
+        (BinOp::And, ValueWrapper::BOOL(a), ValueWrapper::BOOL(b)) => return Ok(Value::from(a && b)),
+        (BinOp::Or, ValueWrapper::BOOL(a), ValueWrapper::BOOL(b)) => return Ok(Value::from(a || b)),
+        (BinOp::And, _, _) | (BinOp::Or, _, _) => return Err(invalid()),
+        // Strings stay interned here, unlike in the VM
+        (BinOp::Plus, ValueWrapper::STR(a), ValueWrapper::STR(b)) => {
+            let s = [interner.get(a), interner.get(b)].concat();
+            return Ok(Value::from(ValueWrapper::STR(interner.intern(&s))));
+        }
+        _ => (),
+    }
+
+    let ty = l.fetch_ty();
+    if ty != r.fetch_ty() || !is_numeric(ty) {
+        return Err(invalid());
+    }
+
+    match op {
+        BinOp::Slash | BinOp::Modulo if is_zero(r.into_inner()) => {
+            Err(InterpretError::DivisionByZero)
+        }
+        BinOp::Carat => power(l.into_inner(), r.into_inner()).map(Value::from),
+        _ if !is_float(ty) => integer_arithmetic(l.into_inner(), op, r.into_inner())
+            .map(Value::from)
+            .ok_or_else(invalid),
+        BinOp::Plus => Ok(l + r),
+        BinOp::Minus => Ok(l - r),
+        BinOp::Star => Ok(l * r),
+        BinOp::Slash => Ok(l / r),
+        BinOp::Modulo => Ok(l % r),
+        _ => Err(invalid()),
+    }
+}
+
+fn logical(interner: &Interner, l: Value, op: RelOp, r: Value) -> Result<Value, InterpretError> {
+    if l.fetch_ty() != r.fetch_ty() {
+        return Err(InterpretError::InvalidOperation(format!(
+            "{:?} {:?} {:?}",
+            l.into_inner(),
+            op,
+            r.into_inner()
+        )));
+    }
+
+    let (a, b) = (l.into_inner(), r.into_inner());
+    if let (ValueWrapper::STR(a), ValueWrapper::STR(b)) = (a, b) {
+        let ordering = interner.get(a).cmp(interner.get(b));
+        let res = match op {
+            RelOp::EqualEqual => ordering == Ordering::Equal,
+            RelOp::NotEqual => ordering != Ordering::Equal,
+            RelOp::Less => ordering == Ordering::Less,
+            RelOp::LessEqual => ordering != Ordering::Greater,
+            RelOp::Greater => ordering == Ordering::Greater,
+            RelOp::GreaterEqual => ordering != Ordering::Less,
+        };
+        return Ok(Value::from(res));
+    }
+    let res = match op {
+        RelOp::EqualEqual => a == b,
+        RelOp::NotEqual => a != b,
+        RelOp::Less => a < b,
+        RelOp::LessEqual => a <= b,
+        RelOp::Greater => a > b,
+        RelOp::GreaterEqual => a >= b,
+    };
+    Ok(Value::from(res))
+}
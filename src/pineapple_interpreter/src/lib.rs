@@ -0,0 +1,26 @@
+use crate::interpreter::Interpreter;
+use pineapple_codegen_ssa::analysis::cfg::CFG;
+use pineapple_error::InterpretError;
+use pineapple_ir::mir::Stmt;
+use pineapple_session::{Interner, Natives};
+use std::io::Write;
+
+mod interpreter;
+
+pub fn interpret_linear_code(
+    linear_code: &[Vec<Stmt>],
+    natives: &Natives,
+    interner: &mut Interner,
+    out: &mut dyn Write,
+) -> Result<(), InterpretError> {
+    Interpreter::new(natives, interner, out).run_linear_code(linear_code)
+}
+
+pub fn interpret_cfgs(
+    cfgs: &[CFG],
+    natives: &Natives,
+    interner: &mut Interner,
+    out: &mut dyn Write,
+) -> Result<(), InterpretError> {
+    Interpreter::new(natives, interner, out).run_cfgs(cfgs)
+}
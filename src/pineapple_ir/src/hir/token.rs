@@ -34,8 +34,16 @@ pub enum TokenKind {
     // Literals.
     Ident(Identifier),
     StrLit(InternIndex),
+    // A string literal with `{expr}`s in it, see StrPart
+    InterpolatedStr(Vec<StrPart>),
     IntLit(Value),
     FloatLit(Value),
+    // A number with a type suffix (i.e. 255u8), which is the type it keeps. One
+    // with a signed suffix can be one past the type's max (128i8) for a `-` to go in
+    // front of, it wraps around to the min and the parser checks it's negated
+    TypedLit(Value),
+    // The text of a `///` comment, without the slashes
+    DocComment(String),
 
     // Keywords.
     Class,
@@ -78,7 +86,15 @@ pub enum TokenKind {
     StringTy,
 }
 
-#[derive(Clone)]
+// The pieces of an interpolated string in the order they appear, an embedded
+// expression comes with its own tokens (ending in an Eof)
+#[derive(Debug, PartialEq, Clone)]
+pub enum StrPart {
+    Lit(InternIndex),
+    Expr(Vec<Token>),
+}
+
+#[derive(Clone, PartialEq)]
 pub struct Token {
     pub kind: TokenKind,
 
@@ -43,7 +43,7 @@ ops! {
         Sub, sub, -: [F64, F32, I8, I16, I32, I64, U8, U16, U32, U64],
         Mul, mul, *: [F64, F32, I8, I16, I32, I64, U8, U16, U32, U64],
         Div, div, /: [F64, F32, I8, I16, I32, I64, U8, U16, U32, U64],
-        Rem, rem, %: [I8, I16, I32, I64, U8, U16, U32, U64],
+        Rem, rem, %: [F64, F32, I8, I16, I32, I64, U8, U16, U32, U64],
     }
 }
 
@@ -65,6 +65,40 @@ implicit_cast_rules! {
     }
 }
 
+impl ValueWrapper {
+    // Whether an implicit cast to `ty` keeps the value the same, which is what
+    // literals are checked against, i.e. 300 doesn't fit in a u8 and -1 doesn't fit
+    // in any unsigned type. A float only has to stay finite
+    pub fn fits(self, ty: ValueTy) -> bool {
+        let int = match self {
+            ValueWrapper::I8(v) => v as i128,
+            ValueWrapper::I16(v) => v as i128,
+            ValueWrapper::I32(v) => v as i128,
+            ValueWrapper::I64(v) => v as i128,
+            ValueWrapper::U8(v) => v as i128,
+            ValueWrapper::U16(v) => v as i128,
+            ValueWrapper::U32(v) => v as i128,
+            ValueWrapper::U64(v) => v as i128,
+            ValueWrapper::F64(v) => return v.is_finite() && (ty != ValueTy::F32 || (v as f32).is_finite()),
+            ValueWrapper::F32(v) => return v.is_finite(),
+            _ => return true,
+        };
+
+        let (min, max) = match ty {
+            ValueTy::I8 => (i8::MIN as i128, i8::MAX as i128),
+            ValueTy::I16 => (i16::MIN as i128, i16::MAX as i128),
+            ValueTy::I32 => (i32::MIN as i128, i32::MAX as i128),
+            ValueTy::I64 => (i64::MIN as i128, i64::MAX as i128),
+            ValueTy::U8 => (0, u8::MAX as i128),
+            ValueTy::U16 => (0, u16::MAX as i128),
+            ValueTy::U32 => (0, u32::MAX as i128),
+            ValueTy::U64 => (0, u64::MAX as i128),
+            _ => return true,
+        };
+        min <= int && int <= max
+    }
+}
+
 explicit_cast_rules! {
     pub struct Value, pub enum ValueWrapper, pub enum ValueTy {
         F64:  [F64(f64), F32(f32), I8(i8), I16(i16), I32(i32), I64(i64), U8(u8), U16(u16), U32(u32), U64(u64)],
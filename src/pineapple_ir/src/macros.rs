@@ -113,6 +113,16 @@ macro_rules! types {
              }
         }
 
+        impl $enum {
+            pub fn fetch_ty(&self) -> $enum2 {
+                match self {
+                    $(
+                        $enum::$variant(_) => $enum2::$variant,
+                    )+
+                }
+            }
+        }
+
         impl ValueInner for $enum {
             fn into_tagged_box(self) -> ValueBox<Self> {
                 #[doc(hidden)]
@@ -298,19 +308,21 @@ macro_rules! explicit_cast_rules {
                 }
 
             }
+        }
 
-            pub fn explicit_cast_unchecked(self, cast_ty: $enum2) -> Self {
-                match (self.into_inner(), cast_ty) {
+        // Same as above, without boxing the result (i.e. for the VM's registers)
+        impl $enum {
+            pub fn try_explicit_cast(self, cast_ty: $enum2) -> Result<Self, ()> {
+                match (self, cast_ty) {
                     $(
                         $(
                             ($enum::$variant_from(t), $enum2::$variant_to) => {
-                                $struct::from(t as $ty)
+                                Ok($enum::$variant_to(t as $ty))
                             }
                         )*
                     )+
-                    _ => panic!("")
+                    _ => Err(())
                 }
-
             }
         }
     };
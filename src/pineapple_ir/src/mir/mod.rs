@@ -4,11 +4,18 @@ use crate::op::{BinOp, RelOp};
 use crate::Value;
 use crate::ValueTy;
 
+mod parser;
+mod printer;
+
+pub use parser::{parse_mir, parse_type, parse_value, MirParseError};
+pub use printer::{print_expr, print_label, print_mir, print_oper, print_stmt, print_value, type_name};
+
 type BlockIndex = usize;
 type Interned = usize;
 type Arity = usize;
 type Sym = usize;
 type Version = usize;
+type Line = usize;
 
 type StatementIndex = usize;
 type Statement = Rc<RefCell<Stmt>>;
@@ -34,6 +41,10 @@ pub enum Stmt {
 
     Return(Option<Oper>),
 
+    // Source line of the statements that follow, only there for the debug line
+    // table, it doesn't do anything
+    Line(Line),
+
     //  Special pseudo-instruction for SSA destruction
     //  See SSA-Book p. 36z
     ParallelCopy(Vec<Statement>),
@@ -134,14 +145,16 @@ impl std::fmt::Debug for Stmt {
             Stmt::StackPush(rval) => write!(f, "_push {:?}", rval),
             Stmt::Call(sym, arity) => write!(f, "call {}({})", sym, arity),
             Stmt::Return(oper) => write!(f, "ret {:?}", oper),
+            Stmt::Line(line) => write!(f, "line {}", line),
         }
     }
 }
 
 #[derive(Clone, PartialEq)]
 pub enum Expr {
-    Binary(Oper, BinOp, Oper),
-    Logical(Oper, RelOp, Oper),
+    // Both operands have the trailing type, it's what picks the instruction
+    Binary(Oper, BinOp, Oper, ValueTy),
+    Logical(Oper, RelOp, Oper, ValueTy),
     Oper(Oper),
     Phi(Vec<(Oper, BlockIndex)>),
 }
@@ -153,7 +166,7 @@ impl Expr {
                 Oper::SSA(_) => vec![*o],
                 _ => vec![],
             },
-            Expr::Binary(l, _, r) => [
+            Expr::Binary(l, _, r, _) => [
                 match *l {
                     Oper::SSA(_) => vec![*l],
                     _ => vec![],
@@ -164,7 +177,7 @@ impl Expr {
                 },
             ]
             .concat(),
-            Expr::Logical(l, _, r) => [
+            Expr::Logical(l, _, r, _) => [
                 match *l {
                     Oper::SSA(_) => vec![*l],
                     _ => vec![],
@@ -178,7 +191,7 @@ impl Expr {
             Expr::Phi(args) => {
                 let mut used: Vec<Oper> = vec![];
                 for arg in args {
-                    if let Oper::SSA(SSA::Var(_, _)) = arg.0 {
+                    if let Oper::SSA(_) = arg.0 {
                         used.push(arg.0);
                     }
                 }
@@ -190,11 +203,11 @@ impl Expr {
     pub fn replace_oper_with(&mut self, a: &Oper, b: &Oper) {
         match self {
             Expr::Oper(o) => o.replace_oper_with(a, b),
-            Expr::Binary(l, _, r) => {
+            Expr::Binary(l, _, r, _) => {
                 l.replace_oper_with(a, b);
                 r.replace_oper_with(a, b);
             }
-            Expr::Logical(l, _, r) => {
+            Expr::Logical(l, _, r, _) => {
                 l.replace_oper_with(a, b);
                 r.replace_oper_with(a, b);
             }
@@ -211,11 +224,11 @@ impl Expr {
     pub fn replace_with_ssa(&mut self, value: Oper, ssa: usize) {
         match self {
             Expr::Oper(o) => o.replace_with_ssa(value, ssa),
-            Expr::Binary(l, _, r) => {
+            Expr::Binary(l, _, r, _) => {
                 l.replace_with_ssa(value, ssa);
                 r.replace_with_ssa(value, ssa);
             }
-            Expr::Logical(l, _, r) => {
+            Expr::Logical(l, _, r, _) => {
                 l.replace_with_ssa(value, ssa);
                 r.replace_with_ssa(value, ssa);
             }
@@ -227,8 +240,8 @@ impl Expr {
 impl std::fmt::Debug for Expr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
         match self {
-            Expr::Binary(l, o, r) => write!(f, "{:?} {:?} {:?}", *l, o, *r),
-            Expr::Logical(l, o, r) => write!(f, "{:?} {:?} {:?}", *l, o, *r),
+            Expr::Binary(l, o, r, t) => write!(f, "{} {:?} {:?} {:?}", type_name(*t), *l, o, *r),
+            Expr::Logical(l, o, r, t) => write!(f, "{} {:?} {:?} {:?}", type_name(*t), *l, o, *r),
             Expr::Oper(o) => write!(f, "{:?}", *o),
             Expr::Phi(args) => {
                 write!(f, "Φ(")?;
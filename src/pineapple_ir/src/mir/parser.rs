@@ -0,0 +1,681 @@
+// Parser for the textual MIR format written by printer.rs.
+//
+// A file is a list of functions, each one starting at its named label. There is
+// one statement per line and `//` starts a comment that runs to the end of the line.
+//
+//  @fibo:
+//      line 1
+//      _v1.0 = pop
+//      line 2
+//      _t0.0 = i32 _v1.0 > 1i32
+//      if _t0.0 goto L0
+//  L1:
+//      line 3
+//      ret _v1.0
+//  L0:
+//      line 5
+//      _t1.0 = i32 _v1.0 - 1i32
+//      push _t1.0
+//      call @fibo/1
+//      _t2.0 = $rv
+//      ...
+//
+//  label   ::= "L" NUMBER | "@" NAME
+//  stmt    ::= label ":"
+//            | "goto" label
+//            | "if" expr "goto" label
+//            | "cast" oper "as" type
+//            | "call" "@" NAME "/" NUMBER
+//            | "push" oper
+//            | "ret" oper?
+//            | "line" NUMBER
+//            | "pcopy" "(" (oper "=" expr ("," oper "=" expr)*)? ")"
+//            | oper "=" expr
+//  expr    ::= "phi" "(" ("B" NUMBER ":" oper ("," "B" NUMBER ":" oper)*)? ")"
+//            | type oper (binop | relop) oper
+//            | oper
+//  oper    ::= "_v" NUMBER "." NUMBER | "_t" NUMBER "." NUMBER
+//            | "$" NUMBER | "$rv" | "sp[-" NUMBER "]" | "pop"
+//            | value
+//  value   ::= NUMBER type | "true" | "false" | "none" | STRING
+//  type    ::= "i8" | "i16" | "i32" | "i64" | "u8" | "u16" | "u32" | "u64"
+//            | "f32" | "f64" | "bool" | "string" | "none"
+use std::cell::RefCell;
+use std::fmt;
+use std::iter::Peekable;
+use std::rc::Rc;
+use std::str::Chars;
+
+use crate::mir::{Expr, Label, Oper, Stmt, SSA};
+use crate::op::{BinOp, RelOp};
+use crate::{NoneTy, Value, ValueTy};
+
+type Line = usize;
+type Intern<'a> = &'a mut dyn FnMut(&str) -> usize;
+
+#[derive(Debug)]
+pub enum MirParseError {
+    UnexpectedToken(Line, String, String),
+    UnexpectedEndOfLine(Line, String),
+    InvalidValue(Line, String),
+    InvalidType(Line, String),
+    MissingOperandType(Line),
+    StatementOutsideFunction(Line),
+}
+
+impl fmt::Display for MirParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MirParseError::UnexpectedToken(line, expected, found) => {
+                write!(f, "At line {}. Expected {} but instead found: `{}`.", line, expected, found)
+            }
+            MirParseError::UnexpectedEndOfLine(line, expected) => {
+                write!(f, "At line {}. Expected {} but the line ended.", line, expected)
+            }
+            MirParseError::InvalidValue(line, found) => {
+                write!(f, "At line {}. `{}` is not a valid value.", line, found)
+            }
+            MirParseError::InvalidType(line, found) => {
+                write!(f, "At line {}. `{}` is not a valid type.", line, found)
+            }
+            MirParseError::MissingOperandType(line) => {
+                write!(f, "At line {}. Expected the operand type in front of the expression (i.e. `i32 _v1.0 + 1i32`).", line)
+            }
+            MirParseError::StatementOutsideFunction(line) => {
+                write!(f, "At line {}. Statements must come after a function label (i.e. `@main:`).", line)
+            }
+        }
+    }
+}
+
+pub fn parse_mir(buf: &str, intern: Intern) -> Result<Vec<Vec<Stmt>>, MirParseError> {
+    let mut functions: Vec<Vec<Stmt>> = vec![];
+
+    for (index, line) in buf.lines().enumerate() {
+        let line_no = index + 1;
+        let mut cursor = Cursor::new(line, line_no);
+
+        cursor.skip_whitespace();
+        if cursor.at_end() {
+            continue;
+        }
+
+        let stmt = cursor.stmt(intern)?;
+        cursor.expect_end()?;
+
+        match stmt {
+            Stmt::Label(Label::Named(_)) => functions.push(vec![stmt]),
+            _ => match functions.last_mut() {
+                Some(function) => function.push(stmt),
+                None => return Err(MirParseError::StatementOutsideFunction(line_no)),
+            },
+        }
+    }
+
+    Ok(functions)
+}
+
+pub fn parse_type(name: &str) -> Option<ValueTy> {
+    match name {
+        "f64" => Some(ValueTy::F64),
+        "f32" => Some(ValueTy::F32),
+        "i8" => Some(ValueTy::I8),
+        "i16" => Some(ValueTy::I16),
+        "i32" => Some(ValueTy::I32),
+        "i64" => Some(ValueTy::I64),
+        "u8" => Some(ValueTy::U8),
+        "u16" => Some(ValueTy::U16),
+        "u32" => Some(ValueTy::U32),
+        "u64" => Some(ValueTy::U64),
+        "bool" => Some(ValueTy::BOOL),
+        "string" => Some(ValueTy::STR),
+        "none" => Some(ValueTy::NONE),
+        _ => None,
+    }
+}
+
+// Parses a single value literal (i.e. `5i32`, `"hi"`, `none`) on its own, for other
+// textual formats that want to write values the same way MIR does.
+pub fn parse_value(text: &str, intern: Intern) -> Option<Value> {
+    let mut cursor = Cursor::new(text, 0);
+    match cursor.oper(intern) {
+        Ok(Oper::Value(value)) if cursor.at_end() => Some(value),
+        _ => None,
+    }
+}
+
+#[derive(Clone)]
+struct Cursor<'a> {
+    it: Peekable<Chars<'a>>,
+    line: Line,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(buf: &'a str, line: Line) -> Self {
+        Cursor {
+            it: buf.chars().peekable(),
+            line,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(ch) = self.it.peek() {
+            if ch.is_whitespace() {
+                self.it.next();
+            } else {
+                break;
+            }
+        }
+
+        // Comments run until the end of the line, so we can just drop the rest
+        let mut lookahead = self.it.clone();
+        if lookahead.next() == Some('/') && lookahead.next() == Some('/') {
+            while self.it.next().is_some() {}
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_whitespace();
+        self.it.peek().is_none()
+    }
+
+    fn expect_end(&mut self) -> Result<(), MirParseError> {
+        if self.at_end() {
+            Ok(())
+        } else {
+            let rest: String = self.it.clone().collect();
+            Err(MirParseError::UnexpectedToken(
+                self.line,
+                "the end of the line".to_string(),
+                rest,
+            ))
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.it.peek().copied()
+    }
+
+    fn starts_with(&mut self, s: &str) -> bool {
+        self.skip_whitespace();
+        let mut lookahead = self.it.clone();
+        s.chars().all(|c| lookahead.next() == Some(c))
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        if self.starts_with(s) {
+            for _ in s.chars() {
+                self.it.next();
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), MirParseError> {
+        if self.eat(s) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("`{}`", s)))
+        }
+    }
+
+    fn unexpected(&mut self, expected: &str) -> MirParseError {
+        let found = self.word();
+        if found.is_empty() {
+            match self.it.peek() {
+                Some(c) => MirParseError::UnexpectedToken(self.line, expected.to_string(), c.to_string()),
+                None => MirParseError::UnexpectedEndOfLine(self.line, expected.to_string()),
+            }
+        } else {
+            MirParseError::UnexpectedToken(self.line, expected.to_string(), found)
+        }
+    }
+
+    // A "word" is anything made up of characters that can appear in names and literals
+    fn word(&mut self) -> String {
+        self.skip_whitespace();
+        let mut word = String::new();
+        while let Some(&c) = self.it.peek() {
+            if c.is_alphanumeric() || c == '_' || c == '.' {
+                word.push(c);
+                self.it.next();
+            } else {
+                break;
+            }
+        }
+        word
+    }
+
+    // Like word, but stops at dots so that `_v1.2` can be split up
+    fn number(&mut self) -> Result<usize, MirParseError> {
+        self.skip_whitespace();
+        let mut digits = String::new();
+        while let Some(&c) = self.it.peek() {
+            if c.is_ascii_digit() {
+                digits.push(c);
+                self.it.next();
+            } else {
+                break;
+            }
+        }
+
+        match digits.parse::<usize>() {
+            Ok(n) => Ok(n),
+            Err(_) => Err(self.unexpected("a number")),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, MirParseError> {
+        self.skip_whitespace();
+        let mut name = String::new();
+        while let Some(&c) = self.it.peek() {
+            if c.is_alphanumeric() || c == '_' {
+                name.push(c);
+                self.it.next();
+            } else {
+                break;
+            }
+        }
+
+        if name.is_empty() {
+            Err(self.unexpected("a name"))
+        } else {
+            Ok(name)
+        }
+    }
+
+    fn label(&mut self, intern: Intern) -> Result<Label, MirParseError> {
+        if self.eat("@") {
+            let name = self.name()?;
+            Ok(Label::Named(intern(&name)))
+        } else if self.eat("L") {
+            Ok(Label::Marker(self.number()?))
+        } else {
+            Err(self.unexpected("a label"))
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        // Make sure that we don't eat the front of a longer name, i.e. `pop` vs `popcount`
+        if !self.starts_with(keyword) {
+            return false;
+        }
+        let mut lookahead = self.it.clone();
+        for _ in keyword.chars() {
+            lookahead.next();
+        }
+        match lookahead.next() {
+            Some(c) if c.is_alphanumeric() || c == '_' => false,
+            _ => self.eat(keyword),
+        }
+    }
+
+    fn stmt(&mut self, intern: Intern) -> Result<Stmt, MirParseError> {
+        if self.starts_with("@") || self.starts_with_marker() {
+            let label = self.label(intern)?;
+            self.expect(":")?;
+            Ok(Stmt::Label(label))
+        } else if self.keyword("goto") {
+            Ok(Stmt::Jump(self.label(intern)?))
+        } else if self.keyword("if") {
+            let cond = self.expr(intern)?;
+            if !self.keyword("goto") {
+                return Err(self.unexpected("`goto`"));
+            }
+            Ok(Stmt::CJump(cond, self.label(intern)?))
+        } else if self.keyword("cast") {
+            let oper = self.oper(intern)?;
+            if !self.keyword("as") {
+                return Err(self.unexpected("`as`"));
+            }
+            let name = self.name()?;
+            match parse_type(&name) {
+                Some(ty) => Ok(Stmt::CastAs(oper, ty)),
+                None => Err(MirParseError::InvalidType(self.line, name)),
+            }
+        } else if self.keyword("call") {
+            self.expect("@")?;
+            let name = self.name()?;
+            self.expect("/")?;
+            let arity = self.number()?;
+            Ok(Stmt::Call(intern(&name), arity))
+        } else if self.keyword("push") {
+            Ok(Stmt::StackPush(self.oper(intern)?))
+        } else if self.keyword("ret") {
+            if self.at_end() {
+                Ok(Stmt::Return(None))
+            } else {
+                Ok(Stmt::Return(Some(self.oper(intern)?)))
+            }
+        } else if self.keyword("line") {
+            Ok(Stmt::Line(self.number()?))
+        } else if self.keyword("pcopy") {
+            self.expect("(")?;
+            let mut copies = vec![];
+            while !self.eat(")") {
+                if !copies.is_empty() {
+                    self.expect(",")?;
+                }
+                let lval = self.oper(intern)?;
+                self.expect("=")?;
+                let rval = self.expr(intern)?;
+                copies.push(Rc::new(RefCell::new(Stmt::Tac(lval, rval))));
+            }
+            Ok(Stmt::ParallelCopy(copies))
+        } else {
+            let lval = self.oper(intern)?;
+            self.expect("=")?;
+            Ok(Stmt::Tac(lval, self.expr(intern)?))
+        }
+    }
+
+    fn starts_with_marker(&mut self) -> bool {
+        self.skip_whitespace();
+        let mut lookahead = self.it.clone();
+        lookahead.next() == Some('L') && lookahead.next().map_or(false, |c| c.is_ascii_digit())
+    }
+
+    fn expr(&mut self, intern: Intern) -> Result<Expr, MirParseError> {
+        if self.keyword("phi") {
+            self.expect("(")?;
+            let mut args = vec![];
+            while !self.eat(")") {
+                if !args.is_empty() {
+                    self.expect(",")?;
+                }
+                self.expect("B")?;
+                let block = self.number()?;
+                self.expect(":")?;
+                args.push((self.oper(intern)?, block));
+            }
+            return Ok(Expr::Phi(args));
+        }
+
+        let ty = self.operand_ty()?;
+        let left = self.oper(intern)?;
+
+        // Two character operators need to be checked before their one character prefixes
+        let relops = [
+            ("==", RelOp::EqualEqual),
+            ("!=", RelOp::NotEqual),
+            ("<=", RelOp::LessEqual),
+            (">=", RelOp::GreaterEqual),
+            ("<", RelOp::Less),
+            (">", RelOp::Greater),
+        ];
+        for (sym, op) in relops.iter() {
+            if self.eat(sym) {
+                let ty = ty.ok_or(MirParseError::MissingOperandType(self.line))?;
+                return Ok(Expr::Logical(left, *op, self.oper(intern)?, ty));
+            }
+        }
+
+        let binops = [
+            ("&&", BinOp::And),
+            ("||", BinOp::Or),
+            ("+", BinOp::Plus),
+            ("-", BinOp::Minus),
+            ("*", BinOp::Star),
+            ("/", BinOp::Slash),
+            ("%", BinOp::Modulo),
+            ("^", BinOp::Carat),
+        ];
+        for (sym, op) in binops.iter() {
+            if self.eat(sym) {
+                let ty = ty.ok_or(MirParseError::MissingOperandType(self.line))?;
+                return Ok(Expr::Binary(left, *op, self.oper(intern)?, ty));
+            }
+        }
+
+        match ty {
+            Some(_) => Err(self.unexpected("an operator")),
+            None => Ok(Expr::Oper(left)),
+        }
+    }
+
+    // The operand type in front of a binary or logical expression. `none` is both a
+    // type and a value, so it's only taken as the type when an operand follows it
+    fn operand_ty(&mut self) -> Result<Option<ValueTy>, MirParseError> {
+        let mut lookahead = self.clone();
+
+        let ty = match lookahead.name().ok().as_deref().and_then(parse_type) {
+            Some(ty) => ty,
+            None => return Ok(None),
+        };
+        let operand_follows = match lookahead.peek() {
+            Some(c) => c.is_alphanumeric() || "_$\"".contains(c),
+            None => false,
+        };
+        if ty == ValueTy::NONE && !operand_follows {
+            return Ok(None);
+        }
+
+        self.it = lookahead.it;
+        Ok(Some(ty))
+    }
+
+    fn oper(&mut self, intern: Intern) -> Result<Oper, MirParseError> {
+        match self.peek() {
+            Some('$') => {
+                self.expect("$")?;
+                if self.eat("rv") {
+                    Ok(Oper::ReturnValue)
+                } else {
+                    Ok(Oper::Register(self.number()?))
+                }
+            }
+            Some('"') => self.string(intern),
+            Some('_') => {
+                let var = if self.eat("_v") {
+                    true
+                } else if self.eat("_t") {
+                    false
+                } else {
+                    return Err(self.unexpected("an operand"));
+                };
+                let sym = self.number()?;
+                self.expect(".")?;
+                let version = self.number()?;
+
+                if var {
+                    Ok(Oper::SSA(SSA::Var(sym, version)))
+                } else {
+                    Ok(Oper::SSA(SSA::Temp(sym, version)))
+                }
+            }
+            Some(_) => {
+                if self.eat("sp[-") {
+                    let offset = self.number()?;
+                    self.expect("]")?;
+                    Ok(Oper::StackLocation(offset))
+                } else if self.keyword("pop") {
+                    Ok(Oper::StackPop)
+                } else if self.keyword("true") {
+                    Ok(Oper::Value(Value::from(true)))
+                } else if self.keyword("false") {
+                    Ok(Oper::Value(Value::from(false)))
+                } else if self.keyword("none") {
+                    Ok(Oper::Value(Value::from(NoneTy::None)))
+                } else {
+                    self.numeric()
+                }
+            }
+            None => Err(MirParseError::UnexpectedEndOfLine(
+                self.line,
+                "an operand".to_string(),
+            )),
+        }
+    }
+
+    fn numeric(&mut self) -> Result<Oper, MirParseError> {
+        let negative = self.eat("-");
+
+        // Read everything up to the type suffix, exponents (1e-9) are the only place
+        // where a sign can show up inside of the literal
+        let mut literal = String::new();
+        if negative {
+            literal.push('-');
+        }
+        while let Some(&c) = self.it.peek() {
+            let exponent_sign = (c == '-' || c == '+') && literal.ends_with('e');
+            if c.is_ascii_digit() || c == '.' || c == 'e' || exponent_sign {
+                literal.push(c);
+                self.it.next();
+            } else {
+                break;
+            }
+        }
+
+        // inf and NaN don't start with a digit so they need a bit of special casing
+        for special in ["inf", "NaN"].iter() {
+            if literal.trim_start_matches('-').is_empty() && self.eat(special) {
+                literal.push_str(special);
+            }
+        }
+
+        let suffix = self.name().unwrap_or_default();
+
+        let value = match suffix.as_str() {
+            "f64" => literal.parse::<f64>().map(Value::from).ok(),
+            "f32" => literal.parse::<f32>().map(Value::from).ok(),
+            "i8" => literal.parse::<i8>().map(Value::from).ok(),
+            "i16" => literal.parse::<i16>().map(Value::from).ok(),
+            "i32" => literal.parse::<i32>().map(Value::from).ok(),
+            "i64" => literal.parse::<i64>().map(Value::from).ok(),
+            "u8" => literal.parse::<u8>().map(Value::from).ok(),
+            "u16" => literal.parse::<u16>().map(Value::from).ok(),
+            "u32" => literal.parse::<u32>().map(Value::from).ok(),
+            "u64" => literal.parse::<u64>().map(Value::from).ok(),
+            _ => None,
+        };
+
+        match value {
+            Some(value) => Ok(Oper::Value(value)),
+            None => Err(MirParseError::InvalidValue(
+                self.line,
+                format!("{}{}", literal, suffix),
+            )),
+        }
+    }
+
+    // Strings are written the same way Rust debug prints them, so we handle the same escapes
+    fn string(&mut self, intern: Intern) -> Result<Oper, MirParseError> {
+        self.expect("\"")?;
+
+        let mut s = String::new();
+        loop {
+            let c = match self.it.next() {
+                Some(c) => c,
+                None => {
+                    return Err(MirParseError::UnexpectedEndOfLine(
+                        self.line,
+                        "a closing `\"`".to_string(),
+                    ))
+                }
+            };
+
+            match c {
+                '"' => break,
+                '\\' => {
+                    let escaped = match self.it.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('0') => '\0',
+                        Some('\\') => '\\',
+                        Some('"') => '"',
+                        Some('\'') => '\'',
+                        Some('u') => {
+                            let mut hex = String::new();
+                            if self.it.next() != Some('{') {
+                                return Err(MirParseError::InvalidValue(self.line, s));
+                            }
+                            for c in &mut self.it {
+                                if c == '}' {
+                                    break;
+                                }
+                                hex.push(c);
+                            }
+                            match u32::from_str_radix(&hex, 16).ok().and_then(std::char::from_u32) {
+                                Some(c) => c,
+                                None => return Err(MirParseError::InvalidValue(self.line, hex)),
+                            }
+                        }
+                        _ => return Err(MirParseError::InvalidValue(self.line, s)),
+                    };
+                    s.push(escaped);
+                }
+                _ => s.push(c),
+            }
+        }
+
+        Ok(Oper::Value(Value::from(intern(&s))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mir::print_mir;
+
+    fn parse(text: &str, names: &mut Vec<String>) -> Result<Vec<Vec<Stmt>>, MirParseError> {
+        parse_mir(text, &mut |name| match names.iter().position(|n| n == name) {
+            Some(sym) => sym,
+            None => {
+                names.push(name.to_string());
+                names.len() - 1
+            }
+        })
+    }
+
+    #[test]
+    fn round_trip() {
+        let text = r#"@half:
+    line 1
+    _v1.0 = pop
+    line 2
+    _t0.0 = f64 _v1.0 / 2.0f64
+    ret _t0.0
+
+@main:
+    line 5
+    _v3.0 = -3i64
+    _v4.0 = "hi\n"
+    goto L0
+L0:
+    _v3.1 = phi(B0: _v3.0, B2: _v3.2)
+    _t1.0 = i64 _v3.1 >= 2i64
+    if _t1.0 goto L1
+L2:
+    _v3.2 = i64 _v3.1 + 1i64
+    goto L0
+L1:
+    _t3.0 = _v3.1
+    cast _t3.0 as u8
+    push -1.5f64
+    call @half/1
+    _t4.0 = $rv
+    push _t4.0
+    push _v4.0
+    push _t3.0
+    call @print/3
+    ret
+"#;
+        let mut names = vec![];
+        let functions = parse(text, &mut names).unwrap();
+        assert_eq!(print_mir(&functions, &|sym| names[sym].clone()), text);
+    }
+
+    #[test]
+    fn error_position() {
+        let text = "@main:\n    line 1\n    _v1.0 = i32 _v2.0 +\n    ret\n";
+        match parse(text, &mut vec![]) {
+            Err(MirParseError::UnexpectedEndOfLine(3, _)) => (),
+            Err(e) => panic!("expected the line to end early, found: {}", e),
+            Ok(_) => panic!("expected the line to end early"),
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use crate::mir::{Expr, Label, Oper, Stmt, SSA};
+use crate::value::ValueContainer;
+use crate::{Value, ValueTy, ValueWrapper};
+
+// Printer for the textual MIR format, see parser.rs for the grammar. Interned
+// symbols (function names, strings) live in pineapple_session, which depends on
+// this crate, so the caller hands us a way of resolving them.
+type NameOf<'a> = &'a dyn Fn(usize) -> String;
+
+pub fn print_mir(functions: &[Vec<Stmt>], name_of: NameOf) -> String {
+    let mut out = String::new();
+    for (i, function) in functions.iter().enumerate() {
+        if i != 0 {
+            out.push('\n');
+        }
+        for stmt in function {
+            out.push_str(&print_stmt(stmt, name_of));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+pub fn print_stmt(stmt: &Stmt, name_of: NameOf) -> String {
+    match stmt {
+        Stmt::Label(label) => format!("{}:", print_label(label, name_of)),
+        _ => format!("    {}", print_instruction(stmt, name_of)),
+    }
+}
+
+fn print_instruction(stmt: &Stmt, name_of: NameOf) -> String {
+    match stmt {
+        Stmt::Tac(lval, rval) => format!(
+            "{} = {}",
+            print_oper(lval, name_of),
+            print_expr(rval, name_of)
+        ),
+        Stmt::Label(label) => format!("{}:", print_label(label, name_of)),
+        Stmt::Jump(label) => format!("goto {}", print_label(label, name_of)),
+        Stmt::CJump(cond, label) => format!(
+            "if {} goto {}",
+            print_expr(cond, name_of),
+            print_label(label, name_of)
+        ),
+        Stmt::CastAs(oper, ty) => format!("cast {} as {}", print_oper(oper, name_of), type_name(*ty)),
+        Stmt::Call(sym, arity) => format!("call @{}/{}", name_of(*sym), arity),
+        Stmt::StackPush(oper) => format!("push {}", print_oper(oper, name_of)),
+        Stmt::Return(Some(oper)) => format!("ret {}", print_oper(oper, name_of)),
+        Stmt::Return(None) => "ret".to_string(),
+        Stmt::Line(line) => format!("line {}", line),
+        Stmt::ParallelCopy(copies) => {
+            let copies = copies
+                .iter()
+                .map(|copy| print_instruction(&*copy.borrow(), name_of))
+                .collect::<Vec<String>>()
+                .join(", ");
+            format!("pcopy({})", copies)
+        }
+    }
+}
+
+pub fn print_label(label: &Label, name_of: NameOf) -> String {
+    match label {
+        Label::Marker(marker) => format!("L{}", marker),
+        Label::Named(sym) => format!("@{}", name_of(*sym)),
+    }
+}
+
+pub fn print_expr(expr: &Expr, name_of: NameOf) -> String {
+    match expr {
+        Expr::Binary(l, op, r, ty) => format!(
+            "{} {} {:?} {}",
+            type_name(*ty),
+            print_oper(l, name_of),
+            op,
+            print_oper(r, name_of)
+        ),
+        Expr::Logical(l, op, r, ty) => format!(
+            "{} {} {:?} {}",
+            type_name(*ty),
+            print_oper(l, name_of),
+            op,
+            print_oper(r, name_of)
+        ),
+        Expr::Oper(oper) => print_oper(oper, name_of),
+        Expr::Phi(args) => {
+            let args = args
+                .iter()
+                .map(|(oper, block)| format!("B{}: {}", block, print_oper(oper, name_of)))
+                .collect::<Vec<String>>()
+                .join(", ");
+            format!("phi({})", args)
+        }
+    }
+}
+
+pub fn print_oper(oper: &Oper, name_of: NameOf) -> String {
+    match oper {
+        Oper::SSA(SSA::Var(sym, version)) => format!("_v{}.{}", sym, version),
+        Oper::SSA(SSA::Temp(sym, version)) => format!("_t{}.{}", sym, version),
+        Oper::Value(value) => print_value(value, name_of),
+        Oper::StackPop => "pop".to_string(),
+        Oper::Register(reg) => format!("${}", reg),
+        Oper::ReturnValue => "$rv".to_string(),
+        Oper::StackLocation(offset) => format!("sp[-{}]", offset),
+    }
+}
+
+// Every numeric literal carries its type as a suffix so that it reads back as the
+// exact same Value, i.e. 5i32, 255u8, 1.5f64.
+pub fn print_value(value: &Value, name_of: NameOf) -> String {
+    match value.into_inner() {
+        ValueWrapper::F64(v) => format!("{:?}f64", v),
+        ValueWrapper::F32(v) => format!("{:?}f32", v),
+        ValueWrapper::I8(v) => format!("{}i8", v),
+        ValueWrapper::I16(v) => format!("{}i16", v),
+        ValueWrapper::I32(v) => format!("{}i32", v),
+        ValueWrapper::I64(v) => format!("{}i64", v),
+        ValueWrapper::U8(v) => format!("{}u8", v),
+        ValueWrapper::U16(v) => format!("{}u16", v),
+        ValueWrapper::U32(v) => format!("{}u32", v),
+        ValueWrapper::U64(v) => format!("{}u64", v),
+        ValueWrapper::BOOL(v) => format!("{}", v),
+        ValueWrapper::STR(sym) => format!("{:?}", name_of(sym)),
+        ValueWrapper::NONE(_) => "none".to_string(),
+    }
+}
+
+pub fn type_name(ty: ValueTy) -> &'static str {
+    match ty {
+        ValueTy::F64 => "f64",
+        ValueTy::F32 => "f32",
+        ValueTy::I8 => "i8",
+        ValueTy::I16 => "i16",
+        ValueTy::I32 => "i32",
+        ValueTy::I64 => "i64",
+        ValueTy::U8 => "u8",
+        ValueTy::U16 => "u16",
+        ValueTy::U32 => "u32",
+        ValueTy::U64 => "u64",
+        ValueTy::BOOL => "bool",
+        ValueTy::STR => "string",
+        ValueTy::NONE => "none",
+    }
+}
@@ -1,22 +1,26 @@
+use std::convert::TryFrom;
 use std::iter::Peekable;
 use std::str::Chars;
 
 use pineapple_error::ScanError;
-use pineapple_ir::hir::token::{Token, TokenKind};
-use pineapple_ir::Value;
-use pineapple_session::intern_string;
+use pineapple_ir::hir::token::{StrPart, Token, TokenKind};
+use pineapple_ir::{Value, ValueTy, ValueWrapper};
+use pineapple_session::Interner;
 
 pub struct Lexer<'a> {
     it: Peekable<Chars<'a>>,
 
+    interner: &'a mut Interner,
+
     line: usize,
     column: usize,
 }
 
 impl<'a> Lexer<'a> {
-    pub fn new(buf: &'a str) -> Self {
+    pub fn new(buf: &'a str, interner: &'a mut Interner) -> Self {
         Lexer {
             it: buf.chars().peekable(),
+            interner,
             line: 1,
             column: 0,
         }
@@ -80,15 +84,26 @@ impl<'a> Lexer<'a> {
                 }
                 '/' => {
                     self.next()?;
-                    if *self.it.peek().unwrap() == '/' {
-                        while let Some(ch) = self.it.peek() {
-                            match ch {
-                                '\n' => break,
-                                _ => self.next()?,
-                            };
+                    let start_line = self.line;
+                    let start_col = self.column;
+
+                    match self.peek_nth(0) {
+                        // `///` is a doc comment, but `////` isn't
+                        Some('/') if self.peek_nth(1) == Some('/') && self.peek_nth(2) != Some('/') => {
+                            self.next()?;
+                            self.next()?;
+                            let text: String = self.consume_while(|c| c != '\n')?.into_iter().collect();
+                            let text = text.strip_prefix(' ').unwrap_or(&text).trim_end();
+                            return Ok(Some(TokenKind::DocComment(text.to_string())));
+                        }
+                        Some('/') => {
+                            self.consume_while(|c| c != '\n')?;
+                        }
+                        Some('*') => {
+                            self.next()?;
+                            self.block_comment(start_line, start_col)?;
                         }
-                    } else {
-                        return Ok(Some(TokenKind::Slash));
+                        _ => return Ok(Some(TokenKind::Slash)),
                     }
                 }
                 _ => break,
@@ -98,58 +113,375 @@ impl<'a> Lexer<'a> {
         Ok(None)
     }
 
+    // Block comments nest, so code that has one in it can still be commented out
+    fn block_comment(&mut self, start_line: usize, start_col: usize) -> Result<(), ScanError> {
+        let mut depth = 1;
+        while depth > 0 {
+            let ch = match self.next() {
+                Ok(ch) => ch,
+                Err(_) => return Err(ScanError::UnterminatedComment(start_line, start_col)),
+            };
+
+            match ch {
+                '/' if self.it.peek() == Some(&'*') => {
+                    self.next()?;
+                    depth += 1;
+                }
+                '*' if self.it.peek() == Some(&'/') => {
+                    self.next()?;
+                    depth -= 1;
+                }
+                '\n' => {
+                    self.line += 1;
+                    self.column = 0;
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /*
+        Numbers are decimal unless they start with 0x, 0o or 0b, and can have `_`s
+        between their digits. A decimal can have a fraction and/or an exponent
+        (1.5, 1e-9, 2.5E3), which makes it a float.
+
+        Without a suffix an integer is a u32 (a u64 if it doesn't fit) and a float
+        an f64, which the typechecker casts to whatever type is expected. With one
+        (255u8, 1.5f32, 1f64) the literal is of that type, and has to fit in it, or
+        in the case of a signed one be the magnitude of its min (128i8 for -128i8).
+    */
     fn number(&mut self, x: char) -> Result<TokenKind, ScanError> {
         let start_line = self.line;
         let start_col = self.column;
 
-        let mut result = String::new();
-        result.push(x);
+        let radix = match (x, self.it.peek()) {
+            ('0', Some('x')) => 16,
+            ('0', Some('o')) => 8,
+            ('0', Some('b')) => 2,
+            _ => 10,
+        };
 
-        let integer: String = self
-            .consume_while(|c| c.is_numeric())?
+        let mut digits = String::new();
+        if radix == 10 {
+            digits.push(x);
+        } else {
+            self.next()?;
+        }
+        digits.push_str(&self.digits(radix)?);
+        if digits.is_empty() {
+            return Err(ScanError::InvalidNumeric(start_line, start_col));
+        }
+
+        let mut is_float = false;
+        if radix == 10 {
+            // The dot needs a digit after it, so `1.foo` is still 1 followed by a Dot
+            if self.it.peek() == Some(&'.') && self.peek_nth(1).map_or(false, |c| c.is_ascii_digit()) {
+                self.next()?;
+                digits.push('.');
+                digits.push_str(&self.digits(10)?);
+                is_float = true;
+            }
+
+            let exponent = match (self.peek_nth(0), self.peek_nth(1), self.peek_nth(2)) {
+                (Some('e'), Some(d), _) | (Some('E'), Some(d), _) if d.is_ascii_digit() => true,
+                (Some('e'), Some('+'), Some(d))
+                | (Some('e'), Some('-'), Some(d))
+                | (Some('E'), Some('+'), Some(d))
+                | (Some('E'), Some('-'), Some(d)) => d.is_ascii_digit(),
+                _ => false,
+            };
+            if exponent {
+                digits.push(self.next()?);
+                if let Some(&sign) = self.it.peek() {
+                    if sign == '+' || sign == '-' {
+                        digits.push(self.next()?);
+                    }
+                }
+                digits.push_str(&self.digits(10)?);
+                is_float = true;
+            }
+        }
+
+        let suffix: String = self
+            .consume_while(|c| c.is_alphanumeric() || c == '_')?
             .into_iter()
             .collect();
-        result.push_str(integer.as_str());
 
-        if self.it.peek() == Some(&'.') {
-            self.next()?;
-            let decimal: String = self
-                .consume_while(|c| c.is_numeric())?
-                .into_iter()
-                .collect();
-            result.push('.');
-            self.column += 1;
-            result.push_str(decimal.as_str());
-
-            let res_f = match result.parse::<f64>() {
-                Ok(value) => value,
-                Err(_) => {
-                    return Err(ScanError::InvalidNumeric(start_line, start_col));
+        let invalid = ScanError::InvalidNumeric(start_line, start_col);
+        let out_of_range = ScanError::NumericOutOfRange(start_line, start_col);
+        let ty = match suffix.as_str() {
+            "" => None,
+            "i8" => Some(ValueTy::I8),
+            "i16" => Some(ValueTy::I16),
+            "i32" => Some(ValueTy::I32),
+            "i64" => Some(ValueTy::I64),
+            "u8" => Some(ValueTy::U8),
+            "u16" => Some(ValueTy::U16),
+            "u32" => Some(ValueTy::U32),
+            "u64" => Some(ValueTy::U64),
+            "f32" => Some(ValueTy::F32),
+            "f64" => Some(ValueTy::F64),
+            _ => return Err(invalid),
+        };
+
+        match ty {
+            Some(ValueTy::F32) | Some(ValueTy::F64) | None if is_float => {
+                if radix != 10 {
+                    return Err(invalid);
                 }
-            };
-            Ok(TokenKind::FloatLit(Value::from(res_f)))
-        } else {
-            let res_i = match result.parse::<u32>() {
-                Ok(value) => value,
-                Err(_) => {
-                    return Err(ScanError::InvalidNumeric(start_line, start_col));
+                // Too big a float parses to inf instead of failing
+                let value = match digits.parse::<f64>() {
+                    Ok(value) if value.is_finite() => ValueWrapper::F64(value),
+                    Ok(_) => return Err(out_of_range),
+                    Err(_) => return Err(invalid),
+                };
+                match ty {
+                    None => Ok(TokenKind::FloatLit(Value::from(value))),
+                    Some(ty) => Ok(TokenKind::TypedLit(self.typed_literal(value, ty, out_of_range)?)),
                 }
-            };
-            Ok(TokenKind::IntLit(Value::from(res_i)))
+            }
+            // An integer with an integer suffix, or none. Only decimals can be floats
+            _ if is_float => Err(invalid),
+            Some(ValueTy::F32) | Some(ValueTy::F64) if radix != 10 => Err(invalid),
+            _ => {
+                let value = match u64::from_str_radix(&digits, radix) {
+                    Ok(value) => value,
+                    Err(_) => return Err(out_of_range),
+                };
+                match ty {
+                    None => match u32::try_from(value) {
+                        Ok(small) => Ok(TokenKind::IntLit(Value::from(small))),
+                        Err(_) => Ok(TokenKind::IntLit(Value::from(value))),
+                    },
+                    Some(ty) => Ok(TokenKind::TypedLit(self.typed_literal(ValueWrapper::U64(value), ty, out_of_range)?)),
+                }
+            }
         }
     }
 
+    // The digits of a number in the radix, without the `_`s between them
+    fn digits(&mut self, radix: u32) -> Result<String, ScanError> {
+        Ok(self
+            .consume_while(|c| c.is_digit(radix) || c == '_')?
+            .into_iter()
+            .filter(|c| *c != '_')
+            .collect())
+    }
+
+    fn typed_literal(&self, value: ValueWrapper, ty: ValueTy, out_of_range: ScanError) -> Result<Value, ScanError> {
+        let min_magnitude = match (value, ty) {
+            (ValueWrapper::U64(v), ValueTy::I8) => v == i8::MIN.unsigned_abs() as u64,
+            (ValueWrapper::U64(v), ValueTy::I16) => v == i16::MIN.unsigned_abs() as u64,
+            (ValueWrapper::U64(v), ValueTy::I32) => v == i32::MIN.unsigned_abs() as u64,
+            (ValueWrapper::U64(v), ValueTy::I64) => v == i64::MIN.unsigned_abs(),
+            _ => false,
+        };
+        if !value.fits(ty) && !min_magnitude {
+            return Err(out_of_range);
+        }
+        let mut value = Value::from(value);
+        match value.try_explicit_cast(ty) {
+            Ok(()) => Ok(value),
+            Err(()) => Err(out_of_range),
+        }
+    }
+
+    // Looks past the next character, Peekable only sees the one
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.it.clone().nth(n)
+    }
+
+    // A string without any `{expr}` in it is a plain literal. `{{` and `}}` are
+    // literal braces, and strings can span several lines
     fn string(&mut self, delim: char) -> Result<TokenKind, ScanError> {
         let start_line = self.line;
         let start_col = self.column;
 
-        let result = self.consume_while(|c| c != delim)?;
-        if self.next()? != delim {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        loop {
+            let ch = match self.next() {
+                Ok(ch) => ch,
+                Err(_) => return Err(ScanError::UnterminatedString(start_line, start_col)),
+            };
+
+            match ch {
+                ch if ch == delim => break,
+                '\\' => {
+                    if let Some(ch) = self.escape()? {
+                        literal.push(ch);
+                    }
+                }
+                '\n' => {
+                    literal.push(ch);
+                    self.line += 1;
+                    self.column = 0;
+                }
+                '{' | '}' if self.it.peek() == Some(&ch) => {
+                    self.next()?;
+                    literal.push(ch);
+                }
+                '{' => {
+                    if !literal.is_empty() {
+                        parts.push(StrPart::Lit(self.interner.intern(&std::mem::take(&mut literal))));
+                    }
+                    parts.push(StrPart::Expr(self.interpolation(delim)?));
+                }
+                ch => literal.push(ch),
+            }
+        }
+
+        if parts.is_empty() {
+            return Ok(TokenKind::StrLit(self.interner.intern(&literal)));
+        }
+        if !literal.is_empty() {
+            parts.push(StrPart::Lit(self.interner.intern(&literal)));
+        }
+        Ok(TokenKind::InterpolatedStr(parts))
+    }
+
+    /*
+        The character a backslash stands for, the same escapes as in Rust:
+
+            \n \t \r \0 \\ \" \'    the usual
+            \{ \}                  braces that don't start or end an interpolation
+            \u{1F34D}              a unicode scalar value, in 1 to 6 hex digits
+
+        A backslash at the end of a line skips the line break and the indentation
+        of the next line, which gives None.
+    */
+    fn escape(&mut self) -> Result<Option<char>, ScanError> {
+        let start_line = self.line;
+        let start_col = self.column;
+
+        let ch = match self.next() {
+            Ok(ch) => ch,
+            Err(_) => return Err(ScanError::InvalidEscape(start_line, start_col)),
+        };
+        let escaped = match ch {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' | '"' | '\'' | '{' | '}' => ch,
+            'u' => return self.unicode_escape(start_line, start_col).map(Some),
+            '\n' => {
+                self.line += 1;
+                self.column = 0;
+                self.consume_while(|c| c == ' ' || c == '\t')?;
+                return Ok(None);
+            }
+            _ => return Err(ScanError::InvalidEscape(start_line, start_col)),
+        };
+        Ok(Some(escaped))
+    }
+
+    fn unicode_escape(&mut self, start_line: usize, start_col: usize) -> Result<char, ScanError> {
+        let invalid = ScanError::InvalidUnicodeEscape(start_line, start_col);
+
+        if self.it.peek() != Some(&'{') {
+            return Err(invalid);
+        }
+        self.next()?;
+        let hex: String = self
+            .consume_while(|c| c.is_ascii_hexdigit())?
+            .into_iter()
+            .collect();
+        if self.it.peek() != Some(&'}') || hex.is_empty() || hex.len() > 6 {
+            return Err(invalid);
+        }
+        self.next()?;
+
+        // Surrogates and anything past 10FFFF aren't chars
+        match u32::from_str_radix(&hex, 16).ok().and_then(std::char::from_u32) {
+            Some(ch) => Ok(ch),
+            None => Err(invalid),
+        }
+    }
+
+    // `r"..."` is taken as it is, without escapes or interpolation. Any number of
+    // #s can go around the quotes so the string can hold a `"` that isn't followed
+    // by that many #s, i.e. r#"say "hi""#
+    fn raw_string(&mut self) -> Result<TokenKind, ScanError> {
+        let start_line = self.line;
+        let start_col = self.column;
+
+        let hashes = self.consume_while(|c| c == '#')?.len();
+        if self.it.peek() != Some(&'"') {
             return Err(ScanError::UnterminatedString(start_line, start_col));
         }
+        self.next()?;
 
-        let str: String = result.iter().collect();
-        Ok(TokenKind::StrLit(intern_string(str)))
+        let mut literal = String::new();
+        loop {
+            let ch = match self.next() {
+                Ok(ch) => ch,
+                Err(_) => return Err(ScanError::UnterminatedString(start_line, start_col)),
+            };
+
+            match ch {
+                '"' => {
+                    let mut closing = 0;
+                    while closing < hashes && self.it.peek() == Some(&'#') {
+                        self.next()?;
+                        closing += 1;
+                    }
+                    if closing == hashes {
+                        break;
+                    }
+                    literal.push('"');
+                    literal.extend(std::iter::repeat('#').take(closing));
+                }
+                '\n' => {
+                    literal.push(ch);
+                    self.line += 1;
+                    self.column = 0;
+                }
+                ch => literal.push(ch),
+            }
+        }
+        Ok(TokenKind::StrLit(self.interner.intern(&literal)))
+    }
+
+    // Everything up to the `}` closing an embedded expression, tokenized on its own
+    // The string's own quote ends it even inside of a {...}, a string inside of
+    // an embedded expression has to use the other quote
+    fn interpolation(&mut self, delim: char) -> Result<Vec<Token>, ScanError> {
+        let start_line = self.line;
+        let start_col = self.column;
+
+        let mut source = String::new();
+        let mut depth = 0;
+        loop {
+            let ch = match self.next() {
+                Ok(ch) => ch,
+                Err(_) => return Err(ScanError::InvalidInterpolation(start_line, start_col)),
+            };
+
+            match ch {
+                ch if ch == delim => return Err(ScanError::InvalidInterpolation(start_line, start_col)),
+                '}' if depth == 0 => break,
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => (),
+            }
+            source.push(ch);
+        }
+
+        let mut lexer = Lexer {
+            it: source.chars().peekable(),
+            interner: &mut *self.interner,
+            line: start_line,
+            column: start_col,
+        };
+        let tokens = lexer.tokenize()?;
+
+        // Nothing but the Eof
+        if tokens.len() == 1 {
+            return Err(ScanError::InvalidInterpolation(start_line, start_col));
+        }
+        Ok(tokens)
     }
 
     fn keyword(&mut self, name: String) -> Result<TokenKind, ScanError> {
@@ -188,7 +520,7 @@ impl<'a> Lexer<'a> {
             "bool" => TokenKind::BoolTy,
 
             "string" => TokenKind::StringTy,
-            _ => TokenKind::Ident(intern_string(name.clone())),
+            _ => TokenKind::Ident(self.interner.intern(&name)),
         };
 
         Ok(key)
@@ -211,10 +543,10 @@ impl<'a> Lexer<'a> {
         let mut tokens: Vec<Token> = Vec::new();
 
         loop {
-            if let Some(symbol) = self.whitespace()? {
+            // A slash or doc comment can be followed by more of either
+            while let Some(symbol) = self.whitespace()? {
                 tokens.push(Token::new(symbol, self.line, self.column));
-                self.whitespace()?;
-            };
+            }
 
             let ch = match self.next() {
                 Ok(ch) => {
@@ -251,6 +583,7 @@ impl<'a> Lexer<'a> {
                 '#' => TokenKind::Fun,
                 '@' => TokenKind::Class,
                 '$' => TokenKind::Var,
+                'r' if matches!(self.it.peek(), Some('"') | Some('#')) => self.raw_string()?,
                 x if x.is_numeric() => self.number(x)?,
                 x if x.is_alphabetic() => self.identifier(x)?,
                 '\'' | '"' => self.string(ch)?,
@@ -1,8 +1,9 @@
 use pineapple_ir::hir::token::Token;
+use pineapple_session::Interner;
 
 mod lexer;
 
-pub fn lex(buf: &str) -> Result<Vec<Token>, pineapple_error::ScanError> {
-    let mut lexer = lexer::Lexer::new(buf);
+pub fn lex(buf: &str, interner: &mut Interner) -> Result<Vec<Token>, pineapple_error::ScanError> {
+    let mut lexer = lexer::Lexer::new(buf, interner);
     lexer.tokenize()
 }
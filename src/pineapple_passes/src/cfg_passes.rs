@@ -0,0 +1,71 @@
+use pineapple_codegen_ssa::analysis::cfg::CFG;
+
+use crate::manager::{Form, Pass};
+
+pub struct ConstructSSA;
+
+impl Pass for ConstructSSA {
+    fn name(&self) -> &'static str {
+        "construct-ssa"
+    }
+
+    fn run(&mut self, cfg: &mut CFG) {
+        pineapple_codegen_ssa::convert_cfg_to_ssa_form(cfg);
+    }
+}
+
+pub struct CopyPropagation;
+
+impl Pass for CopyPropagation {
+    fn name(&self) -> &'static str {
+        "copy-prop"
+    }
+
+    fn run(&mut self, cfg: &mut CFG) {
+        pineapple_codegen_ssa::copy_propagation(cfg);
+    }
+}
+
+pub struct DeadCodeElimination;
+
+impl Pass for DeadCodeElimination {
+    fn name(&self) -> &'static str {
+        "dce"
+    }
+
+    fn run(&mut self, cfg: &mut CFG) {
+        pineapple_codegen_ssa::dead_code_elimination(cfg);
+    }
+}
+
+pub struct DestructSSA;
+
+impl Pass for DestructSSA {
+    fn name(&self) -> &'static str {
+        "destruct-ssa"
+    }
+
+    fn run(&mut self, cfg: &mut CFG) {
+        pineapple_codegen_ssa::destruct_cfg_from_ssa_form(cfg);
+    }
+
+    fn produces(&self) -> Form {
+        Form::Destructed
+    }
+}
+
+pub struct RegisterAllocation;
+
+impl Pass for RegisterAllocation {
+    fn name(&self) -> &'static str {
+        "register-allocation"
+    }
+
+    fn run(&mut self, cfg: &mut CFG) {
+        pineapple_codegen_ssa::register_allocation(cfg);
+    }
+
+    fn produces(&self) -> Form {
+        Form::Destructed
+    }
+}
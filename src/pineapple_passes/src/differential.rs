@@ -0,0 +1,83 @@
+use pineapple_codegen_bytecode::module::Module;
+use pineapple_codegen_ssa::analysis::cfg::CFG;
+use pineapple_session::{Interner, Natives};
+use pineapple_vm::VMOptions;
+
+// Differential testing: the MIR interpreter run over the linear code is the oracle,
+// and every later stage of the pipeline (including the VM) has to print the same thing.
+pub struct Differential {
+    natives: Natives,
+    // The program's names, every run works on its own copy since the interpreter
+    // interns the strings it makes
+    interner: Interner,
+    expected: Result<String, String>,
+    results: Vec<(String, Result<String, String>)>,
+}
+
+impl Differential {
+    pub fn new(
+        linear_code: &[Vec<pineapple_ir::mir::Stmt>],
+        natives: &Natives,
+        interner: &Interner,
+    ) -> Self {
+        let mut out: Vec<u8> = vec![];
+        let expected = pineapple_interpreter::interpret_linear_code(
+            linear_code,
+            natives,
+            &mut interner.clone(),
+            &mut out,
+        )
+        .map(|_| String::from_utf8_lossy(&out).to_string())
+        .map_err(|e| e.to_string());
+
+        Differential {
+            natives: natives.clone(),
+            interner: interner.clone(),
+            expected,
+            results: vec![],
+        }
+    }
+
+    pub fn check_cfgs(&mut self, pass: &str, cfgs: &[CFG]) {
+        let mut out: Vec<u8> = vec![];
+        let mut interner = self.interner.clone();
+        let result =
+            pineapple_interpreter::interpret_cfgs(cfgs, &self.natives, &mut interner, &mut out)
+                .map(|_| String::from_utf8_lossy(&out).to_string())
+                .map_err(|e| e.to_string());
+        self.results.push((format!("After {}", pass), result));
+    }
+
+    pub fn check_vm(&mut self, module: Module) {
+        let mut out: Vec<u8> = vec![];
+        let result = pineapple_vm::execute_vm_with_output(
+            module,
+            &self.natives,
+            &self.interner,
+            VMOptions::default(),
+            &mut out,
+        )
+        .map(|_| String::from_utf8_lossy(&out).to_string())
+        .map_err(|e| e.to_string());
+        self.results.push(("VM Execution".to_string(), result));
+    }
+
+    pub fn report(&self) {
+        println!("::Differential Testing::");
+        match &self.expected {
+            Ok(_) => println!("{:<28}reference", "Linear MIR"),
+            Err(e) => println!("{:<28}error: {}", "Linear MIR", e),
+        }
+
+        for (stage, result) in &self.results {
+            if *result == self.expected {
+                println!("{:<28}ok", stage);
+            } else {
+                println!("{:<28}MISMATCH", stage);
+                println!("    expected: {:?}", self.expected);
+                println!("    found:    {:?}", result);
+            }
+        }
+        println!();
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use pineapple_ast::ast::Stmt;
+use pineapple_codegen_bytecode::module::Module;
+use pineapple_error::{CompileError, RuntimeError, RuntimeErrorKind};
+use pineapple_ir::value::ValueContainer;
+use pineapple_ir::{Value, ValueTy};
+use pineapple_session::{Interner, NativeResult, NativeValue, Natives};
+use pineapple_vm::VMOptions;
+
+use crate::manager::PassManager;
+use crate::{codegen_ssa_pass, verify_module, PassArgs};
+
+type Sym = usize;
+
+// Argument types and return type of a function, as the typechecker saw them
+type Signature = (Vec<ValueTy>, ValueTy);
+
+/*
+    Embedding API, for running Pineapple code from a Rust program instead of the CLI:
+
+        let engine = Engine::new().optimize(true);
+        let mut program = engine.compile("#add(a: i32, b: i32): i32 { return a + b; }")?;
+        let sum = program.call("add", &[ValueWrapper::I32(1).into(), ValueWrapper::I32(2).into()])?;
+
+    Everything a compile needs is created for it, the Program owns the interner
+    with its names and string literals and nothing is shared between programs, so
+    an Engine and its Programs can be moved to (or cloned for) other threads.
+    `print` writes to stdout unless the program is given its own sink.
+
+    The host can give scripts functions of its own, which are typechecked like
+    any other function and called without leaving the VM:
+
+        let engine = Engine::new().register_native("double", vec![ValueTy::I32], ValueTy::I32,
+            |args, _| match args[0] {
+                NativeValue::Value(ValueWrapper::I32(x)) => Ok(ValueWrapper::I32(x * 2).into()),
+                _ => Err("expected an i32".to_string()),
+            });
+
+    Strings are a `NativeValue::Str` both for natives and for the arguments and
+    results of Program::call. examples/embed.rs at the root of the repository
+    uses all of it.
+*/
+#[derive(Clone)]
+pub struct Engine {
+    optimize: bool,
+    natives: Natives,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine {
+            optimize: false,
+            natives: pineapple_vm::standard_natives(),
+        }
+    }
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Engine::default()
+    }
+
+    // Runs the default optimisation passes, the same as -o
+    pub fn optimize(mut self, optimize: bool) -> Self {
+        self.optimize = optimize;
+        self
+    }
+
+    // Makes `name` callable from scripts, the closure gets arguments of `arg_tys`
+    // in the order they were passed and returns a value of `return_ty`, or an error
+    // message that stops the program. A script function with the same name
+    // takes precedence
+    pub fn register_native(
+        mut self,
+        name: &str,
+        arg_tys: Vec<ValueTy>,
+        return_ty: ValueTy,
+        function: impl Fn(&[NativeValue], &mut dyn Write) -> NativeResult + Send + Sync + 'static,
+    ) -> Self {
+        self.natives.register(name, arg_tys, return_ty, function);
+        self
+    }
+
+    pub fn compile(&self, source: &str) -> Result<Program, CompileError> {
+        let args = PassArgs {
+            optimize: self.optimize,
+            ..PassArgs::default()
+        };
+        let mut manager = PassManager::new(&args);
+        let mut interner = Interner::new();
+
+        let tokens = pineapple_lexer::lex(source, &mut interner).map_err(CompileError::Scan)?;
+        let mut ast = pineapple_ast::parse(tokens, &mut interner).map_err(CompileError::Parse)?;
+        pineapple_semantics::typecheck(&mut ast, &self.natives, &mut interner)
+            .map_err(CompileError::Type)?;
+
+        let signatures = ast
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Function(name, args, return_ty, _, _, _) => {
+                    let arg_tys = args.iter().map(|arg| arg.1).collect();
+                    Some((*name, (arg_tys, *return_ty)))
+                }
+                _ => None,
+            })
+            .collect();
+
+        let linear_code = pineapple_translation::convert_ast_to_linear_code(ast, &mut interner);
+        let cfgs = codegen_ssa_pass(linear_code, &args, &interner, &mut manager);
+        let module = pineapple_codegen_bytecode::compile_cfgs_to_bytecode(cfgs);
+
+        // Anything the verifier rejects here is a bug in the compiler, not the program
+        verify_module(&module, &self.natives, &interner).map_err(CompileError::Malformed)?;
+
+        Ok(Program {
+            module,
+            natives: self.natives.clone(),
+            interner,
+            signatures,
+            out: Box::new(std::io::stdout()),
+        })
+    }
+}
+
+pub struct Program {
+    module: Module,
+    natives: Natives,
+    interner: Interner,
+    signatures: HashMap<Sym, Signature>,
+    out: Box<dyn Write + Send>,
+}
+
+impl Program {
+    // Where `print` writes to from now on
+    pub fn set_output(&mut self, out: Box<dyn Write + Send>) {
+        self.out = out;
+    }
+
+    // Runs a function to completion and gives back its return value, which is a
+    // none value for functions that don't return one. Integer and float arguments
+    // are widened the same way literals are (i.e. an i8 works for an i32)
+    pub fn call(
+        &mut self,
+        function: &str,
+        args: &[NativeValue],
+    ) -> Result<NativeValue, RuntimeError> {
+        let error = |kind| RuntimeError {
+            kind,
+            trace: vec![],
+        };
+
+        // Only looked up, a name the program doesn't have can't be one of its functions
+        let signature = self
+            .interner
+            .lookup(function)
+            .and_then(|name| Some((name, self.signatures.get(&name)?)));
+        let (name, (arg_tys, _)) = match signature {
+            Some(signature) => signature,
+            None => {
+                let kind = RuntimeErrorKind::UndefinedFunction(function.to_string());
+                return Err(error(kind));
+            }
+        };
+        if args.len() != arg_tys.len() {
+            let kind =
+                RuntimeErrorKind::ArityMismatch(function.to_string(), arg_tys.len(), args.len());
+            return Err(error(kind));
+        }
+
+        let mut values = vec![];
+        for (i, (arg, ty)) in args.iter().zip(arg_tys).enumerate() {
+            let value = match arg {
+                NativeValue::Value(value) => {
+                    let mut value = Value::from(*value);
+                    if value.fetch_ty() == *ty || value.try_implicit_cast(*ty).is_ok() {
+                        Ok(NativeValue::Value(value.into_inner()))
+                    } else {
+                        Err(value.fetch_ty())
+                    }
+                }
+                NativeValue::Str(_) if *ty == ValueTy::STR => Ok(arg.clone()),
+                NativeValue::Str(_) => Err(ValueTy::STR),
+            };
+            match value {
+                Ok(value) => values.push(value),
+                Err(found) => {
+                    let kind = RuntimeErrorKind::ArgumentTypeMismatch(
+                        function.to_string(),
+                        i + 1,
+                        *ty,
+                        found,
+                    );
+                    return Err(error(kind));
+                }
+            }
+        }
+
+        let ret = pineapple_vm::execute_function(
+            &self.module,
+            &self.natives,
+            &self.interner,
+            name,
+            &values,
+            VMOptions::default(),
+            &mut *self.out,
+        )?;
+        if let Err(e) = self.out.flush() {
+            return Err(error(RuntimeErrorKind::Output(e.to_string())));
+        }
+        Ok(ret.unwrap_or_else(NativeValue::none))
+    }
+}
@@ -1,33 +1,90 @@
 use pineapple_codegen_bytecode::module::Module;
 use pineapple_codegen_ssa::analysis::cfg::CFG;
-use std::cell::RefCell;
-use std::path::PathBuf;
-use std::time::Instant;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
+use crate::cfg_passes::{ConstructSSA, DestructSSA, RegisterAllocation};
+use crate::differential::Differential;
+use crate::manager::PassManager;
 use pineapple_ast::ast::Stmt;
+use pineapple_error::{AssembleError, BytecodeError, LoadError, RuntimeError};
 use pineapple_ir::hir::token::Token;
+use pineapple_ir::mir::Label;
+use pineapple_session::{Interner, Natives};
+use pineapple_vm::{GcStats, Profile, VMOptions};
 use structopt::StructOpt;
 
-#[macro_export]
-macro_rules! benchmark {
-    ($name: expr, $code:expr) => {
-        let start = Instant::now();
-        let x = $code;
-        let duration = start.elapsed();
-
-        PERF_METRICS.with(|m| {
-            m.borrow_mut().push(format!(
-                "{:<24}{:}s",
-                $name,
-                format!("{:?}", duration.as_secs_f64())
-            ))
-        });
-        x
-    };
+mod cfg_passes;
+mod differential;
+mod engine;
+mod manager;
+
+pub use engine::{Engine, Program};
+pub use manager::{lookup_pass, registered_passes, Form, Pass};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Emit {
+    CfgDot,
+    DomtreeDot,
+    Mir,
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cfg-dot" => Ok(Emit::CfgDot),
+            "domtree-dot" => Ok(Emit::DomtreeDot),
+            "mir" => Ok(Emit::Mir),
+            _ => Err(format!("unknown emit kind '{}'", s)),
+        }
+    }
+}
+
+// An unknown pass is an argument error, before anything gets compiled
+fn check_pass(name: String) -> Result<(), String> {
+    lookup_pass(&name).map(|_| ())
 }
 
-thread_local! {
-    static PERF_METRICS: RefCell<Vec<String>> = RefCell::new(vec![]);
+fn check_printed_pass(name: String) -> Result<(), String> {
+    manager::check_printed_pass(&name)
+}
+
+// The options of every command that runs the VM
+#[derive(Debug, Default, StructOpt)]
+pub struct VmArgs {
+    /// Run a full garbage collection before every VM allocation
+    #[structopt(long = "gc-stress")]
+    pub gc_stress: bool,
+
+    /// Step through the program in the VM's interactive debugger (`help` lists the commands)
+    #[structopt(long = "debug-vm")]
+    pub debug_vm: bool,
+
+    /// Log every VM instruction with its operand values and writes to stderr, only the ones in a function with --trace=NAME
+    #[structopt(long = "trace", require_equals = true, conflicts_with = "profile")]
+    pub trace: Option<Option<String>>,
+
+    /// Profile the VM run and write the call stacks next to the input as .folded (for flamegraphs)
+    #[structopt(long = "profile")]
+    pub profile: bool,
+}
+
+impl VmArgs {
+    pub fn options(&self) -> VMOptions {
+        VMOptions {
+            gc_stress: self.gc_stress,
+            debug_vm: self.debug_vm,
+            profile: self.profile,
+            // --trace=NAME takes the function with or without its @
+            trace: self.trace.as_ref().map(|only| {
+                only.as_ref()
+                    .map(|name| name.trim_start_matches('@').to_string())
+            }),
+        }
+    }
 }
 
 #[derive(Debug, Default, StructOpt)]
@@ -41,152 +98,434 @@ pub struct PassArgs {
     #[structopt(short = "o", long = "optimize")]
     pub optimize: bool,
 
+    /// Optimisation passes to run on the SSA form, in order (i.e. --passes=copy-prop,dce)
+    #[structopt(long = "passes", require_delimiter = true, validator = check_pass)]
+    pub passes: Vec<String>,
+
+    /// Dump the IR before the named passes run, or "all"
+    #[structopt(long = "print-before", require_delimiter = true, validator = check_printed_pass)]
+    pub print_before: Vec<String>,
+
+    /// Dump the IR after the named passes run, or "all"
+    #[structopt(long = "print-after", require_delimiter = true, validator = check_printed_pass)]
+    pub print_after: Vec<String>,
+
+    #[structopt(long = "verify-ir")]
+    pub verify_ir: bool,
+
+    /// Extra artifacts to write next to the input: cfg-dot, domtree-dot, mir
+    #[structopt(long = "emit", require_delimiter = true)]
+    pub emit: Vec<Emit>,
+
+    /// Treat the input as textual MIR (i.e. from --emit=mir) instead of source code
+    #[structopt(long = "from-mir")]
+    pub from_mir: bool,
+
+    /// Run the MIR interpreter after every pass and compare its output against the VM
+    #[structopt(long = "differential")]
+    pub differential: bool,
+
+    #[structopt(parse(from_os_str))]
+    pub input: PathBuf,
+}
+
+// `pineapple FILE [ARGS]`, which compiles and runs in one go. `build` only takes
+// the PassArgs, so it rejects the VM's options instead of ignoring them
+#[derive(Debug, Default, StructOpt)]
+pub struct CompileArgs {
+    #[structopt(flatten)]
+    pub passes: PassArgs,
+
+    #[structopt(flatten)]
+    pub vm: VmArgs,
+}
+
+#[derive(Debug, Default, StructOpt)]
+pub struct RunArgs {
+    #[structopt(short, long)]
+    pub debug: bool,
+
+    #[structopt(short, long)]
+    pub perf: bool,
+
+    #[structopt(flatten)]
+    pub vm: VmArgs,
+
     #[structopt(parse(from_os_str))]
     pub input: PathBuf,
 }
 
-pub fn compile(buf: &str, args: PassArgs) {
-    let tokens = lexical_pass(buf, &args);
+#[derive(Debug, Default, StructOpt)]
+pub struct AsmArgs {
+    #[structopt(short, long)]
+    pub debug: bool,
+
+    /// Compare each program's output against its `; expect:` comments instead of printing it
+    #[structopt(long = "check")]
+    pub check: bool,
+
+    #[structopt(flatten)]
+    pub vm: VmArgs,
+
+    #[structopt(parse(from_os_str), required = true)]
+    pub inputs: Vec<PathBuf>,
+}
+
+pub fn compile(buf: &str, args: CompileArgs) -> Result<(), RuntimeError> {
+    let mut manager = PassManager::new(&args.passes);
+    let natives = pineapple_vm::standard_natives();
+    let mut interner = Interner::new();
+    let module = compile_to_module(buf, &args.passes, &natives, &mut interner, &mut manager);
+
+    if let Some(differential) = &mut manager.differential {
+        differential.check_vm(module.clone());
+        differential.report();
+    }
+
+    let options = args.vm.options();
+    let stats = manager.time("VM Execution", || {
+        pineapple_vm::execute_vm(module, &natives, &interner, options)
+    })?;
+
+    if args.passes.perf {
+        manager.report_timings();
+        report_gc_stats(&stats.gc);
+    }
+    if let Some(profile) = stats.profile {
+        report_profile(&args.passes.input, &profile);
+    }
+    Ok(())
+}
+
+// Compiles the program down to a serialized .pbc module instead of running it
+pub fn build(buf: &str, args: PassArgs) -> Vec<u8> {
+    let mut manager = PassManager::new(&args);
+    let natives = pineapple_vm::standard_natives();
+    let mut interner = Interner::new();
+    let module = compile_to_module(buf, &args, &natives, &mut interner, &mut manager);
+
+    if let Some(differential) = &mut manager.differential {
+        differential.check_vm(module.clone());
+        differential.report();
+    }
+
+    let pbc = manager.time("Bytecode Writing", || {
+        pineapple_codegen_bytecode::write_pbc(&module, &|sym| interner.get_string(sym))
+    });
+
+    if args.perf {
+        manager.report_timings();
+    }
+    pbc
+}
+
+// Runs a .pbc module that was previously written by build()
+pub fn run(buf: &[u8], args: RunArgs) -> Result<(), LoadError> {
+    let mut manager = PassManager::default();
+    let natives = pineapple_vm::standard_natives();
+    let mut interner = Interner::new();
+
+    let mut intern = |s: &str| interner.intern(s);
+    let module = manager.time("Bytecode Loading", || {
+        pineapple_codegen_bytecode::load_pbc(buf, &mut intern)
+    })?;
+
+    manager
+        .time("Bytecode Verification", || verify_program(&module, &natives, &interner))
+        .map_err(LoadError::Malformed)?;
+
+    if args.debug {
+        print_module(&module, &interner);
+    }
+
+    let options = args.vm.options();
+    let stats = manager
+        .time("VM Execution", || {
+            pineapple_vm::execute_vm(module, &natives, &interner, options)
+        })
+        .map_err(LoadError::Runtime)?;
+
+    if args.perf {
+        manager.report_timings();
+        report_gc_stats(&stats.gc);
+    }
+    if let Some(profile) = stats.profile {
+        report_profile(&args.input, &profile);
+    }
+    Ok(())
+}
+
+// Assembles a hand written bytecode program and runs it, see
+// pineapple_codegen_bytecode/src/assemble.rs for the syntax
+pub fn assemble(
+    buf: &str,
+    input: &Path,
+    args: &AsmArgs,
+    out: &mut dyn Write,
+) -> Result<(), AssembleError> {
+    let natives = pineapple_vm::standard_natives();
+    let mut interner = Interner::new();
+    let module = pineapple_codegen_bytecode::assemble_module(buf, &mut |s| interner.intern(s))?;
+
+    if args.debug {
+        print_module(&module, &interner);
+    }
+
+    verify_program(&module, &natives, &interner).map_err(AssembleError::Malformed)?;
+    let options = args.vm.options();
+    let stats = pineapple_vm::execute_vm_with_output(module, &natives, &interner, options, out)
+        .map_err(AssembleError::Runtime)?;
+
+    if let Some(profile) = stats.profile {
+        report_profile(input, &profile);
+    }
+    Ok(())
+}
+
+// Disassembles a .pbc module without running it
+pub fn disassemble(buf: &[u8]) -> Result<String, LoadError> {
+    let mut interner = Interner::new();
+    let module = pineapple_codegen_bytecode::load_pbc(buf, &mut |s| interner.intern(s))?;
+    Ok(pineapple_codegen_bytecode::disassemble_module(
+        &module,
+        &|sym| interner.get_string(sym),
+    ))
+}
+
+// The interner ends up with every name in the module, it's needed to run it
+fn compile_to_module(
+    buf: &str,
+    args: &PassArgs,
+    natives: &Natives,
+    interner: &mut Interner,
+    manager: &mut PassManager,
+) -> Module {
+    let linear_code = if args.from_mir {
+        mir_pass(buf, args, interner, manager)
+    } else {
+        frontend_pass(buf, args, natives, interner, manager)
+    };
+    let name_of = |sym| interner.get_string(sym);
+
+    if args.emit.contains(&Emit::Mir) {
+        let mir = pineapple_ir::mir::print_mir(&linear_code, &name_of);
+        emit_file(&args.input, "mir", mir);
+    }
+
+    if args.differential {
+        manager.differential = Some(Differential::new(&linear_code, natives, interner));
+    }
+
+    let cfgs = codegen_ssa_pass(linear_code, args, interner, manager);
+
+    let module = manager.time("CFGs to Bytecode", || {
+        pineapple_codegen_bytecode::compile_cfgs_to_bytecode(cfgs)
+    });
+    if args.debug {
+        print_module(&module, interner);
+    }
+
+    // Anything the verifier rejects here is a bug in the compiler, not the program
+    let verified = manager.time("Bytecode Verification", || {
+        verify_module(&module, natives, interner)
+    });
+    if let Err(e) = verified {
+        panic!("Compiled to a malformed module. {}", e);
+    }
+
+    // Whereas a source file without a main function is the program's fault
+    if let Err(e) = pineapple_codegen_bytecode::verify_main(&module, &name_of) {
+        panic!("{}", e);
+    }
+
+    module
+}
+
+fn verify_module(
+    module: &Module,
+    natives: &Natives,
+    interner: &Interner,
+) -> Result<(), BytecodeError> {
+    pineapple_codegen_bytecode::verify_module(
+        module,
+        &|sym| natives.arity(interner.get(sym)),
+        &|sym| interner.get_string(sym),
+    )
+}
+
+// A module the CLI runs needs somewhere to start, on top of being well formed
+fn verify_program(
+    module: &Module,
+    natives: &Natives,
+    interner: &Interner,
+) -> Result<(), BytecodeError> {
+    verify_module(module, natives, interner)?;
+    pineapple_codegen_bytecode::verify_main(module, &|sym| interner.get_string(sym))
+}
+
+fn report_gc_stats(stats: &GcStats) {
+    println!("::Memory::");
+    println!("{:<24}{}", "Allocations", stats.allocations);
+    println!("{:<24}{}", "Collections", stats.collections);
+    println!("{:<24}{}", "Freed", stats.freed);
+    println!("{:<24}{}", "Peak live", stats.peak_live);
+}
+
+fn report_profile(input: &Path, profile: &Profile) {
+    print!("{}", profile.report());
+    emit_file(input, "folded", profile.folded());
+}
+
+fn print_module(module: &Module, interner: &Interner) {
+    let listing =
+        pineapple_codegen_bytecode::disassemble_module(module, &|sym| interner.get_string(sym));
+    println!("::Disassembly::\n{}", listing);
+}
+
+fn frontend_pass(
+    buf: &str,
+    args: &PassArgs,
+    natives: &Natives,
+    interner: &mut Interner,
+    manager: &mut PassManager,
+) -> Vec<Vec<pineapple_ir::mir::Stmt>> {
+    let tokens = lexical_pass(buf, interner, manager);
     if args.debug {
         println!("::Lexical Analysis::\n{:#?}\n", tokens);
     }
 
-    let mut ast = ast_pass(tokens, &args);
+    let mut ast = ast_pass(tokens, interner, manager);
     if args.debug {
         println!("::AST Creation::\n{:#?}\n", ast);
     }
 
-    typcheck_pass(&mut ast, &args);
+    typcheck_pass(&mut ast, natives, interner, manager);
     if args.debug {
         println!("::Type Checking::\n{:#?}\n", ast);
     }
 
-    let linear_code = linear_code_pass(ast, &args);
+    let linear_code = manager.time("AST to LinearCode", || {
+        pineapple_translation::convert_ast_to_linear_code(ast, interner)
+    });
     if args.debug {
         println!("::AST to LinearCode::\n{:#?}\n", linear_code);
     }
 
-    let cfgs = codegen_ssa_pass(linear_code, &args);
+    linear_code
+}
 
-    let module = codegen_bytecode_pass(cfgs, &args);
-    if args.debug {
-        for chunk in module.clone().chunks {
-            for instruction in chunk.instructions {
-                println!("{:?}", instruction);
-            }
-            println!("")
+fn mir_pass(
+    buf: &str,
+    args: &PassArgs,
+    interner: &mut Interner,
+    manager: &mut PassManager,
+) -> Vec<Vec<pineapple_ir::mir::Stmt>> {
+    let mut intern = |s: &str| interner.intern(s);
+    let linear_code = manager.time("MIR Parsing", || {
+        match pineapple_ir::mir::parse_mir(buf, &mut intern) {
+            Ok(linear_code) => linear_code,
+            Err(e) => panic!("{}", e),
         }
-    }
-
-    exec_virtual_machine(module, &args);
+    });
 
-    if args.perf {
-        PERF_METRICS.with(|m| println!("{:#?}", m.borrow()));
+    if args.debug {
+        println!("::MIR Parsing::\n{:#?}\n", linear_code);
     }
+    linear_code
 }
 
-fn lexical_pass(buf: &str, args: &PassArgs) -> Vec<pineapple_ir::hir::token::Token> {
-    let code = || match pineapple_lexer::lex(buf) {
+fn lexical_pass(
+    buf: &str,
+    interner: &mut Interner,
+    manager: &mut PassManager,
+) -> Vec<pineapple_ir::hir::token::Token> {
+    manager.time("Lexical Analysis", || match pineapple_lexer::lex(buf, interner) {
         Ok(tokens) => tokens,
         Err(e) => panic!(format!("{}", e)),
-    };
-
-    if args.perf {
-        benchmark! {
-            "Lexical Analysis",
-            code()
-        }
-    } else {
-        code()
-    }
+    })
 }
 
-fn ast_pass(tokens: Vec<Token>, args: &PassArgs) -> Vec<pineapple_ast::ast::Stmt> {
-    let code = || match pineapple_ast::parse(tokens) {
+fn ast_pass(
+    tokens: Vec<Token>,
+    interner: &mut Interner,
+    manager: &mut PassManager,
+) -> Vec<pineapple_ast::ast::Stmt> {
+    manager.time("AST Creation", || match pineapple_ast::parse(tokens, interner) {
         Ok(ast) => ast,
         Err(e) => panic!(format!("{}", e)),
-    };
-
-    if args.perf {
-        benchmark! {
-            "AST Creation",
-            code()
-        }
-    } else {
-        code()
-    }
+    })
 }
 
-fn typcheck_pass(ast: &mut Vec<Stmt>, args: &PassArgs) {
-    let mut code = || match pineapple_semantics::typecheck(ast) {
+fn typcheck_pass(
+    ast: &mut Vec<Stmt>,
+    natives: &Natives,
+    interner: &mut Interner,
+    manager: &mut PassManager,
+) {
+    manager.time("Type Checking", || match pineapple_semantics::typecheck(ast, natives, interner) {
         Ok(ast) => ast,
         Err(e) => panic!(format!("{}", e)),
-    };
-
-    if args.perf {
-        benchmark! {
-            "Type Checking",
-            code()
-        }
-    } else {
-        code()
-    }
+    })
 }
 
-fn linear_code_pass(ast: Vec<Stmt>, args: &PassArgs) -> Vec<Vec<pineapple_ir::mir::Stmt>> {
-    let code = || pineapple_translation::convert_ast_to_linear_code(ast);
+fn codegen_ssa_pass(
+    linear_code: Vec<Vec<pineapple_ir::mir::Stmt>>,
+    args: &PassArgs,
+    interner: &Interner,
+    manager: &mut PassManager,
+) -> Vec<CFG> {
+    let mut optimizations = match manager.optimization_passes() {
+        Ok(passes) => passes,
+        Err(e) => panic!("{}", e),
+    };
 
-    if args.perf {
-        benchmark! {
-            "AST to LinearCode",
-            code()
-        }
-    } else {
-        code()
-    }
-}
+    let mut cfgs: Vec<CFG> = manager.time("CFG Creation", || {
+        linear_code.into_iter().map(CFG::from).collect()
+    });
 
-fn codegen_ssa_pass(linear_code: Vec<Vec<pineapple_ir::mir::Stmt>>, args: &PassArgs) -> Vec<CFG> {
-    let mut cfgs: Vec<CFG> = vec![];
+    manager.run(&mut ConstructSSA, &mut cfgs, interner);
 
-    for compilable_block in linear_code {
-        let mut cfg = CFG::from(compilable_block);
+    let name_of = |sym| interner.get_string(sym);
+    for cfg in cfgs.iter() {
+        if args.emit.contains(&Emit::CfgDot) {
+            let name = function_name(cfg, interner);
+            let dot = pineapple_codegen_ssa::cfg_to_dot(cfg, &name, &name_of);
+            emit_file(&args.input, &format!("{}.cfg.dot", name), dot);
+        }
 
-        pineapple_codegen_ssa::convert_cfg_to_ssa_form(&mut cfg);
-        pineapple_codegen_ssa::destruct_cfg_from_ssa_form(&mut cfg);
-        pineapple_codegen_ssa::register_allocation(&mut cfg);
+        if args.emit.contains(&Emit::DomtreeDot) {
+            let name = function_name(cfg, interner);
+            let dot = pineapple_codegen_ssa::domtree_to_dot(cfg, &name, &name_of);
+            emit_file(&args.input, &format!("{}.domtree.dot", name), dot);
+        }
+    }
 
-        cfgs.push(cfg);
+    for pass in optimizations.iter_mut() {
+        manager.run(pass.as_mut(), &mut cfgs, interner);
     }
 
+    manager.run(&mut DestructSSA, &mut cfgs, interner);
+    manager.run(&mut RegisterAllocation, &mut cfgs, interner);
+
     cfgs
 }
 
-fn codegen_bytecode_pass(
-    cfgs: Vec<CFG>,
-    args: &PassArgs,
-) -> pineapple_codegen_bytecode::module::Module {
-    let code = || pineapple_codegen_bytecode::compile_cfgs_to_bytecode(cfgs);
-
-    if args.perf {
-        benchmark! {
-            "CFGs to Bytecode",
-            code()
-        }
-    } else {
-        code()
+fn function_name(cfg: &CFG, interner: &Interner) -> String {
+    match cfg.entry_label {
+        Label::Named(sym) => interner.get_string(sym),
+        Label::Marker(marker) => format!("_L{}", marker),
     }
 }
 
-fn exec_virtual_machine(module: Module, args: &PassArgs) {
-    let code = || pineapple_vm::execute_vm(module);
+// Artifacts get written alongside the input, i.e. samples/test.pi -> samples/test.main.cfg.dot
+fn emit_file(input: &Path, suffix: &str, contents: String) {
+    let stem = input
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let path = input.with_file_name(format!("{}.{}", stem, suffix));
 
-    if args.perf {
-        benchmark! {
-            "VM Execution",
-            code()
-        }
-    } else {
-        code()
+    if let Err(e) = std::fs::write(&path, contents) {
+        panic!("unable to write {}: {}", path.display(), e);
     }
 }
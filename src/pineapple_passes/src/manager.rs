@@ -0,0 +1,205 @@
+use std::time::{Duration, Instant};
+
+use pineapple_codegen_ssa::analysis::basic_block::{BlockEntry, BlockExit};
+use pineapple_codegen_ssa::analysis::cfg::CFG;
+use pineapple_error::SSAError;
+use pineapple_ir::mir::print_stmt;
+use pineapple_session::Interner;
+
+use crate::cfg_passes::{
+    ConstructSSA, CopyPropagation, DeadCodeElimination, DestructSSA, RegisterAllocation,
+};
+use crate::differential::Differential;
+use crate::{function_name, PassArgs};
+
+// The shape a pass leaves the CFG in, which decides what --verify-ir checks it against
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Form {
+    SSA,
+    Destructed,
+}
+
+pub trait Pass {
+    fn name(&self) -> &'static str;
+
+    fn run(&mut self, cfg: &mut CFG);
+
+    fn produces(&self) -> Form {
+        Form::SSA
+    }
+}
+
+// Every optimisation that can be picked with --passes. They all run on SSA form,
+// between construct-ssa and destruct-ssa.
+pub fn registered_passes() -> Vec<Box<dyn Pass>> {
+    vec![Box::new(CopyPropagation), Box::new(DeadCodeElimination)]
+}
+
+// What -o turns on when --passes isn't given
+const DEFAULT_OPTIMIZATIONS: [&str; 2] = ["copy-prop", "dce"];
+
+pub fn lookup_pass(name: &str) -> Result<Box<dyn Pass>, String> {
+    match registered_passes().into_iter().find(|pass| pass.name() == name) {
+        Some(pass) => Ok(pass),
+        None => {
+            let available: Vec<&str> = registered_passes().iter().map(|pass| pass.name()).collect();
+            Err(unknown_pass(name, &available))
+        }
+    }
+}
+
+// --print-before/--print-after can also name the passes every compile runs, in
+// the order they run, or "all" of them
+pub fn check_printed_pass(name: &str) -> Result<(), String> {
+    let mut available = vec![ConstructSSA.name()];
+    available.extend(registered_passes().iter().map(|pass| pass.name()));
+    available.extend(&[DestructSSA.name(), RegisterAllocation.name(), "all"]);
+
+    if available.contains(&name) {
+        Ok(())
+    } else {
+        Err(unknown_pass(name, &available))
+    }
+}
+
+fn unknown_pass(name: &str, available: &[&str]) -> String {
+    format!(
+        "Unknown pass '{}', the available passes are: {}",
+        name,
+        available.join(", ")
+    )
+}
+
+#[derive(Default)]
+pub struct PassManager {
+    timings: Vec<(String, Duration)>,
+    optimizations: Vec<String>,
+    print_before: Vec<String>,
+    print_after: Vec<String>,
+    verify_ir: bool,
+    pub differential: Option<Differential>,
+}
+
+impl PassManager {
+    pub fn new(args: &PassArgs) -> Self {
+        let optimizations = if !args.passes.is_empty() {
+            args.passes.clone()
+        } else if args.optimize {
+            DEFAULT_OPTIMIZATIONS.iter().map(|p| p.to_string()).collect()
+        } else {
+            vec![]
+        };
+
+        PassManager {
+            timings: vec![],
+            optimizations,
+            print_before: args.print_before.clone(),
+            print_after: args.print_after.clone(),
+            verify_ir: args.verify_ir,
+            differential: None,
+        }
+    }
+
+    pub fn optimization_passes(&self) -> Result<Vec<Box<dyn Pass>>, String> {
+        self.optimizations
+            .iter()
+            .map(|name| lookup_pass(name))
+            .collect()
+    }
+
+    pub fn time<T, F>(&mut self, name: &str, code: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        let start = Instant::now();
+        let x = code();
+        self.timings.push((name.to_string(), start.elapsed()));
+        x
+    }
+
+    // The interner is only for naming functions in dumps and errors
+    pub fn run(&mut self, pass: &mut dyn Pass, cfgs: &mut [CFG], interner: &Interner) {
+        if selected(&self.print_before, pass.name()) {
+            dump("Before", pass.name(), cfgs, interner);
+        }
+
+        self.time(pass.name(), || {
+            for cfg in cfgs.iter_mut() {
+                pass.run(cfg);
+            }
+        });
+
+        if selected(&self.print_after, pass.name()) {
+            dump("After", pass.name(), cfgs, interner);
+        }
+
+        if self.verify_ir {
+            for cfg in cfgs.iter() {
+                let name = function_name(cfg, interner);
+                match pass.produces() {
+                    Form::SSA => verify(pass.name(), &name, cfg, pineapple_codegen_ssa::verify_ssa_form),
+                    Form::Destructed => verify(
+                        pass.name(),
+                        &name,
+                        cfg,
+                        pineapple_codegen_ssa::verify_destructed_form,
+                    ),
+                }
+            }
+        }
+
+        if let Some(differential) = &mut self.differential {
+            differential.check_cfgs(pass.name(), cfgs);
+        }
+    }
+
+    pub fn report_timings(&self) {
+        println!("::Timings::");
+        let mut total = Duration::default();
+        for (name, duration) in &self.timings {
+            println!("{:<24}{:?}s", name, duration.as_secs_f64());
+            total += *duration;
+        }
+        println!("{:<24}{:?}s", "Total", total.as_secs_f64());
+    }
+}
+
+fn selected(names: &[String], pass: &str) -> bool {
+    names.iter().any(|name| name == pass || name == "all")
+}
+
+fn verify<F>(pass: &str, function: &str, cfg: &CFG, verify: F)
+where
+    F: Fn(&CFG) -> Result<(), SSAError>,
+{
+    if let Err(e) = verify(cfg) {
+        panic!(
+            "IR verification failed after {} in '{}':\n{:?}\n{}",
+            pass,
+            function,
+            cfg,
+            e
+        );
+    }
+}
+
+// Dumps are in the textual MIR format with a comment marking where each block starts
+fn dump(when: &str, pass: &str, cfgs: &[CFG], interner: &Interner) {
+    let name_of = |sym| interner.get_string(sym);
+    println!("// *** IR Dump {} {} ***", when, pass);
+    for cfg in cfgs {
+        for block in &cfg.blocks {
+            println!("// B{}", block.index);
+            if let BlockEntry::Entry(s) = &block.entry {
+                println!("{}", print_stmt(&*s.borrow(), &name_of));
+            }
+            for s in &block.statements {
+                println!("{}", print_stmt(&*s.borrow(), &name_of));
+            }
+            if let BlockExit::Exit(s) = &block.exit {
+                println!("{}", print_stmt(&*s.borrow(), &name_of));
+            }
+        }
+        println!();
+    }
+}
@@ -1,8 +1,13 @@
 use pineapple_ast::ast::Stmt;
 use pineapple_error::TypeError;
+use pineapple_session::{Interner, Natives};
 
 mod typecheck;
 
-pub fn typecheck(ast: &mut Vec<Stmt>) -> Result<(), TypeError> {
-    typecheck::typecheck(ast)
+pub fn typecheck(
+    ast: &mut Vec<Stmt>,
+    natives: &Natives,
+    interner: &mut Interner,
+) -> Result<(), TypeError> {
+    typecheck::typecheck(ast, natives, interner)
 }
@@ -2,88 +2,120 @@ use pineapple_ast::ast::{Expr, Stmt};
 use pineapple_error::TypeError;
 use pineapple_ir::op::BinOp;
 use pineapple_ir::op::RelOp;
+use pineapple_ir::value::ValueContainer;
 use pineapple_ir::{Value, ValueTy};
+use pineapple_session::{Interner, Natives, SymbolTable};
 
 type Ident = usize;
 type Type = ValueTy;
 type Args = Vec<(Ident, Type)>;
 
-pub fn typecheck(ast: &mut Vec<Stmt>) -> Result<(), TypeError> {
-    pineapple_session::insert_symbol_table_context();
+pub fn typecheck(
+    ast: &mut Vec<Stmt>,
+    natives: &Natives,
+    interner: &mut Interner,
+) -> Result<(), TypeError> {
+    let symbols = &mut SymbolTable::default();
+
+    // Natives are a scope of their own around the program, so its functions shadow them
+    symbols.insert_context();
+    for native in natives.iter() {
+        for (arg_tys, return_ty) in native.signatures.iter().flatten() {
+            let name = interner.intern(&native.name);
+            symbols.insert_function_overload(name, *return_ty, arg_tys.clone());
+        }
+    }
+
+    symbols.insert_context();
     for stmt in ast.iter() {
-        if let Stmt::Function(name, args, return_ty, _) = stmt {
+        if let Stmt::Function(name, args, return_ty, _, _, _) = stmt {
             let arg_types = args.iter().map(|ty| ty.1).collect();
-            pineapple_session::insert_function_into_symbol_table(name, return_ty, arg_types);
+            symbols.insert_function(*name, *return_ty, arg_types);
         }
     }
 
+    // Only needed to name things in errors from here on
+    let names = &*interner;
     for stmt in ast.iter_mut() {
-        check_stmt(stmt, None)?;
+        check_stmt(symbols, names, stmt, None)?;
     }
-    pineapple_session::pop_symbol_table_context();
+    symbols.pop_context();
+    symbols.pop_context();
     Ok(())
 }
 
 fn check_function(
+    symbols: &mut SymbolTable,
+    names: &Interner,
     name: &Ident,
     args: &Args,
     return_ty: &Type,
     body: &mut Stmt,
 ) -> Result<(), TypeError> {
     let arg_types = args.iter().map(|ty| ty.1).collect();
-    pineapple_session::insert_function_into_symbol_table(name, return_ty, arg_types);
-    pineapple_session::insert_symbol_table_context();
+    symbols.insert_function(*name, *return_ty, arg_types);
+    symbols.insert_context();
 
     for (ident, value_ty) in args {
-        pineapple_session::insert_variable_into_symbol_table(ident, value_ty);
+        symbols.insert_variable(*ident, *value_ty);
     }
 
-    check_stmt(body, Some(*return_ty))?;
+    check_stmt(symbols, names, body, Some(*return_ty))?;
 
-    pineapple_session::pop_symbol_table_context();
+    symbols.pop_context();
     Ok(())
 }
 
-fn check_stmt(stmt: &mut Stmt, func_return_ty: Option<Type>) -> Result<(), TypeError> {
+fn check_stmt(
+    symbols: &mut SymbolTable,
+    names: &Interner,
+    stmt: &mut Stmt,
+    func_return_ty: Option<Type>,
+) -> Result<(), TypeError> {
     match stmt {
-        Stmt::Function(name, args, return_ty, body) => check_function(name, args, return_ty, body),
+        Stmt::Function(name, args, return_ty, body, _, _) => check_function(symbols, names, name, args, return_ty, body),
         Stmt::Block(stmts) => {
             for stmt in stmts {
-                check_stmt(stmt, func_return_ty)?;
+                check_stmt(symbols, names, stmt, func_return_ty)?;
             }
             Ok(())
         }
-        Stmt::If(cond, body, other) => {
-            check_expr(cond, None)?;
-            check_stmt(body, func_return_ty)?;
+        Stmt::If(cond, body, other, _) => {
+            check_expr(symbols, names, cond, None)?;
+            check_stmt(symbols, names, body, func_return_ty)?;
 
             if let Some(other) = other {
-                check_stmt(other, func_return_ty)?;
+                check_stmt(symbols, names, other, func_return_ty)?;
             }
             Ok(())
         }
-        Stmt::While(cond, body) => {
-            check_expr(cond, None)?;
-            check_stmt(body, func_return_ty)
+        Stmt::While(cond, body, _) => {
+            check_expr(symbols, names, cond, None)?;
+            check_stmt(symbols, names, body, func_return_ty)
         }
-        Stmt::Expression(expr) => match check_expr(expr, None) {
+        Stmt::Expression(expr, _) => match check_expr(symbols, names, expr, None) {
             Ok(_) => Ok(()),
             Err(e) => Err(e),
         },
-        Stmt::Print(expressions) => {
+        Stmt::Print(expressions, _) => {
             for expr in expressions {
-                match check_expr(expr, None) {
+                match check_expr(symbols, names, expr, None) {
                     Ok(_) => Ok(()),
                     Err(e) => Err(e),
                 }?;
             }
             Ok(())
         },
-        Stmt::Return(expr) => {
+        Stmt::Return(expr, _) => {
             if let Some(expr) = expr {
-                let rtype = check_expr(expr, func_return_ty)?;
+                let rtype = check_expr(symbols, names, expr, func_return_ty)?;
                 if rtype != func_return_ty {
-                    panic!("invalid return type!")
+                    return match (func_return_ty, rtype) {
+                        (Some(expected), Some(actual)) => {
+                            Err(TypeError::InvalidReturnType(expected, actual))
+                        }
+                        _ => Err(TypeError::ExpectedNestedType),
+                    };
                 }
             }
             Ok(())
@@ -91,7 +123,12 @@ fn check_stmt(stmt: &mut Stmt, func_return_ty: Option<Type>) -> Result<(), TypeE
     }
 }
 
-fn check_expr(expr: &mut Expr, expected_ty: Option<Type>) -> Result<Option<Type>, TypeError> {
+fn check_expr(
+    symbols: &mut SymbolTable,
+    names: &Interner,
+    expr: &mut Expr,
+    expected_ty: Option<Type>,
+) -> Result<Option<Type>, TypeError> {
     // Resolves an rval type, making sure that the expected type equals it
     fn resolve_rval_ty(ty1: Option<Type>, ty2: Option<Type>) -> Result<(), TypeError> {
         match (ty1, ty2) {
@@ -111,21 +148,21 @@ fn check_expr(expr: &mut Expr, expected_ty: Option<Type>) -> Result<Option<Type>
                 //  If the assign statement has a ty, that means it is "fresh", this identifier hasn't been assigned to before.
                 //      In such a case we evaluate the expression with the type given and add it to the symbol table
                 Some(ty) => {
-                    let rval_ty = check_expr(rval, Some(*ty))?;
+                    let rval_ty = check_expr(symbols, names, rval, Some(*ty))?;
                     match resolve_rval_ty(Some(*ty), rval_ty) {
                         Ok(()) => (),
                         Err(e) => return Err(e),
                     }
-                    pineapple_session::insert_variable_into_symbol_table(lval, ty);
+                    symbols.insert_variable(*lval, *ty);
                 }
                 //  If the assign statement does not have a ty, that means it is "old", this identifier has been assigned to before.
                 //      In such a case we evaluate the expression with the stored in the symbol table
                 None => {
-                    let ty = match pineapple_session::get_variable_ty(lval) {
+                    let ty = match symbols.get_variable_ty(lval) {
                         Some(ty) => ty,
-                        None => panic!("undefined variable"),
+                        None => return Err(TypeError::UndefinedVariable(names.get_string(*lval))),
                     };
-                    let rval_ty = check_expr(rval, Some(ty))?;
+                    let rval_ty = check_expr(symbols, names, rval, Some(ty))?;
                     match resolve_rval_ty(Some(ty), rval_ty) {
                         Ok(()) => (),
                         Err(e) => return Err(e),
@@ -134,38 +171,53 @@ fn check_expr(expr: &mut Expr, expected_ty: Option<Type>) -> Result<Option<Type>
             }
             Ok(None)
         }
-        Expr::Binary(left, _, right) => {
-            let ty = check_expr(left, expected_ty)?;
-            check_expr(right, ty)
+        Expr::Binary(left, op, right, operand_ty) => {
+            *operand_ty = check_operands(symbols, names, left, right, expected_ty)?;
+            // Strings can only be concatenated
+            if *operand_ty == Some(ValueTy::STR) && *op != BinOp::Plus {
+                return Err(TypeError::UnsupportedOperator(*op, ValueTy::STR));
+            }
+            Ok(*operand_ty)
         }
-        Expr::Logical(left, _, right) => {
-            let ty = check_expr(left, expected_ty)?;
-            check_expr(right, ty)?;
+        Expr::Logical(left, _, right, operand_ty) => {
+            *operand_ty = check_operands(symbols, names, left, right, None)?;
             Ok(Some(ValueTy::BOOL))
         }
-        Expr::Grouping(group) => check_expr(group, expected_ty),
+        Expr::Grouping(group) => check_expr(symbols, names, group, expected_ty),
+        Expr::Negate(operand, operand_ty) => {
+            *operand_ty = check_expr(symbols, names, operand, expected_ty)?;
+            match *operand_ty {
+                Some(ValueTy::I8) | Some(ValueTy::I16) | Some(ValueTy::I32) | Some(ValueTy::I64)
+                | Some(ValueTy::F32) | Some(ValueTy::F64) => Ok(*operand_ty),
+                Some(ty) => Err(TypeError::UnsupportedOperator(BinOp::Minus, ty)),
+                None => Err(TypeError::ExpectedNestedType),
+            }
+        }
         Expr::Variable(ident) => {
-            match (pineapple_session::get_variable_ty(ident), expected_ty) {
+            match (symbols.get_variable_ty(ident), expected_ty) {
                 (Some(ty), Some(expected_ty)) => {
                     // If we have a variable in our symbol table AND it matches our expected type, we pass
                     if ty == expected_ty {
                         Ok(Some(expected_ty))
                     } else {
-                        Err(TypeError::InvalidVariableType(*ident, expected_ty, ty))
+                        Err(TypeError::InvalidVariableType(names.get_string(*ident), expected_ty, ty))
                     }
                 }
                 (Some(ty), None) => {
                     // If we have a variable w/ no type annotation, we pass as it exists
                     Ok(Some(ty))
                 }
-                _ => Err(TypeError::UndefinedVariable(*ident)),
+                _ => Err(TypeError::UndefinedVariable(names.get_string(*ident))),
             }
         }
-        Expr::Value(value) => {
+        Expr::Value(value, (line, col)) => {
             if let Some(ty) = expected_ty {
                 // If the value is not of the expected type we then we error
                 let value_ty = value.fetch_ty();
 
+                if !value.into_inner().fits(ty) {
+                    return Err(TypeError::LiteralOutOfRange(value.clone(), ty, *line, *col));
+                }
                 if value_ty != ty {
                     match value.try_implicit_cast(ty) {
                         Ok(()) => Ok(expected_ty),
@@ -175,48 +227,190 @@ fn check_expr(expr: &mut Expr, expected_ty: Option<Type>) -> Result<Option<Type>
                     Ok(expected_ty)
                 }
             } else {
-                Ok(expected_ty)
+                Ok(Some(value.fetch_ty()))
             }
         }
-        Expr::CastAs(expr, ty) => check_cast(expr, ty),
-        Expr::Call(callee, args) => check_call(callee, args, expected_ty),
+        Expr::CastAs(expr, ty) => check_cast(symbols, names, expr, ty),
+        Expr::Call(callee, args) => check_call(symbols, names, callee, args, expected_ty),
+        Expr::Interpolation(parts) => {
+            for (part, part_ty) in parts.iter_mut() {
+                *part_ty = check_expr(symbols, names, part, None)?;
+                match *part_ty {
+                    Some(ValueTy::STR) => (),
+                    Some(ty) if converts_to_string(symbols, names, ty) => (),
+                    Some(ty) => return Err(TypeError::NotConvertibleToString(ty)),
+                    None => return Err(TypeError::ExpectedNestedType),
+                }
+            }
+
+            match expected_ty {
+                Some(ty) if ty != ValueTy::STR => Err(TypeError::InvalidExprType(ty, ValueTy::STR)),
+                _ => Ok(Some(ValueTy::STR)),
+            }
+        }
+    }
+}
+
+// Whether there's a to_string (native or not) taking the type, which is what an
+// interpolated expression gets turned into a string with
+fn converts_to_string(symbols: &SymbolTable, names: &Interner, ty: Type) -> bool {
+    let to_string = match names.lookup("to_string") {
+        Some(to_string) => to_string,
+        None => return false,
+    };
+    match symbols.get_function_overloads(&to_string) {
+        Some(overloads) => overloads
+            .iter()
+            .any(|(return_ty, args_ty)| *return_ty == ValueTy::STR && *args_ty == [ty]),
+        None => false,
+    }
+}
+
+// Both operands end up with the same type, which is what the compiler picks the
+// instruction by. A literal on the left takes its type from the right instead of
+// the other way around, so `5 > x` works the same as `x < 5`. Two literals meet at
+// the left one's type when the right one is the only one that fits both, so
+// `-1 + 1` is an i32 add the same as `1 + -1`
+fn check_operands(
+    symbols: &mut SymbolTable,
+    names: &Interner,
+    left: &mut Expr,
+    right: &mut Expr,
+    expected_ty: Option<Type>,
+) -> Result<Option<Type>, TypeError> {
+    if let (None, Expr::Value(l, _), Expr::Value(r, _)) = (expected_ty, &*left, &*right) {
+        let (left_ty, right_ty) = (l.fetch_ty(), r.fetch_ty());
+        if !literal_casts_to(left, right_ty) && literal_casts_to(right, left_ty) {
+            let ty = check_expr(symbols, names, left, None)?;
+            return check_expr(symbols, names, right, ty);
+        }
+    }
+
+    if expected_ty.is_none() && matches!(left, Expr::Value(..)) {
+        let ty = check_expr(symbols, names, right, None)?;
+        check_expr(symbols, names, left, ty)
+    } else {
+        let ty = check_expr(symbols, names, left, expected_ty)?;
+        check_expr(symbols, names, right, ty)
     }
 }
 
 fn check_call(
+    symbols: &mut SymbolTable,
+    names: &Interner,
     callee: &Expr,
     args: &mut Vec<Expr>,
     expected_ty: Option<Type>,
 ) -> Result<Option<Type>, TypeError> {
     if let Expr::Variable(ident) = callee {
-        match (
-            pineapple_session::get_function_ty(ident),
-            pineapple_session::get_function_arg_tys(ident),
-        ) {
-            (Some(return_ty), Some(args_ty)) => {
-                if let Some(expected_ty) = expected_ty {
-                    if expected_ty != return_ty {
-                        panic!("return type did not equal expected type")
-                    }
-                }
+        let overloads = match symbols.get_function_overloads(ident) {
+            Some(overloads) => overloads,
+            None => return Err(TypeError::UndefinedFunction(names.get_string(*ident))),
+        };
+        if let [(return_ty, args_ty)] = overloads.as_slice() {
+            return check_signature(symbols, names, ident, args, expected_ty, *return_ty, args_ty);
+        }
 
-                for i in 0..args_ty.len() {
-                    check_expr(&mut args[i], Some(args_ty[i]))?;
-                }
+        // Overloads all take the same number of arguments
+        let arity = overloads[0].1.len();
+        if args.len() != arity {
+            return Err(TypeError::FunctionArityMismatch(names.get_string(*ident), arity, args.len()));
+        }
 
-                Ok(Some(return_ty))
+        // The arguments are checked once, on their own, to find out what they are
+        let mut arg_tys = vec![];
+        for arg in args.iter_mut() {
+            match check_expr(symbols, names, arg, None)? {
+                Some(ty) => arg_tys.push(ty),
+                None => return Err(TypeError::ExpectedNestedType),
             }
-            _ => {
-                panic!("expected function #1!");
+        }
+
+        // An overload that takes the arguments as they are wins over one they'd
+        // have to be cast for, i.e. abs(x) with an i64 x calls the i64 abs
+        let returns = |return_ty: &Type| expected_ty.map_or(true, |ty| ty == *return_ty);
+        if let Some((return_ty, _)) = overloads
+            .iter()
+            .find(|(return_ty, args_ty)| *args_ty == arg_tys && returns(return_ty))
+        {
+            return Ok(Some(*return_ty));
+        }
+
+        // Otherwise the first overload (in the order they were registered) that
+        // the literals among the arguments can be cast to
+        let castable = |args_ty: &[Type]| {
+            args.iter()
+                .zip(arg_tys.iter().zip(args_ty))
+                .all(|(arg, (arg_ty, ty))| arg_ty == ty || literal_casts_to(arg, *ty))
+        };
+        if let Some((return_ty, args_ty)) = overloads
+            .iter()
+            .find(|(return_ty, args_ty)| returns(return_ty) && castable(args_ty))
+        {
+            // Only the literals are checked again, this time against the overload's
+            // types so they get cast
+            for (arg, (arg_ty, ty)) in args.iter_mut().zip(arg_tys.iter().zip(args_ty)) {
+                if arg_ty != ty {
+                    check_expr(symbols, names, arg, Some(*ty))?;
+                }
             }
+            return Ok(Some(*return_ty));
         }
+        Err(TypeError::NoMatchingOverload(names.get_string(*ident), arg_tys))
     } else {
         panic!("expected function #2!")
     }
 }
 
-fn check_cast(expr: &mut Expr, ty: &mut ValueTy) -> Result<Option<Type>, TypeError> {
-    if let Expr::Value(value) = expr {
+// Whether an argument is made up of nothing but literals that can be cast to the
+// type, checking it against the type is what actually casts them
+fn literal_casts_to(arg: &Expr, ty: Type) -> bool {
+    match arg {
+        Expr::Value(value, _) => value.into_inner().fits(ty) && value.clone().try_implicit_cast(ty).is_ok(),
+        Expr::Grouping(group) => literal_casts_to(group, ty),
+        Expr::Negate(operand, _) => literal_casts_to(operand, ty),
+        Expr::Binary(left, _, right, _) => literal_casts_to(left, ty) && literal_casts_to(right, ty),
+        _ => false,
+    }
+}
+
+fn check_signature(
+    symbols: &mut SymbolTable,
+    names: &Interner,
+    ident: &Ident,
+    args: &mut Vec<Expr>,
+    expected_ty: Option<Type>,
+    return_ty: Type,
+    args_ty: &[Type],
+) -> Result<Option<Type>, TypeError> {
+    if let Some(expected_ty) = expected_ty {
+        if expected_ty != return_ty {
+            return Err(TypeError::InvalidExprType(expected_ty, return_ty));
+        }
+    }
+
+    if args.len() != args_ty.len() {
+        return Err(TypeError::FunctionArityMismatch(
+            names.get_string(*ident),
+            args_ty.len(),
+            args.len(),
+        ));
+    }
+
+    for i in 0..args_ty.len() {
+        check_expr(symbols, names, &mut args[i], Some(args_ty[i]))?;
+    }
+
+    Ok(Some(return_ty))
+}
+
+fn check_cast(
+    symbols: &mut SymbolTable,
+    names: &Interner,
+    expr: &mut Expr,
+    ty: &mut ValueTy,
+) -> Result<Option<Type>, TypeError> {
+    if let Expr::Value(value, _) = expr {
         match value.try_explicit_cast(*ty) {
             Ok(()) => Ok(Some(*ty)),
             Err(()) => Err(TypeError::InvalidValueType(
@@ -226,16 +420,16 @@ fn check_cast(expr: &mut Expr, ty: &mut ValueTy) -> Result<Option<Type>, TypeErr
             )),
         }
     } else if let Expr::Variable(ident) = expr {
-        let var_ty = match pineapple_session::get_variable_ty(ident) {
+        let var_ty = match symbols.get_variable_ty(ident) {
             Some(ty) => ty,
-            None => panic!("undefined variable"),
+            None => return Err(TypeError::UndefinedVariable(names.get_string(*ident))),
         };
         if !Value::can_explicit_cast(var_ty, *ty) {
-            return Err(TypeError::InvalidVariableType(*ident, var_ty, *ty));
+            return Err(TypeError::InvalidVariableType(names.get_string(*ident), var_ty, *ty));
         } else {
             Ok(Some(*ty))
         }
     } else {
-        check_expr(expr, Some(*ty))
+        check_expr(symbols, names, expr, Some(*ty))
     }
 }
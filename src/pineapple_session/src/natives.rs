@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::sync::Arc;
+
+use pineapple_ir::{NoneTy, ValueTy, ValueWrapper};
+
+pub type NativeResult = Result<NativeValue, String>;
+
+// Argument types and return type
+pub type NativeSignature = (Vec<ValueTy>, ValueTy);
+
+// Gets the arguments in the order they were passed, along with the program's output.
+// Send + Sync so that an Engine (and the programs it compiles) can move between threads
+pub type NativeFn = Arc<dyn Fn(&[NativeValue], &mut dyn Write) -> NativeResult + Send + Sync>;
+
+// What natives (and the host calling into a program) take and give back. Strings
+// made while a program runs live in the VM's heap rather than the interner, so
+// natives get (and return) their contents instead of a ValueWrapper::STR, which
+// is never used here
+#[derive(Debug, Clone, PartialEq)]
+pub enum NativeValue {
+    Value(ValueWrapper),
+    Str(String),
+}
+
+impl NativeValue {
+    pub fn none() -> Self {
+        NativeValue::Value(ValueWrapper::NONE(NoneTy::None))
+    }
+
+    // The primitive inside, for natives that only take primitives
+    pub fn primitive(&self) -> Result<ValueWrapper, String> {
+        match self {
+            NativeValue::Value(value) => Ok(*value),
+            NativeValue::Str(s) => Err(format!("Expected a primitive but got {:?}", s)),
+        }
+    }
+
+    pub fn as_str(&self) -> Result<&str, String> {
+        match self {
+            NativeValue::Str(s) => Ok(s),
+            NativeValue::Value(value) => Err(format!("Expected a string but got {:?}", value)),
+        }
+    }
+}
+
+impl From<ValueWrapper> for NativeValue {
+    fn from(value: ValueWrapper) -> Self {
+        NativeValue::Value(value)
+    }
+}
+
+impl From<String> for NativeValue {
+    fn from(s: String) -> Self {
+        NativeValue::Str(s)
+    }
+}
+
+// The way print shows a value, strings without quotes
+impl fmt::Display for NativeValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NativeValue::Value(value) => write!(f, "{:?}", value),
+            NativeValue::Str(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct NativeFunction {
+    // By name rather than interned, the same natives are used with the interner of
+    // every program they're given to
+    pub name: String,
+
+    // Every way of calling the function, the typechecker picks one by the types
+    // of the arguments and the closure gets values of that overload's types. All
+    // of them take the same number of arguments. None for a variadic function
+    // (i.e. print), which takes any number of arguments of any type and can only
+    // be called as a statement
+    pub signatures: Option<Vec<NativeSignature>>,
+
+    pub function: NativeFn,
+}
+
+/*
+    Functions implemented by the host (in Rust) that a program can call like its
+    own. The typechecker gets their signatures, the verifier their arity, and a
+    CALL to one of them runs the closure instead of pushing a frame. A function
+    of the program with the same name takes precedence over a native one.
+*/
+#[derive(Clone, Default)]
+pub struct Natives {
+    functions: Vec<NativeFunction>,
+    indices: HashMap<String, usize>,
+}
+
+impl Natives {
+    pub fn new() -> Self {
+        Natives::default()
+    }
+
+    // Registering a name again replaces the earlier function
+    pub fn register(
+        &mut self,
+        name: &str,
+        arg_tys: Vec<ValueTy>,
+        return_ty: ValueTy,
+        function: impl Fn(&[NativeValue], &mut dyn Write) -> NativeResult + Send + Sync + 'static,
+    ) {
+        self.register_overloads(name, vec![(arg_tys, return_ty)], function);
+    }
+
+    // One closure behind several signatures, it has to handle the argument types
+    // of each of them (i.e. by matching on the ValueWrapper variants)
+    pub fn register_overloads(
+        &mut self,
+        name: &str,
+        signatures: Vec<NativeSignature>,
+        function: impl Fn(&[NativeValue], &mut dyn Write) -> NativeResult + Send + Sync + 'static,
+    ) {
+        assert!(
+            !signatures.is_empty() && signatures.windows(2).all(|w| w[0].0.len() == w[1].0.len()),
+            "The native function '{}' needs overloads that take the same number of arguments",
+            name
+        );
+        self.insert(name, Some(signatures), function);
+    }
+
+    pub fn register_variadic(
+        &mut self,
+        name: &str,
+        function: impl Fn(&[NativeValue], &mut dyn Write) -> NativeResult + Send + Sync + 'static,
+    ) {
+        self.insert(name, None, function);
+    }
+
+    fn insert(
+        &mut self,
+        name: &str,
+        signatures: Option<Vec<NativeSignature>>,
+        function: impl Fn(&[NativeValue], &mut dyn Write) -> NativeResult + Send + Sync + 'static,
+    ) {
+        let native = NativeFunction {
+            name: name.to_string(),
+            signatures,
+            function: Arc::new(function),
+        };
+
+        match self.indices.get(name) {
+            Some(index) => self.functions[*index] = native,
+            None => {
+                self.indices.insert(name.to_string(), self.functions.len());
+                self.functions.push(native);
+            }
+        }
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<usize> {
+        self.indices.get(name).copied()
+    }
+
+    pub fn get(&self, index: usize) -> &NativeFunction {
+        &self.functions[index]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &NativeFunction> {
+        self.functions.iter()
+    }
+
+    // What the verifier checks calls against, Some(None) for a variadic function
+    pub fn arity(&self, name: &str) -> Option<Option<usize>> {
+        self.lookup(name)
+            .map(|index| match &self.get(index).signatures {
+                Some(signatures) => signatures.first().map(|(arg_tys, _)| arg_tys.len()),
+                None => None,
+            })
+    }
+}
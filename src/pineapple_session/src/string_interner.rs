@@ -1,44 +1,47 @@
-use std::cell::RefCell;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
-use std::thread_local;
 
 pub type InternIndex = usize;
 
-thread_local!(
-     static INTERNER: RefCell<Interner> = Default::default()
-);
-
+// Names and string literals of one program. Every compile gets its own and the
+// program keeps it to make sense of the indices in its module, so nothing is
+// shared between programs or outlives the one that interned it
 // TODO :- Improve this
 // https://matklad.github.io/2020/03/22/fast-simple-rust-interner.html
-#[derive(Default)]
-struct Interner {
+#[derive(Debug, Default, Clone)]
+pub struct Interner {
     map: HashMap<String, usize>,
     vec: Vec<String>,
 }
 
 impl Interner {
-    fn intern(&mut self, s: String) -> InternIndex {
-        match self.map.entry(s.clone()) {
+    pub fn new() -> Self {
+        Interner::default()
+    }
+
+    pub fn intern(&mut self, s: &str) -> InternIndex {
+        match self.map.entry(s.to_string()) {
             Entry::Occupied(entry) => *entry.get(),
             Entry::Vacant(entry) => {
                 let slot = self.vec.len();
                 entry.insert(slot);
-                self.vec.push(s);
+                self.vec.push(s.to_string());
                 slot
             }
         }
     }
 
-    fn get(&self, i: usize) -> String {
-        self.vec[i].clone()
+    // The index of a string that has already been interned, without adding it
+    pub fn lookup(&self, s: &str) -> Option<InternIndex> {
+        self.map.get(s).copied()
     }
-}
 
-pub fn intern_string(s: String) -> InternIndex {
-    INTERNER.with(|interner| interner.borrow_mut().intern(s))
-}
+    pub fn get(&self, i: InternIndex) -> &str {
+        &self.vec[i]
+    }
 
-pub fn get_string(i: usize) -> String {
-    INTERNER.with(|interner| interner.borrow().get(i))
+    // An owned copy of get(), for the name_of callbacks of the printers
+    pub fn get_string(&self, i: InternIndex) -> String {
+        self.get(i).to_string()
+    }
 }
@@ -1,31 +1,30 @@
-use std::cell::RefCell;
 use std::collections::HashMap;
 
 use pineapple_ir::ValueTy;
 
 type Ident = usize;
+type Signature = (ValueTy, Vec<ValueTy>);
 
-thread_local! {
-    static SYMBOL_TABLE: RefCell<SymbolTable> = Default::default()
-}
+// Scopes of the program being typechecked, innermost last. Every typecheck gets
+// its own, so nothing is left over from an earlier program (or one that failed)
 #[derive(Default)]
-struct SymbolTable {
+pub struct SymbolTable {
     stack: Vec<SymbolTableContext>,
 }
 
 impl SymbolTable {
-    pub fn add_table(&mut self) {
+    fn add_table(&mut self) {
         self.stack.push(SymbolTableContext::default());
     }
 
-    pub fn get_current_context_mut(&mut self) -> &mut SymbolTableContext {
+    fn get_current_context_mut(&mut self) -> &mut SymbolTableContext {
         match self.stack.last_mut() {
             Some(context) => context,
             None => panic!("unchecked: expected symbol table context"),
         }
     }
 
-    pub fn search_table_for_variable_ty(&self, ident: &Ident) -> Option<ValueTy> {
+    fn search_table_for_variable_ty(&self, ident: &Ident) -> Option<ValueTy> {
         let mut index = self.stack.len() - 1;
 
         loop {
@@ -40,12 +39,14 @@ impl SymbolTable {
         }
     }
 
-    pub fn search_table_for_function_ty(&self, ident: &Ident) -> Option<ValueTy> {
+    // The innermost scope with the function has all of the overloads that are
+    // visible, a function of the program hides every native one with its name
+    fn search_table_for_function_overloads(&self, ident: &Ident) -> Option<Vec<Signature>> {
         let mut index = self.stack.len() - 1;
 
         loop {
-            if let Some(ty) = self.stack[index].get_function_ty(ident) {
-                return Some(ty);
+            if let Some(overloads) = self.stack[index].get_function_overloads(ident) {
+                return Some(overloads);
             }
 
             if index == 0 {
@@ -55,23 +56,46 @@ impl SymbolTable {
         }
     }
 
-    pub fn search_table_for_function_arg_tys(&self, ident: &Ident) -> Option<Vec<ValueTy>> {
-        let mut index = self.stack.len() - 1;
+    fn pop_table(&mut self) {
+        self.stack.pop();
+    }
 
-        loop {
-            if let Some(arg_tys) = self.stack[index].get_function_arg_tys(ident) {
-                return Some(arg_tys);
-            }
+    pub fn insert_context(&mut self) {
+        self.add_table();
+    }
 
-            if index == 0 {
-                return None;
-            }
-            index -= 1;
-        }
+    pub fn pop_context(&mut self) {
+        self.pop_table();
     }
 
-    pub fn pop_table(&mut self) {
-        self.stack.pop();
+    pub fn insert_variable(&mut self, ident: Ident, assoc_ty: ValueTy) {
+        self.get_current_context_mut()
+            .insert_variable(ident, assoc_ty);
+    }
+
+    pub fn insert_function(&mut self, ident: Ident, return_ty: ValueTy, arg_types: Vec<ValueTy>) {
+        self.get_current_context_mut()
+            .insert_function(ident, return_ty, arg_types);
+    }
+
+    // Adds another signature to a function in the current scope instead of replacing it
+    pub fn insert_function_overload(
+        &mut self,
+        ident: Ident,
+        return_ty: ValueTy,
+        arg_types: Vec<ValueTy>,
+    ) {
+        self.get_current_context_mut()
+            .insert_function_overload(ident, return_ty, arg_types);
+    }
+
+    pub fn get_variable_ty(&self, ident: &Ident) -> Option<ValueTy> {
+        self.search_table_for_variable_ty(ident)
+    }
+
+    // (return type, argument types) of every overload, in the order they were inserted
+    pub fn get_function_overloads(&self, ident: &Ident) -> Option<Vec<Signature>> {
+        self.search_table_for_function_overloads(ident)
     }
 }
 
@@ -89,30 +113,46 @@ impl SymbolTableContext {
     pub fn insert_function(&mut self, ident: Ident, return_type: ValueTy, arg_types: Vec<ValueTy>) {
         self.table.insert(
             ident,
-            SymbolData::Fun(FunctionSymbolData {
+            SymbolData::Fun(vec![FunctionSymbolData {
                 return_type,
                 arg_types,
-            }),
+            }]),
         );
     }
 
-    pub fn get_variable_ty(&self, ident: &Ident) -> Option<ValueTy> {
-        match self.table.get(ident) {
-            Some(SymbolData::Var(data)) => Some(data.var_type),
-            _ => None,
+    pub fn insert_function_overload(
+        &mut self,
+        ident: Ident,
+        return_type: ValueTy,
+        arg_types: Vec<ValueTy>,
+    ) {
+        let overload = FunctionSymbolData {
+            return_type,
+            arg_types,
+        };
+        match self.table.get_mut(&ident) {
+            Some(SymbolData::Fun(overloads)) => overloads.push(overload),
+            _ => {
+                self.table.insert(ident, SymbolData::Fun(vec![overload]));
+            }
         }
     }
 
-    pub fn get_function_ty(&self, ident: &Ident) -> Option<ValueTy> {
+    pub fn get_variable_ty(&self, ident: &Ident) -> Option<ValueTy> {
         match self.table.get(ident) {
-            Some(SymbolData::Fun(data)) => Some(data.return_type),
+            Some(SymbolData::Var(data)) => Some(data.var_type),
             _ => None,
         }
     }
 
-    pub fn get_function_arg_tys(&self, ident: &Ident) -> Option<Vec<ValueTy>> {
+    pub fn get_function_overloads(&self, ident: &Ident) -> Option<Vec<Signature>> {
         match self.table.get(ident) {
-            Some(SymbolData::Fun(data)) => Some(data.arg_types.clone()),
+            Some(SymbolData::Fun(overloads)) => Some(
+                overloads
+                    .iter()
+                    .map(|data| (data.return_type, data.arg_types.clone()))
+                    .collect(),
+            ),
             _ => None,
         }
     }
@@ -132,43 +172,5 @@ struct VariableSymbolData {
 #[derive(Debug, Clone)]
 enum SymbolData {
     Var(VariableSymbolData),
-    Fun(FunctionSymbolData),
-}
-
-pub fn insert_context() {
-    SYMBOL_TABLE.with(|table| table.borrow_mut().add_table());
-}
-
-pub fn pop_context() {
-    SYMBOL_TABLE.with(|table| table.borrow_mut().pop_table());
-}
-
-pub fn insert_variable(ident: Ident, assoc_ty: ValueTy) {
-    SYMBOL_TABLE.with(|table| {
-        table
-            .borrow_mut()
-            .get_current_context_mut()
-            .insert_variable(ident, assoc_ty)
-    });
-}
-
-pub fn insert_function(ident: Ident, return_ty: ValueTy, arg_types: Vec<ValueTy>) {
-    SYMBOL_TABLE.with(|table| {
-        table
-            .borrow_mut()
-            .get_current_context_mut()
-            .insert_function(ident, return_ty, arg_types)
-    });
-}
-
-pub fn get_variable_ty(ident: &Ident) -> Option<ValueTy> {
-    SYMBOL_TABLE.with(|table| table.borrow().search_table_for_variable_ty(ident))
-}
-
-pub fn get_function_ty(ident: &Ident) -> Option<ValueTy> {
-    SYMBOL_TABLE.with(|table| table.borrow().search_table_for_function_ty(ident))
-}
-
-pub fn get_function_arg_tys(ident: &Ident) -> Option<Vec<ValueTy>> {
-    SYMBOL_TABLE.with(|table| table.borrow().search_table_for_function_arg_tys(ident))
+    Fun(Vec<FunctionSymbolData>),
 }
@@ -1,15 +1,164 @@
-use pineapple_passes::PassArgs;
+use pineapple_passes::{AsmArgs, CompileArgs, PassArgs, RunArgs};
+use std::path::PathBuf;
 use structopt::StructOpt;
 
-pub fn parse_cli() -> Result<(), Box<dyn std::error::Error>> {
-    let args = PassArgs::from_args();
+#[derive(Debug, StructOpt)]
+#[structopt(name = "pineapple")]
+enum Command {
+    /// Compile a program to a .pbc bytecode file without running it
+    Build {
+        /// Where to write the bytecode, defaults to the input with a .pbc extension
+        #[structopt(long = "output", parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        args: PassArgs,
+    },
+
+    /// Run a .pbc bytecode file produced by `build`
+    Run(RunArgs),
+
+    /// Assemble and run hand written bytecode (.pasm) files
+    Asm(AsmArgs),
+
+    /// Print the disassembly of a .pbc bytecode file
+    Disasm {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+    },
+}
 
-    let content = std::fs::read_to_string(&args.input)?;
-    build(content.as_str(), args)?;
+// `pineapple build|run|asm|disasm ...` are subcommands, anything else is
+// the original `pineapple FILE [ARGS]` that compiles and runs in one go.
+pub fn parse_cli() -> Result<(), Box<dyn std::error::Error>> {
+    match std::env::args().nth(1).as_deref() {
+        Some("build") | Some("run") | Some("asm") | Some("disasm") => match Command::from_args() {
+            Command::Build { output, args } => {
+                let content = std::fs::read_to_string(&args.input)?;
+                let output = output.unwrap_or_else(|| args.input.with_extension("pbc"));
+                build(content.as_str(), output, args)?;
+            }
+            Command::Run(args) => {
+                let content = std::fs::read(&args.input)?;
+                run(&content, args)?;
+            }
+            Command::Asm(args) => assemble(args)?,
+            Command::Disasm { input } => {
+                let content = std::fs::read(&input)?;
+                disassemble(&content)?;
+            }
+        },
+        _ => {
+            let args = CompileArgs::from_args();
+            let content = std::fs::read_to_string(&args.passes.input)?;
+            compile(content.as_str(), args)?;
+        }
+    }
     Ok(())
 }
 
-fn build(buf: &str, args: PassArgs) -> Result<(), String> {
-    pineapple_passes::compile(buf, args);
+fn compile(buf: &str, args: CompileArgs) -> Result<(), String> {
+    match pineapple_passes::compile(buf, args) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(format!("{}", e)),
+    }
+}
+
+fn build(buf: &str, output: PathBuf, args: PassArgs) -> Result<(), String> {
+    let pbc = pineapple_passes::build(buf, args);
+    match std::fs::write(&output, pbc) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(format!("unable to write {}: {}", output.display(), e)),
+    }
+}
+
+fn run(buf: &[u8], args: RunArgs) -> Result<(), String> {
+    match pineapple_passes::run(buf, args) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(format!("{}", e)),
+    }
+}
+
+fn disassemble(buf: &[u8]) -> Result<(), String> {
+    match pineapple_passes::disassemble(buf) {
+        Ok(listing) => {
+            print!("{}", listing);
+            Ok(())
+        }
+        Err(e) => Err(format!("{}", e)),
+    }
+}
+
+fn assemble(args: AsmArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if args.check {
+        return check_assembly(args);
+    }
+
+    for input in &args.inputs {
+        let content = std::fs::read_to_string(input)?;
+        let stdout = std::io::stdout();
+        let mut out = stdout.lock();
+        if let Err(e) = pineapple_passes::assemble(&content, input, &args, &mut out) {
+            return Err(format!("{}: {}", input.display(), e).into());
+        }
+    }
     Ok(())
 }
+
+// Each program lists the lines it should print as `; expect: ...` comments, the
+// VM pads every printed value with a space so trailing whitespace is ignored. A
+// program that should fail (to verify or at runtime) also has an `; error: ...`
+// comment with part of the error message.
+fn check_assembly(args: AsmArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut failed = 0;
+
+    for input in &args.inputs {
+        let content = std::fs::read_to_string(input)?;
+        let expected: Vec<String> = content
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("; expect:"))
+            .map(|line| line.trim().to_string())
+            .collect();
+        let expected_error = content
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("; error:"))
+            .map(|line| line.trim().to_string());
+
+        let mut out: Vec<u8> = vec![];
+        let result = pineapple_passes::assemble(&content, input, &args, &mut out);
+
+        let found: Vec<String> = String::from_utf8_lossy(&out)
+            .lines()
+            .map(|line| line.trim_end().to_string())
+            .collect();
+
+        let error = match (result, &expected_error) {
+            (Err(e), None) => Some(format!("{}", e)),
+            (Err(e), Some(error)) if !e.to_string().contains(error.as_str()) => Some(format!(
+                "expected the error {:?}\n    found    {:?}",
+                error,
+                e.to_string()
+            )),
+            (Ok(()), Some(error)) => Some(format!("expected the error {:?}", error)),
+            _ if found != expected => {
+                Some(format!("expected {:?}\n    found    {:?}", expected, found))
+            }
+            _ => None,
+        };
+
+        match error {
+            None => println!("{:<40}ok", input.display()),
+            Some(e) => {
+                failed += 1;
+                println!("{:<40}FAILED\n    {}", input.display(), e);
+            }
+        }
+    }
+
+    println!("\n{} passed, {} failed", args.inputs.len() - failed, failed);
+    if failed > 0 {
+        Err(format!("{} assembly program(s) failed", failed).into())
+    } else {
+        Ok(())
+    }
+}
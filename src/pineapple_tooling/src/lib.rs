@@ -3,6 +3,9 @@ mod cli;
 pub fn exec_from_command_line() {
     match cli::parse_cli() {
         Ok(_) => (),
-        Err(e) => panic!(format!("{}", e)),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
     }
 }
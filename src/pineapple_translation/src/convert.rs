@@ -3,9 +3,9 @@ use std::collections::{hash_map::Entry, HashMap};
 use pineapple_ast::ast;
 use pineapple_ir::mir::Label;
 use pineapple_ir::op::BinOp;
-use pineapple_ir::ValueTy;
 use pineapple_ir::{mir::Expr, mir::Oper, mir::Stmt, mir::SSA, op::RelOp};
-use pineapple_session::intern_string;
+use pineapple_ir::{Value, ValueTy};
+use pineapple_session::Interner;
 
 type Block = Vec<Stmt>;
 type Identifier = usize;
@@ -13,16 +13,18 @@ type Type = ValueTy;
 
 type Arg = (Identifier, Type);
 
-pub struct LinearCodeTranslator {
+pub struct LinearCodeTranslator<'a> {
+    interner: &'a mut Interner,
     statements: Vec<Block>,
     reg_count: usize,
     label_count: usize,
     backpatch: Vec<usize>,
 }
 
-impl LinearCodeTranslator {
-    pub fn new() -> Self {
+impl<'a> LinearCodeTranslator<'a> {
+    pub fn new(interner: &'a mut Interner) -> Self {
         LinearCodeTranslator {
+            interner,
             statements: vec![],
             reg_count: 0,
             label_count: 0,
@@ -114,8 +116,9 @@ impl LinearCodeTranslator {
     pub fn translate(&mut self, ast: Vec<ast::Stmt>) -> Vec<Block> {
         let mut block = Block::new();
         for stmt in &ast {
-            if let ast::Stmt::Function(function_sym, args, _, body) = stmt {
+            if let ast::Stmt::Function(function_sym, args, _, body, _, line) = stmt {
                 block.push(Stmt::Label(Label::Named(*function_sym)));
+                block.push(Stmt::Line(*line));
                 for arg in args.iter().rev() {
                     block.push(Stmt::Tac(
                         Oper::SSA(SSA::Var(arg.0, 0)),
@@ -147,10 +150,11 @@ impl LinearCodeTranslator {
 
     fn translate_statement(&mut self, stmt: &ast::Stmt, block: &mut Block) {
         match stmt {
-            ast::Stmt::If(ref cond, ref body, ref other) => {
-                self.translate_if_statement(cond, body, other, block);
+            ast::Stmt::If(ref cond, ref body, ref other, line) => {
+                self.translate_if_statement(cond, body, other, *line, block);
             }
-            ast::Stmt::Expression(ref expr) => {
+            ast::Stmt::Expression(ref expr, line) => {
+                block.push(Stmt::Line(*line));
                 self.translate_expression(expr, false, block);
             }
             ast::Stmt::Block(ref statements) => {
@@ -158,15 +162,19 @@ impl LinearCodeTranslator {
                     self.translate_statement(stmt, block);
                 }
             }
-            ast::Stmt::While(ref cond, ref body) => {
-                self.translate_while_statement(cond, body, block);
+            ast::Stmt::While(ref cond, ref body, line) => {
+                self.translate_while_statement(cond, body, *line, block);
             }
-            ast::Stmt::Print(args) => {
+            ast::Stmt::Print(args, line) => {
+                block.push(Stmt::Line(*line));
                 self.translate_print_statement(args, block);
             }
-            ast::Stmt::Return(to_return) => self.translate_return(to_return, block),
-            ast::Stmt::Function(function_sym, args, _, body) => {
-                self.translate_function(function_sym, args, body);
+            ast::Stmt::Return(to_return, line) => {
+                block.push(Stmt::Line(*line));
+                self.translate_return(to_return, block)
+            }
+            ast::Stmt::Function(function_sym, args, _, body, _, line) => {
+                self.translate_function(function_sym, args, body, *line);
             }
         }
     }
@@ -181,10 +189,17 @@ impl LinearCodeTranslator {
         }
     }
 
-    fn translate_function(&mut self, function_sym: &usize, args: &[Arg], body: &ast::Stmt) {
+    fn translate_function(
+        &mut self,
+        function_sym: &usize,
+        args: &[Arg],
+        body: &ast::Stmt,
+        line: usize,
+    ) {
         let mut block_inner = Block::new();
 
         block_inner.push(Stmt::Label(Label::Named(*function_sym)));
+        block_inner.push(Stmt::Line(line));
         for arg in args.iter().rev() {
             block_inner.push(Stmt::Tac(
                 Oper::SSA(SSA::Var(arg.0, 0)),
@@ -208,9 +223,11 @@ impl LinearCodeTranslator {
         cond: &ast::Expr,
         body: &ast::Stmt,
         other: &Option<Box<ast::Stmt>>,
+        line: usize,
         block: &mut Block,
     ) {
         // Translate the condition
+        block.push(Stmt::Line(line));
         let jump_if_label = self.new_label();
         let cond = Expr::Oper(self.translate_expression(cond, true, block));
         let cjump = Stmt::CJump(cond, jump_if_label);
@@ -237,7 +254,7 @@ impl LinearCodeTranslator {
 
             // If we are done with if statements, begin backpatching
             match **stmt {
-                ast::Stmt::If(_, _, _) => {}
+                ast::Stmt::If(_, _, _, _) => {}
                 _ => {
                     let label = self.new_label();
                     block.push(Stmt::Jump(label));
@@ -252,12 +269,23 @@ impl LinearCodeTranslator {
         }
     }
 
-    fn translate_while_statement(&mut self, cond: &ast::Expr, body: &ast::Stmt, block: &mut Block) {
+    fn translate_while_statement(
+        &mut self,
+        cond: &ast::Expr,
+        body: &ast::Stmt,
+        line: usize,
+        block: &mut Block,
+    ) {
         // Before setting up the block we isolate the condition
         let loop_label = self.new_label();
+        block.push(Stmt::Line(line));
         block.push(Stmt::Jump(loop_label));
         block.push(Stmt::Label(loop_label));
 
+        // The condition is jumped back to from the end of the body, so it gets
+        // its own line instead of carrying on from the body's last statement
+        block.push(Stmt::Line(line));
+
         // Translate the condition
         let jump_if_label = self.new_label();
         let cond = Expr::Oper(self.translate_expression(cond, true, block));
@@ -276,7 +304,7 @@ impl LinearCodeTranslator {
     }
 
     fn translate_print_statement(&mut self, args: &[ast::Expr], block: &mut Block) {
-        let name = intern_string("print".to_string());
+        let name = self.interner.intern("print");
         for arg in args {
             let operand = self.translate_expression(arg, false, block);
             block.push(Stmt::StackPush(operand));
@@ -287,17 +315,47 @@ impl LinearCodeTranslator {
 
     fn translate_expression(&mut self, expr: &ast::Expr, is_cond: bool, block: &mut Block) -> Oper {
         match expr {
-            ast::Expr::Value(value) => Oper::Value(value.clone()),
+            ast::Expr::Value(value, _) => Oper::Value(value.clone()),
             ast::Expr::Variable(n) => Oper::SSA(SSA::Var(*n, 0)),
             ast::Expr::Assign(n, _, l) => self.translate_assign(n, l, block),
             ast::Expr::Call(n, args) => self.translate_call(n, args, block),
-            ast::Expr::Binary(l, o, r) => self.translate_binary(l, o, r, block),
-            ast::Expr::Logical(l, o, r) => self.translate_logical(l, o, r, is_cond, block),
+            ast::Expr::Binary(l, o, r, t) => self.translate_binary(l, o, r, t, block),
+            ast::Expr::Logical(l, o, r, t) => self.translate_logical(l, o, r, t, is_cond, block),
+            ast::Expr::Negate(e, t) => self.translate_negate(e, t, block),
             ast::Expr::Grouping(e) => self.translate_expression(e, is_cond, block),
             ast::Expr::CastAs(e, t) => self.translate_cast(e, t, is_cond, block),
+            ast::Expr::Interpolation(parts) => self.translate_interpolation(parts, block),
         }
     }
 
+    // `"a{x}b"` is `"a" + to_string(x) + "b"`, parts that are already strings are
+    // left as they are
+    fn translate_interpolation(
+        &mut self,
+        parts: &[(ast::Expr, Option<Type>)],
+        block: &mut Block,
+    ) -> Oper {
+        let to_string = self.interner.intern("to_string");
+        let mut parts = parts.iter().map(|(part, ty)| match ty {
+            Some(ValueTy::STR) => part.clone(),
+            _ => ast::Expr::Call(Box::new(ast::Expr::Variable(to_string)), vec![part.clone()]),
+        });
+
+        let first = match parts.next() {
+            Some(part) => part,
+            None => return Oper::Value(Value::from(self.interner.intern(""))),
+        };
+        let concat = parts.fold(first, |left, right| {
+            ast::Expr::Binary(
+                Box::new(left),
+                BinOp::Plus,
+                Box::new(right),
+                Some(ValueTy::STR),
+            )
+        });
+        self.translate_expression(&concat, false, block)
+    }
+
     fn translate_cast(
         &mut self,
         expr: &ast::Expr,
@@ -312,7 +370,7 @@ impl LinearCodeTranslator {
             self.translate_expression(expr, is_cond, block);
             block.push(Stmt::CastAs(temp, *t));
             temp
-        } else if let ast::Expr::Value(v) = expr {
+        } else if let ast::Expr::Value(v, _) = expr {
             let temp = self.new_temporary();
             block.push(Stmt::Tac(temp, Expr::Oper(Oper::Value(*v))));
 
@@ -327,15 +385,21 @@ impl LinearCodeTranslator {
             temp
         } else if let ast::Expr::Grouping(e) = expr {
             self.translate_cast(e, t, is_cond, block)
-        } else if let ast::Expr::Binary(left, op, right) = expr {
+        } else if let ast::Expr::Binary(left, op, right, ty) = expr {
+            let temp = self.new_temporary();
+            let res = self.translate_binary(left, op, right, ty, block);
+            block.push(Stmt::Tac(temp, Expr::Oper(res)));
+            block.push(Stmt::CastAs(temp, *t));
+            temp
+        } else if let ast::Expr::Negate(operand, ty) = expr {
             let temp = self.new_temporary();
-            let res = self.translate_binary(left, op, right, block);
+            let res = self.translate_negate(operand, ty, block);
             block.push(Stmt::Tac(temp, Expr::Oper(res)));
             block.push(Stmt::CastAs(temp, *t));
             temp
-        } else if let ast::Expr::Logical(left, op, right) = expr {
+        } else if let ast::Expr::Logical(left, op, right, ty) = expr {
             let temp = self.new_temporary();
-            let res = self.translate_logical(left, op, right, is_cond, block);
+            let res = self.translate_logical(left, op, right, ty, is_cond, block);
             block.push(Stmt::Tac(temp, Expr::Oper(res)));
             block.push(Stmt::CastAs(temp, *t));
             temp
@@ -387,6 +451,7 @@ impl LinearCodeTranslator {
         l: &ast::Expr,
         o: &BinOp,
         r: &ast::Expr,
+        t: &Option<Type>,
         block: &mut Block,
     ) -> Oper {
         let lval = self.new_temporary();
@@ -394,6 +459,7 @@ impl LinearCodeTranslator {
             self.translate_expression(l, false, block),
             *o,
             self.translate_expression(r, false, block),
+            operand_ty(t),
         );
 
         let code = Stmt::Tac(lval, rval);
@@ -402,11 +468,37 @@ impl LinearCodeTranslator {
         lval
     }
 
+    // `-x` is `x * -1`, which wraps for the smallest integer the same way the
+    // other arithmetic does and gives -0.0 for a float 0.0 (unlike `0 - x`)
+    fn translate_negate(&mut self, e: &ast::Expr, t: &Option<Type>, block: &mut Block) -> Oper {
+        let ty = operand_ty(t);
+        let minus_one = match ty {
+            ValueTy::I8 => Value::from(-1i8),
+            ValueTy::I16 => Value::from(-1i16),
+            ValueTy::I32 => Value::from(-1i32),
+            ValueTy::I64 => Value::from(-1i64),
+            ValueTy::F32 => Value::from(-1f32),
+            ValueTy::F64 => Value::from(-1f64),
+            _ => panic!("Only signed numbers can be negated, the typechecker makes sure"),
+        };
+
+        let lval = self.new_temporary();
+        let rval = Expr::Binary(
+            self.translate_expression(e, false, block),
+            BinOp::Star,
+            Oper::Value(minus_one),
+            ty,
+        );
+        block.push(Stmt::Tac(lval, rval));
+        lval
+    }
+
     fn translate_logical(
         &mut self,
         l: &ast::Expr,
         o: &RelOp,
         r: &ast::Expr,
+        t: &Option<Type>,
         is_condition: bool,
         block: &mut Block,
     ) -> Oper {
@@ -416,12 +508,14 @@ impl LinearCodeTranslator {
                 self.translate_expression(l, false, block),
                 o.flip(),
                 self.translate_expression(r, false, block),
+                operand_ty(t),
             )
         } else {
             Expr::Logical(
                 self.translate_expression(l, false, block),
                 *o,
                 self.translate_expression(r, false, block),
+                operand_ty(t),
             )
         };
 
@@ -431,3 +525,8 @@ impl LinearCodeTranslator {
         lval
     }
 }
+
+// The typechecker fills in the operand type of every binary, logical and negate expression
+fn operand_ty(t: &Option<Type>) -> Type {
+    t.expect("Expression should have been typechecked before translation")
+}
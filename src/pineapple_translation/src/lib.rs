@@ -1,5 +1,6 @@
 use pineapple_ast::ast;
 use pineapple_ir::mir::Stmt;
+use pineapple_session::Interner;
 
 use crate::convert::LinearCodeTranslator;
 
@@ -7,7 +8,7 @@ mod convert;
 
 type Block = Vec<Stmt>;
 
-pub fn convert_ast_to_linear_code(ast: Vec<ast::Stmt>) -> Vec<Block> {
-    let mut translator = LinearCodeTranslator::new();
+pub fn convert_ast_to_linear_code(ast: Vec<ast::Stmt>, interner: &mut Interner) -> Vec<Block> {
+    let mut translator = LinearCodeTranslator::new(interner);
     translator.translate(ast)
 }
@@ -0,0 +1,245 @@
+use crate::typed;
+use pineapple_ir::value::ValueContainer;
+use pineapple_ir::{Value, ValueTy, ValueWrapper};
+use pineapple_session::{NativeResult, NativeSignature, NativeValue, Natives};
+
+const INTEGERS: [ValueTy; 8] = [
+    ValueTy::I8,
+    ValueTy::I16,
+    ValueTy::I32,
+    ValueTy::I64,
+    ValueTy::U8,
+    ValueTy::U16,
+    ValueTy::U32,
+    ValueTy::U64,
+];
+
+const FLOATS: [ValueTy; 2] = [ValueTy::F64, ValueTy::F32];
+
+// Applies an expression to the value inside of whichever numeric variant the
+// argument is, keeping the variant
+macro_rules! map_numeric {
+    ($value:expr, $v:ident => signed: $signed:expr, unsigned: $unsigned:expr, float: $float:expr) => {
+        match $value {
+            ValueWrapper::I8($v) => Ok(ValueWrapper::I8($signed).into()),
+            ValueWrapper::I16($v) => Ok(ValueWrapper::I16($signed).into()),
+            ValueWrapper::I32($v) => Ok(ValueWrapper::I32($signed).into()),
+            ValueWrapper::I64($v) => Ok(ValueWrapper::I64($signed).into()),
+            ValueWrapper::U8($v) => Ok(ValueWrapper::U8($unsigned).into()),
+            ValueWrapper::U16($v) => Ok(ValueWrapper::U16($unsigned).into()),
+            ValueWrapper::U32($v) => Ok(ValueWrapper::U32($unsigned).into()),
+            ValueWrapper::U64($v) => Ok(ValueWrapper::U64($unsigned).into()),
+            ValueWrapper::F64($v) => Ok(ValueWrapper::F64($float).into()),
+            ValueWrapper::F32($v) => Ok(ValueWrapper::F32($float).into()),
+            value => Err(format!("Expected a number but got {:?}", value)),
+        }
+    };
+}
+
+// The same for two arguments, which are always of the same type
+macro_rules! zip_numeric {
+    ($a:expr, $b:expr, $x:ident, $y:ident => $res:expr) => {
+        zip_numeric!(@match $a, $b, $x, $y, $res, [I8, I16, I32, I64, U8, U16, U32, U64, F64, F32])
+    };
+    (@match $a:expr, $b:expr, $x:ident, $y:ident, $res:expr, [$($ty:ident),*]) => {
+        match ($a, $b) {
+            $(
+                (ValueWrapper::$ty($x), ValueWrapper::$ty($y)) => Ok(ValueWrapper::$ty($res).into()),
+            )*
+            (a, b) => Err(format!("Expected two numbers of the same type but got {:?} and {:?}", a, b)),
+        }
+    };
+}
+
+/*
+    The natives every program gets, embedders can register more on top:
+
+    print(...)          writes its arguments to the output, on one line
+    abs(x)              absolute value, of any numeric type
+    min(x, y)           the smaller of two numbers of the same type
+    max(x, y)           the larger of two numbers of the same type
+    pow(x, y)           x ^ y, integers can't take a negative exponent
+    sqrt(x)             square root, an f64 for integers and the same type for floats
+    floor(x)            rounds a float down
+    ceil(x)             rounds a float up
+    round(x)            rounds a float to the nearest integer, halfway away from zero
+
+    len(s)              the number of characters in a string, as an i64
+    s[i]                the character at index i, as a string of its own
+    substring(s, a, b)  the characters from index a up to (not including) b
+    to_string(x)        a number or bool written out the way print writes it
+    parse_i64(s)        the other way around, erroring if the string isn't one
+    parse_f64(s)
+    parse_bool(s)
+
+    Integer overflow in abs and pow wraps around in debug and release builds alike,
+    the same as ADD, SUB, MUL and POW do. Strings are indexed by character, not by
+    byte.
+*/
+pub fn standard_natives() -> Natives {
+    let mut natives = Natives::new();
+
+    natives.register_variadic("print", |args, out| {
+        for arg in args {
+            write!(out, "{} ", arg).map_err(|e| e.to_string())?;
+        }
+        writeln!(out).map_err(|e| e.to_string())?;
+        Ok(NativeValue::none())
+    });
+
+    let numeric: Vec<ValueTy> = FLOATS.iter().chain(INTEGERS.iter()).copied().collect();
+
+    natives.register_overloads(
+        "abs",
+        same_type(&numeric, 1),
+        |args, _| map_numeric!(args[0].primitive()?, v => signed: v.wrapping_abs(), unsigned: v, float: v.abs()),
+    );
+    natives.register_overloads(
+        "min",
+        same_type(&numeric, 2),
+        |args, _| zip_numeric!(args[0].primitive()?, args[1].primitive()?, x, y => x.min(y)),
+    );
+    natives.register_overloads(
+        "max",
+        same_type(&numeric, 2),
+        |args, _| zip_numeric!(args[0].primitive()?, args[1].primitive()?, x, y => x.max(y)),
+    );
+    natives.register_overloads("pow", same_type(&numeric, 2), |args, _| {
+        let (x, y) = (args[0].primitive()?, args[1].primitive()?);
+        typed::pow(x.fetch_ty(), x, y)
+            .map(NativeValue::from)
+            .map_err(|e| e.to_string())
+    });
+
+    let mut sqrt = same_type(&FLOATS, 1);
+    sqrt.extend(INTEGERS.iter().map(|ty| (vec![*ty], ValueTy::F64)));
+    natives.register_overloads("sqrt", sqrt, |args, _| match args[0].primitive()? {
+        ValueWrapper::F64(v) => Ok(ValueWrapper::F64(v.sqrt()).into()),
+        ValueWrapper::F32(v) => Ok(ValueWrapper::F32(v.sqrt()).into()),
+        arg => {
+            let mut value = Value::from(arg);
+            match (value.try_explicit_cast(ValueTy::F64), value.into_inner()) {
+                (Ok(()), ValueWrapper::F64(v)) => Ok(ValueWrapper::F64(v.sqrt()).into()),
+                _ => Err(format!("Expected a number but got {:?}", arg)),
+            }
+        }
+    });
+
+    natives.register_overloads("floor", same_type(&FLOATS, 1), |args, _| {
+        map_float(args[0].primitive()?, f64::floor, f32::floor)
+    });
+    natives.register_overloads("ceil", same_type(&FLOATS, 1), |args, _| {
+        map_float(args[0].primitive()?, f64::ceil, f32::ceil)
+    });
+    natives.register_overloads("round", same_type(&FLOATS, 1), |args, _| {
+        map_float(args[0].primitive()?, f64::round, f32::round)
+    });
+
+    natives.register("len", vec![ValueTy::STR], ValueTy::I64, |args, _| {
+        let len = args[0].as_str()?.chars().count();
+        Ok(ValueWrapper::I64(len as i64).into())
+    });
+
+    // `s[i]` is parsed as a call to this one
+    let index = INTEGERS
+        .iter()
+        .map(|ty| (vec![ValueTy::STR, *ty], ValueTy::STR))
+        .collect();
+    natives.register_overloads("[]", index, |args, _| {
+        let s = args[0].as_str()?;
+        let i = index_of(&args[1])?;
+        match s.chars().nth(i) {
+            Some(c) => Ok(NativeValue::Str(c.to_string())),
+            None => Err(format!(
+                "Index {} is out of range for a string of length {}",
+                i,
+                s.chars().count()
+            )),
+        }
+    });
+
+    let substring = INTEGERS
+        .iter()
+        .map(|ty| (vec![ValueTy::STR, *ty, *ty], ValueTy::STR))
+        .collect();
+    natives.register_overloads("substring", substring, |args, _| {
+        let s = args[0].as_str()?;
+        let (start, end) = (index_of(&args[1])?, index_of(&args[2])?);
+        let len = s.chars().count();
+        if start > end || end > len {
+            return Err(format!(
+                "Range {}..{} is out of range for a string of length {}",
+                start, end, len
+            ));
+        }
+        Ok(NativeValue::Str(
+            s.chars().skip(start).take(end - start).collect(),
+        ))
+    });
+
+    let mut to_string: Vec<NativeSignature> =
+        numeric.iter().map(|ty| (vec![*ty], ValueTy::STR)).collect();
+    to_string.push((vec![ValueTy::BOOL], ValueTy::STR));
+    natives.register_overloads("to_string", to_string, |args, _| {
+        Ok(NativeValue::Str(format!("{:?}", args[0].primitive()?)))
+    });
+
+    natives.register("parse_i64", vec![ValueTy::STR], ValueTy::I64, |args, _| {
+        parse(args, ValueWrapper::I64)
+    });
+    natives.register("parse_f64", vec![ValueTy::STR], ValueTy::F64, |args, _| {
+        parse(args, ValueWrapper::F64)
+    });
+    natives.register(
+        "parse_bool",
+        vec![ValueTy::STR],
+        ValueTy::BOOL,
+        |args, _| parse(args, ValueWrapper::BOOL),
+    );
+
+    natives
+}
+
+// One overload per type, taking `arity` arguments of that type and returning it
+fn same_type(tys: &[ValueTy], arity: usize) -> Vec<NativeSignature> {
+    tys.iter().map(|ty| (vec![*ty; arity], *ty)).collect()
+}
+
+fn map_float(value: ValueWrapper, f64: fn(f64) -> f64, f32: fn(f32) -> f32) -> NativeResult {
+    match value {
+        ValueWrapper::F64(v) => Ok(ValueWrapper::F64(f64(v)).into()),
+        ValueWrapper::F32(v) => Ok(ValueWrapper::F32(f32(v)).into()),
+        value => Err(format!("Expected a float but got {:?}", value)),
+    }
+}
+
+// An index into a string, whichever integer type it was given as
+fn index_of(arg: &NativeValue) -> Result<usize, String> {
+    let index = match arg.primitive()? {
+        ValueWrapper::I8(i) => i as i64,
+        ValueWrapper::I16(i) => i as i64,
+        ValueWrapper::I32(i) => i as i64,
+        ValueWrapper::I64(i) => i,
+        ValueWrapper::U8(i) => i as i64,
+        ValueWrapper::U16(i) => i as i64,
+        ValueWrapper::U32(i) => i as i64,
+        ValueWrapper::U64(i) => return Ok(i as usize),
+        value => return Err(format!("Expected an integer index but got {:?}", value)),
+    };
+    if index < 0 {
+        return Err(format!("Negative index {}", index));
+    }
+    Ok(index as usize)
+}
+
+fn parse<T: std::str::FromStr>(args: &[NativeValue], wrap: fn(T) -> ValueWrapper) -> NativeResult {
+    let s = args[0].as_str()?;
+    match s.trim().parse() {
+        Ok(value) => Ok(wrap(value).into()),
+        Err(_) => Err(format!(
+            "Can't parse {:?} as a {}",
+            s,
+            std::any::type_name::<T>()
+        )),
+    }
+}
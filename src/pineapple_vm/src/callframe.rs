@@ -1,18 +1,14 @@
-use pineapple_ir::mir::Label;
+use pineapple_codegen_bytecode::bytecode::NUM_REGISTERS;
+use pineapple_ir::ValueWrapper;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum RegVal {
-    // Module Constants
-    ValueLoc(usize),
+    // Primitives are kept unboxed in registers and stack slots
+    Value(ValueWrapper),
 
-    // Stack locations
-    StackLoc(usize),
-
-    // Memory Locations
+    // Heap objects, owned by the garbage collector
     MemLoc(usize),
 
-    RetAddr(usize),
-
     // "None"
     None,
 }
@@ -22,7 +18,7 @@ pub struct CallFrame {
     pub ip: usize,
     pub base_sp: usize,
     pub chunk_index: usize,
-    pub modified_registers: Vec<(usize, RegVal)>,
+    pub modified_registers: [Option<RegVal>; NUM_REGISTERS],
 }
 
 impl CallFrame {
@@ -31,7 +27,15 @@ impl CallFrame {
             ip,
             base_sp,
             chunk_index,
-            modified_registers: vec![],
+            modified_registers: [None; NUM_REGISTERS],
+        }
+    }
+
+    // Only the value from before the frame started gets restored, later writes
+    // to the same register don't need saving (and would keep garbage alive)
+    pub fn save_register(&mut self, reg: usize, old: RegVal) {
+        if self.modified_registers[reg].is_none() {
+            self.modified_registers[reg] = Some(old);
         }
     }
 }
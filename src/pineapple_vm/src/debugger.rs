@@ -0,0 +1,298 @@
+use crate::callframe::RegVal;
+use crate::vm::{Hook, VM};
+use pineapple_codegen_bytecode::bytecode::OR;
+use pineapple_ir::mir::{type_name, Label};
+use pineapple_ir::ValueTy;
+use std::io::{BufRead, Write};
+
+type Line = usize;
+
+const HELP: &str = "\
+step, s             run one instruction
+next, n             run until the source line changes (steps into calls)
+continue, c         run until a breakpoint or the end of the program
+break, b [@f|LINE]  break when entering function f or reaching LINE, lists them without one
+delete, d N         remove breakpoint N
+registers, r        print the registers
+stack               print the stack, top first
+frames, bt          print the call frames, innermost first
+locals, l           print the variables of the current function
+print, p NAME       print a variable of the current function
+quit, q             stop the program
+An empty line repeats the last command.";
+
+enum Breakpoint {
+    Function(String),
+    Line(Line),
+}
+
+#[derive(Copy, Clone, PartialEq)]
+struct Position {
+    depth: usize,
+    chunk: usize,
+    line: Option<Line>,
+}
+
+enum Mode {
+    Step,
+    Next(Position),
+    Continue,
+}
+
+/*
+    Interactive debugger for --debug-vm. The VM asks it before every instruction
+    whether to pause, and while paused it reads commands from stdin until one of
+    them resumes the program. Running out of input carries on without pausing.
+
+    Variables are found through the chunk's debug variable table, which says
+    where the register allocator put every definition of a source variable.
+*/
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    mode: Mode,
+    last: Option<Position>,
+    last_command: String,
+    detached: bool,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: vec![],
+            mode: Mode::Step,
+            last: None,
+            last_command: String::new(),
+            detached: false,
+        }
+    }
+
+    fn breakpoint_hit(&self, vm: &VM, position: Position, entered: bool) -> bool {
+        // Lines only count when they're reached, not for every instruction on them
+        let new_line = self.last.map_or(true, |last| last != position);
+
+        self.breakpoints.iter().any(|breakpoint| match breakpoint {
+            Breakpoint::Function(name) => entered && function_name(vm, position.chunk) == *name,
+            Breakpoint::Line(line) => new_line && position.line == Some(*line),
+        })
+    }
+
+    fn prompt(&mut self, vm: &VM, position: Position) -> bool {
+        let stdin = std::io::stdin();
+        loop {
+            print!("(pdb) ");
+            std::io::stdout().flush().expect("Unable to write output");
+
+            let mut input = String::new();
+            if stdin.lock().read_line(&mut input).unwrap_or(0) == 0 {
+                println!();
+                self.detached = true;
+                return true;
+            }
+
+            let input = match input.trim() {
+                "" => self.last_command.clone(),
+                input => input.to_string(),
+            };
+            self.last_command = input.clone();
+
+            let mut words = input.split_whitespace();
+            let command = words.next().unwrap_or("");
+            let argument = words.next();
+
+            match command {
+                "step" | "s" => {
+                    self.mode = Mode::Step;
+                    return true;
+                }
+                "next" | "n" => {
+                    self.mode = Mode::Next(position);
+                    return true;
+                }
+                "continue" | "c" => {
+                    self.mode = Mode::Continue;
+                    return true;
+                }
+                "quit" | "q" => return false,
+                "break" | "b" => self.add_breakpoint(argument),
+                "delete" | "d" => self.delete_breakpoint(argument),
+                "registers" | "r" => print_registers(vm),
+                "stack" => print_stack(vm),
+                "frames" | "bt" => print_frames(vm),
+                "locals" | "l" => print_locals(vm, None),
+                "print" | "p" => match argument {
+                    Some(name) => print_locals(vm, Some(name)),
+                    None => println!("Expected a variable name (i.e. `print n`)."),
+                },
+                "help" | "h" => println!("{}", HELP),
+                "" => (),
+                _ => println!("Unknown command `{}`, try `help`.", command),
+            }
+        }
+    }
+
+    fn add_breakpoint(&mut self, argument: Option<&str>) {
+        let breakpoint = match argument {
+            None => {
+                for (i, breakpoint) in self.breakpoints.iter().enumerate() {
+                    match breakpoint {
+                        Breakpoint::Function(name) => println!("{}: @{}", i, name),
+                        Breakpoint::Line(line) => println!("{}: line {}", i, line),
+                    }
+                }
+                return;
+            }
+            Some(argument) => match argument.parse::<Line>() {
+                Ok(line) => Breakpoint::Line(line),
+                Err(_) => Breakpoint::Function(argument.trim_start_matches('@').to_string()),
+            },
+        };
+        self.breakpoints.push(breakpoint);
+        println!("Breakpoint {} set.", self.breakpoints.len() - 1);
+    }
+
+    fn delete_breakpoint(&mut self, argument: Option<&str>) {
+        match argument.and_then(|argument| argument.parse::<usize>().ok()) {
+            Some(i) if i < self.breakpoints.len() => {
+                self.breakpoints.remove(i);
+                println!("Breakpoint {} deleted.", i);
+            }
+            _ => println!("Expected the number of a breakpoint (see `break`)."),
+        }
+    }
+}
+
+impl Hook for Debugger {
+    fn before_instruction(&mut self, vm: &VM, entered: bool) -> bool {
+        let position = position(vm);
+        let pause = match self.mode {
+            Mode::Step => true,
+            Mode::Next(start) => {
+                position.line.is_some()
+                    && (position.depth, position.line) != (start.depth, start.line)
+            }
+            Mode::Continue => false,
+        };
+        let pause = pause || self.breakpoint_hit(vm, position, entered);
+        self.last = Some(position);
+
+        if !pause || self.detached {
+            return true;
+        }
+        println!("{}", location(vm));
+        self.prompt(vm, position)
+    }
+}
+
+fn position(vm: &VM) -> Position {
+    let frame = vm.frames().last().expect("Expect &Callframe to exist");
+    Position {
+        depth: vm.frames().len(),
+        chunk: frame.chunk_index,
+        line: vm.module().chunks[frame.chunk_index].line(frame.ip),
+    }
+}
+
+pub(crate) fn function_name(vm: &VM, chunk: usize) -> String {
+    match vm.module().chunks[chunk].label {
+        Label::Named(sym) => vm.interner().get_string(sym),
+        Label::Marker(marker) => format!("L{}", marker),
+    }
+}
+
+// i.e. `@fibo 0002 (line 2)  GT_I32     r1, r0, 1i32`
+fn location(vm: &VM) -> String {
+    let frame = vm.frames().last().expect("Expect &Callframe to exist");
+    let line = match vm.module().chunks[frame.chunk_index].line(frame.ip) {
+        Some(line) => format!("(line {})", line),
+        None => String::new(),
+    };
+    let instruction = pineapple_codegen_bytecode::disassemble_instruction(
+        vm.module(),
+        frame.chunk_index,
+        frame.ip,
+        &|sym| vm.interner().get_string(sym),
+    );
+    format!(
+        "@{} {:04} {:<10} {}",
+        function_name(vm, frame.chunk_index),
+        frame.ip,
+        line,
+        instruction
+    )
+}
+
+pub(crate) fn print_value(vm: &VM, reg: RegVal) -> String {
+    if let Ok(s) = vm.string_of(reg) {
+        return format!("{:?} ({})", s, type_name(ValueTy::STR));
+    }
+    match vm.value_of(reg) {
+        Ok(value) => format!("{:?} ({})", value, type_name(value.fetch_ty())),
+        Err(_) => "uninitialized".to_string(),
+    }
+}
+
+fn print_registers(vm: &VM) {
+    for (i, reg) in vm.registers().iter().enumerate() {
+        if *reg != RegVal::None {
+            println!("r{:<3} {}", i, print_value(vm, *reg));
+        }
+    }
+}
+
+fn print_stack(vm: &VM) {
+    if vm.stack().is_empty() {
+        println!("The stack is empty.");
+    }
+    for (i, slot) in vm.stack().iter().rev().enumerate() {
+        println!("sp[-{}] {}", i + 1, print_value(vm, *slot));
+    }
+}
+
+fn print_frames(vm: &VM) {
+    for (i, frame) in vm.frames().iter().rev().enumerate() {
+        let line = match vm.module().chunks[frame.chunk_index].line(frame.ip) {
+            Some(line) => format!(" line {}", line),
+            None => String::new(),
+        };
+        println!(
+            "#{} @{} (chunk {}) ip {:04}{} base_sp {}",
+            i,
+            function_name(vm, frame.chunk_index),
+            frame.chunk_index,
+            frame.ip,
+            line,
+            frame.base_sp
+        );
+    }
+}
+
+// Every variable of the current function that has been defined by now, or just
+// the one that's asked for
+fn print_locals(vm: &VM, only: Option<&str>) {
+    let frame = vm.frames().last().expect("Expect &Callframe to exist");
+    let locals = vm.module().chunks[frame.chunk_index].locals_at(frame.ip);
+
+    let mut found = false;
+    for local in locals {
+        let name = vm.interner().get(local.name);
+        if only.map_or(false, |only| only != name) {
+            continue;
+        }
+        found = true;
+
+        let value = match local.location {
+            OR::REG(reg) => print_value(vm, vm.registers()[reg]),
+            OR::STACK(offset) => match vm.stack_slot(offset) {
+                Ok(slot) => print_value(vm, vm.stack()[slot]),
+                Err(_) => "out of scope".to_string(),
+            },
+        };
+        println!("{} = {}", name, value);
+    }
+
+    match only {
+        Some(name) if !found => println!("No variable `{}` here.", name),
+        None if !found => println!("No variables here."),
+        _ => (),
+    }
+}
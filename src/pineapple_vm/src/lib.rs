@@ -1,10 +1,82 @@
 use crate::vm::VM;
 use pineapple_codegen_bytecode::module::Module;
+use pineapple_error::RuntimeError;
+use pineapple_session::{InternIndex, Interner, NativeValue, Natives};
+use std::io::Write;
 
+mod builtins;
 mod callframe;
+mod debugger;
+mod memory;
+mod profiler;
+mod tracer;
+mod typed;
 mod vm;
 
-pub fn execute_vm(module: Module) {
-    let mut vm = VM::new(module);
-    vm.run_module();
+pub use builtins::standard_natives;
+pub use memory::GcStats;
+pub use profiler::Profile;
+
+#[derive(Debug, Default, Clone)]
+pub struct VMOptions {
+    // Run a full collection before every allocation
+    pub gc_stress: bool,
+
+    // Pause before the first instruction and take debugger commands from stdin
+    pub debug_vm: bool,
+
+    // Count instructions, calls, time and loop iterations (ignored with debug_vm)
+    pub profile: bool,
+
+    // Log every instruction to stderr, or only the ones in the function with this
+    // name (ignored with debug_vm or profile)
+    pub trace: Option<Option<String>>,
+}
+
+pub struct RunStats {
+    pub gc: GcStats,
+
+    // Only when VMOptions::profile is set
+    pub profile: Option<Profile>,
+}
+
+// The interner is the one the module was compiled (or loaded) with
+pub fn execute_vm(
+    module: Module,
+    natives: &Natives,
+    interner: &Interner,
+    options: VMOptions,
+) -> Result<RunStats, RuntimeError> {
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    execute_vm_with_output(module, natives, interner, options, &mut out)
+}
+
+pub fn execute_vm_with_output(
+    module: Module,
+    natives: &Natives,
+    interner: &Interner,
+    options: VMOptions,
+    out: &mut dyn Write,
+) -> Result<RunStats, RuntimeError> {
+    let mut vm = VM::new(&module, natives, interner, options, out);
+    vm.run_module()?;
+    Ok(RunStats {
+        gc: vm.gc_stats(),
+        profile: vm.take_profile(),
+    })
+}
+
+// Calls one function of the module instead of running main, i.e. for embedding
+pub fn execute_function(
+    module: &Module,
+    natives: &Natives,
+    interner: &Interner,
+    function: InternIndex,
+    args: &[NativeValue],
+    options: VMOptions,
+    out: &mut dyn Write,
+) -> Result<Option<NativeValue>, RuntimeError> {
+    let mut vm = VM::new(module, natives, interner, options, out);
+    vm.call(function, args)
 }
@@ -0,0 +1,109 @@
+use crate::callframe::RegVal;
+
+// Live slots allowed before the first collection, after that the threshold is
+// twice whatever survived the last collection
+const INITIAL_THRESHOLD: usize = 1024;
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct GcStats {
+    pub allocations: usize,
+    pub collections: usize,
+    pub freed: usize,
+    pub peak_live: usize,
+}
+
+// Everything that can live on the heap
+pub enum Object {
+    // Strings made while the program runs (i.e. by concatenation) and the
+    // module's string constants, the interner is only used by the compiler
+    Str(String),
+}
+
+/*
+    Mark and sweep heap for objects that don't fit in a register. Primitives never
+    end up here, registers and stack slots hold them unboxed. Every slot owns
+    its object, sweeping drops the object and puts the slot on the free list so
+    `RegVal::MemLoc` indices get reused instead of growing forever.
+
+    The VM hands over its roots (registers, the registers saved in every frame,
+    the stack, the return value and the constants) when it collects. Objects
+    don't reference other objects yet, so marking a root is all there is to tracing.
+*/
+pub struct Heap {
+    slots: Vec<Option<Object>>,
+    marks: Vec<bool>,
+    free: Vec<usize>,
+    live: usize,
+    threshold: usize,
+    stress: bool,
+    pub stats: GcStats,
+}
+
+impl Heap {
+    pub fn new(stress: bool) -> Self {
+        Heap {
+            slots: vec![],
+            marks: vec![],
+            free: vec![],
+            live: 0,
+            threshold: INITIAL_THRESHOLD,
+            stress,
+            stats: GcStats::default(),
+        }
+    }
+
+    // In stress mode every allocation collects first, so anything that isn't
+    // rooted gets freed (and reused) as early as possible
+    pub fn should_collect(&self) -> bool {
+        self.stress || self.live >= self.threshold
+    }
+
+    pub fn allocate(&mut self, object: Object) -> RegVal {
+        self.live += 1;
+        self.stats.allocations += 1;
+        self.stats.peak_live = self.stats.peak_live.max(self.live);
+
+        match self.free.pop() {
+            Some(slot) => {
+                self.slots[slot] = Some(object);
+                RegVal::MemLoc(slot)
+            }
+            None => {
+                self.slots.push(Some(object));
+                self.marks.push(false);
+                RegVal::MemLoc(self.slots.len() - 1)
+            }
+        }
+    }
+
+    // None for slots that were never handed out or have been freed
+    pub fn get(&self, slot: usize) -> Option<&Object> {
+        self.slots.get(slot).and_then(|object| object.as_ref())
+    }
+
+    pub fn collect<'a>(&mut self, roots: impl Iterator<Item = &'a RegVal>) {
+        for root in roots {
+            if let RegVal::MemLoc(slot) = root {
+                if let Some(mark) = self.marks.get_mut(*slot) {
+                    *mark = true;
+                }
+            }
+        }
+
+        for (slot, mark) in self.marks.iter_mut().enumerate() {
+            if *mark {
+                *mark = false;
+                continue;
+            }
+
+            if self.slots[slot].take().is_some() {
+                self.free.push(slot);
+                self.live -= 1;
+                self.stats.freed += 1;
+            }
+        }
+
+        self.stats.collections += 1;
+        self.threshold = INITIAL_THRESHOLD.max(self.live * 2);
+    }
+}
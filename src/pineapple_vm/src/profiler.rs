@@ -0,0 +1,272 @@
+use crate::debugger::function_name;
+use crate::vm::{Hook, VM};
+use pineapple_codegen_bytecode::bytecode::Instruction;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+type ChunkIndex = usize;
+type Offset = usize;
+type PathIndex = usize;
+
+const HOT_LOOPS: usize = 10;
+
+#[derive(Debug, Default, Copy, Clone)]
+struct FunctionStats {
+    calls: u64,
+    inclusive: Duration,
+    exclusive: Duration,
+}
+
+struct Activation {
+    chunk: ChunkIndex,
+    start: Instant,
+    children: Duration,
+    path: PathIndex,
+}
+
+/*
+    Profiler for --profile, it watches every instruction the VM runs:
+
+    - how many times each instruction ran, summed up per opcode for the report
+    - calls, inclusive and exclusive time per function. Inclusive time only
+      counts the outermost activation of a recursive function, so it's never
+      more than the whole run
+    - how many times each backwards jump was taken, i.e. the iterations of a loop
+    - how many instructions ran in every distinct call stack, which is what
+      goes in the folded stacks (one `main;fibo;fibo 1234` line per stack, as
+      read by flamegraph.pl and inferno)
+
+    Timestamps are only taken on calls and returns so that timing isn't mostly
+    the profiler timing itself.
+*/
+#[derive(Default)]
+pub struct Profile {
+    names: Vec<String>,
+    executed: Vec<Vec<u64>>,
+    functions: Vec<FunctionStats>,
+    back_edges: HashMap<(ChunkIndex, Offset), u64>,
+
+    // Call stacks as (caller's path, chunk), with the instructions run in each
+    paths: Vec<(Option<PathIndex>, ChunkIndex)>,
+    path_indices: HashMap<(Option<PathIndex>, ChunkIndex), PathIndex>,
+    path_instructions: Vec<u64>,
+
+    stack: Vec<Activation>,
+    last: Option<(usize, ChunkIndex, Offset)>,
+
+    // Filled in from the module once the run is over
+    opcodes: Vec<(String, u64)>,
+    loops: Vec<String>,
+}
+
+impl Profile {
+    pub fn new(vm: &VM) -> Self {
+        let chunks = &vm.module().chunks;
+        Profile {
+            names: (0..chunks.len()).map(|c| function_name(vm, c)).collect(),
+            executed: chunks
+                .iter()
+                .map(|chunk| vec![0; chunk.instructions.len()])
+                .collect(),
+            functions: vec![FunctionStats::default(); chunks.len()],
+            ..Profile::default()
+        }
+    }
+
+    fn path(&mut self, parent: Option<PathIndex>, chunk: ChunkIndex) -> PathIndex {
+        match self.path_indices.get(&(parent, chunk)) {
+            Some(path) => *path,
+            None => {
+                let path = self.paths.len();
+                self.paths.push((parent, chunk));
+                self.path_instructions.push(0);
+                self.path_indices.insert((parent, chunk), path);
+                path
+            }
+        }
+    }
+
+    fn push(&mut self, chunk: ChunkIndex) {
+        let parent = self.stack.last().map(|activation| activation.path);
+        let path = self.path(parent, chunk);
+        self.functions[chunk].calls += 1;
+        self.stack.push(Activation {
+            chunk,
+            start: Instant::now(),
+            children: Duration::default(),
+            path,
+        });
+    }
+
+    fn pop(&mut self, now: Instant) {
+        let activation = self.stack.pop().unwrap();
+        let elapsed = now - activation.start;
+
+        let stats = &mut self.functions[activation.chunk];
+        stats.exclusive += elapsed.checked_sub(activation.children).unwrap_or_default();
+        if !self
+            .stack
+            .iter()
+            .any(|other| other.chunk == activation.chunk)
+        {
+            stats.inclusive += elapsed;
+        }
+
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children += elapsed;
+        }
+    }
+
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        let total: u64 = self.path_instructions.iter().sum();
+
+        writeln!(out, "::Profile::").unwrap();
+        writeln!(out, "{:<24}{}", "Instructions", total).unwrap();
+
+        writeln!(out, "\n{:<24}{:>12}{:>8}", "Opcode", "Count", "%").unwrap();
+        for (opcode, count) in &self.opcodes {
+            let percent = *count as f64 * 100.0 / total.max(1) as f64;
+            writeln!(out, "{:<24}{:>12}{:>7.1}%", opcode, count, percent).unwrap();
+        }
+
+        writeln!(
+            out,
+            "\n{:<24}{:>12}{:>14}{:>14}",
+            "Function", "Calls", "Inclusive", "Exclusive"
+        )
+        .unwrap();
+        let mut functions: Vec<(&String, &FunctionStats)> = self
+            .names
+            .iter()
+            .zip(self.functions.iter())
+            .filter(|(_, stats)| stats.calls > 0)
+            .collect();
+        functions.sort_by(|a, b| b.1.exclusive.cmp(&a.1.exclusive));
+        for (name, stats) in functions {
+            writeln!(
+                out,
+                "{:<24}{:>12}{:>13.6}s{:>13.6}s",
+                format!("@{}", name),
+                stats.calls,
+                stats.inclusive.as_secs_f64(),
+                stats.exclusive.as_secs_f64()
+            )
+            .unwrap();
+        }
+
+        writeln!(out, "\n{:<24}{:>12}", "Hot loops", "Iterations").unwrap();
+        for line in &self.loops {
+            writeln!(out, "{}", line).unwrap();
+        }
+        out
+    }
+
+    pub fn folded(&self) -> String {
+        let mut out = String::new();
+        for (path, count) in self.path_instructions.iter().enumerate() {
+            if *count == 0 {
+                continue;
+            }
+
+            let mut names = vec![];
+            let mut current = Some(path);
+            while let Some(path) = current {
+                let (parent, chunk) = self.paths[path];
+                names.push(self.names[chunk].as_str());
+                current = parent;
+            }
+            names.reverse();
+            writeln!(out, "{} {}", names.join(";"), count).unwrap();
+        }
+        out
+    }
+}
+
+impl Hook for Profile {
+    fn before_instruction(&mut self, vm: &VM, _entered: bool) -> bool {
+        let depth = vm.frames().len();
+        if self.stack.len() > depth {
+            let now = Instant::now();
+            while self.stack.len() > depth {
+                self.pop(now);
+            }
+        }
+        while self.stack.len() < depth {
+            let chunk = vm.frames()[self.stack.len()].chunk_index;
+            self.push(chunk);
+        }
+
+        let frame = vm.frames().last().expect("Expect &Callframe to exist");
+        let (chunk, ip) = (frame.chunk_index, frame.ip);
+        self.executed[chunk][ip] += 1;
+        let path = self.stack.last().unwrap().path;
+        self.path_instructions[path] += 1;
+
+        // Landing at or before a jump in the same activation means it went backwards
+        if let Some((last_depth, last_chunk, last_ip)) = self.last {
+            if last_depth == depth && last_chunk == chunk && ip <= last_ip {
+                *self.back_edges.entry((chunk, last_ip)).or_insert(0) += 1;
+            }
+        }
+        self.last = Some((depth, chunk, ip));
+        true
+    }
+
+    fn finish(&mut self, vm: &VM) {
+        let now = Instant::now();
+        while !self.stack.is_empty() {
+            self.pop(now);
+        }
+
+        let mut opcodes: HashMap<String, u64> = HashMap::new();
+        for (c, chunk) in self.executed.iter().enumerate() {
+            for (offset, count) in chunk.iter().enumerate().filter(|(_, count)| **count > 0) {
+                let instruction = pineapple_codegen_bytecode::disassemble_instruction(
+                    vm.module(),
+                    c,
+                    offset,
+                    &|sym| vm.interner().get_string(sym),
+                );
+                let opcode = instruction
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or("")
+                    .to_string();
+                *opcodes.entry(opcode).or_insert(0) += count;
+            }
+        }
+        self.opcodes = opcodes.into_iter().collect();
+        self.opcodes
+            .sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let mut back_edges: Vec<(&(ChunkIndex, Offset), &u64)> = self.back_edges.iter().collect();
+        back_edges.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        self.loops = back_edges
+            .into_iter()
+            .take(HOT_LOOPS)
+            .map(|((c, offset), iterations)| {
+                let chunk = &vm.module().chunks[*c];
+                let start = match chunk.instructions[*offset] {
+                    Instruction::JUMP(label)
+                    | Instruction::BT(_, label)
+                    | Instruction::BF(_, label) => {
+                        vm.module().labels.get(&label).map(|l| l.instruction_index)
+                    }
+                    _ => None,
+                }
+                .unwrap_or(*offset);
+                let line = match chunk.line(start) {
+                    Some(line) => format!(" line {}", line),
+                    None => String::new(),
+                };
+                format!(
+                    "{:<24}{:>12}",
+                    format!("@{} {:04}-{:04}{}", self.names[*c], start, offset, line),
+                    iterations
+                )
+            })
+            .collect();
+    }
+}
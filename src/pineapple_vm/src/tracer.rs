@@ -0,0 +1,202 @@
+use crate::callframe::RegVal;
+use crate::debugger::{function_name, print_value};
+use crate::vm::{Hook, VM};
+use pineapple_codegen_bytecode::bytecode::{Instruction, IR, OR};
+use pineapple_ir::mir::Label;
+
+// What the VM looked like before a traced instruction, to find what it wrote
+struct Snapshot {
+    line: String,
+    reads: Vec<String>,
+    destination: Option<OR>,
+    registers: Vec<RegVal>,
+    stack: Vec<RegVal>,
+    ret: RegVal,
+}
+
+/*
+    Tracer for --trace, logs every instruction the VM runs to stderr, one line
+    each so that the traces of two runs can be diffed:
+
+    @fibo 0002  GT_I32     r1, r0, 1i32 | r0 = 15 (i32), r1 <- false (bool)
+
+    After the instruction come the operands it reads with their values (constants
+    are already in the instruction) and then everything it wrote, registers as
+    `r1 <- ...`, stack slots as `sp[-1] <- ...` or `push ...` and the return value
+    as `rv <- ...`. Writes are only known once the instruction has run, so a line
+    is printed just before the next instruction.
+
+    With a function name only the instructions of that function are logged.
+*/
+pub struct Tracer {
+    only: Option<String>,
+    pending: Option<Snapshot>,
+}
+
+impl Tracer {
+    pub fn new(only: Option<String>) -> Self {
+        Tracer {
+            only,
+            pending: None,
+        }
+    }
+
+    fn flush(&mut self, vm: &VM) {
+        if let Some(before) = self.pending.take() {
+            let written = writes(vm, &before);
+            let mut items = before.reads;
+            items.extend(written);
+            if items.is_empty() {
+                eprintln!("{}", before.line);
+            } else {
+                eprintln!("{:<56} | {}", before.line, items.join(", "));
+            }
+        }
+    }
+}
+
+impl Hook for Tracer {
+    fn before_instruction(&mut self, vm: &VM, _entered: bool) -> bool {
+        self.flush(vm);
+
+        let frame = vm.frames().last().expect("Expect &Callframe to exist");
+        let chunk = &vm.module().chunks[frame.chunk_index];
+        let traced = match (&self.only, chunk.label) {
+            (None, _) => true,
+            (Some(only), Label::Named(sym)) => vm.interner().get(sym) == only,
+            (Some(_), Label::Marker(_)) => false,
+        };
+        if !traced {
+            return true;
+        }
+
+        let instruction = pineapple_codegen_bytecode::disassemble_instruction(
+            vm.module(),
+            frame.chunk_index,
+            frame.ip,
+            &|sym| vm.interner().get_string(sym),
+        );
+        self.pending = Some(Snapshot {
+            line: format!(
+                "@{} {:04}  {}",
+                function_name(vm, frame.chunk_index),
+                frame.ip,
+                instruction
+            ),
+            reads: reads(vm, &chunk.instructions[frame.ip]),
+            destination: destination(&chunk.instructions[frame.ip]),
+            registers: vm.registers().to_vec(),
+            stack: vm.stack().to_vec(),
+            ret: vm.return_value(),
+        });
+        true
+    }
+
+    // The last instruction either halted or failed, neither of which wrote to
+    // its destination
+    fn finish(&mut self, vm: &VM) {
+        if let Some(before) = &mut self.pending {
+            before.destination = None;
+        }
+        self.flush(vm);
+    }
+}
+
+// The operands the instruction reads, i.e. `r0 = 15 (i32)`
+fn reads(vm: &VM, instruction: &Instruction) -> Vec<String> {
+    let operands = match instruction {
+        Instruction::MOV(_, ir) | Instruction::PUSH(ir) | Instruction::RETURN(ir) => vec![*ir],
+        Instruction::BT(ir, _) | Instruction::BF(ir, _) => vec![*ir],
+        Instruction::CAST(or, _) => match or {
+            OR::REG(reg) => vec![IR::REG(*reg)],
+            OR::STACK(offset) => vec![IR::STACK(*offset)],
+        },
+        Instruction::ADD(_, _, l, r)
+        | Instruction::SUB(_, _, l, r)
+        | Instruction::MUL(_, _, l, r)
+        | Instruction::DIV(_, _, l, r)
+        | Instruction::MOD(_, _, l, r)
+        | Instruction::POW(_, _, l, r)
+        | Instruction::LT(_, _, l, r)
+        | Instruction::LTE(_, _, l, r)
+        | Instruction::GT(_, _, l, r)
+        | Instruction::GTE(_, _, l, r)
+        | Instruction::EQ(_, _, l, r)
+        | Instruction::NEQ(_, _, l, r) => vec![*l, *r],
+        _ => vec![],
+    };
+
+    let mut popped = 0;
+    let mut items: Vec<String> = vec![];
+    for ir in operands {
+        let name = match ir {
+            IR::VALUE(_) => continue,
+            IR::REG(reg) => format!("r{}", reg),
+            IR::STACK(offset) => format!("sp[-{}]", offset),
+            IR::MEMLOC(loc) => format!("mem[{}]", loc),
+            IR::STACKPOP => "pop".to_string(),
+            IR::RETVAL => "rv".to_string(),
+        };
+        let value = match vm.peek_ir(&ir, popped) {
+            Ok(reg) => print_value(vm, reg),
+            Err(_) => "out of bounds".to_string(),
+        };
+        if ir == IR::STACKPOP {
+            popped += 1;
+        }
+
+        let item = format!("{} = {}", name, value);
+        if !items.contains(&item) {
+            items.push(item);
+        }
+    }
+    items
+}
+
+fn destination(instruction: &Instruction) -> Option<OR> {
+    match instruction {
+        Instruction::MOV(or, _)
+        | Instruction::CAST(or, _)
+        | Instruction::ADD(_, or, _, _)
+        | Instruction::SUB(_, or, _, _)
+        | Instruction::MUL(_, or, _, _)
+        | Instruction::DIV(_, or, _, _)
+        | Instruction::MOD(_, or, _, _)
+        | Instruction::POW(_, or, _, _)
+        | Instruction::LT(_, or, _, _)
+        | Instruction::LTE(_, or, _, _)
+        | Instruction::GT(_, or, _, _)
+        | Instruction::GTE(_, or, _, _)
+        | Instruction::EQ(_, or, _, _)
+        | Instruction::NEQ(_, or, _, _) => Some(*or),
+        _ => None,
+    }
+}
+
+// The instruction's destination is always written, even with the value it had,
+// anything else that changed was written too (i.e. registers restored by RETURN)
+fn writes(vm: &VM, before: &Snapshot) -> Vec<String> {
+    let stack = vm.stack();
+    let mut items = vec![];
+    for (i, (old, new)) in before.registers.iter().zip(vm.registers()).enumerate() {
+        if old != new || before.destination == Some(OR::REG(i)) {
+            items.push(format!("r{} <- {}", i, print_value(vm, *new)));
+        }
+    }
+
+    // Slots that were there before are named from the top of the stack as it is now
+    for (i, (old, new)) in before.stack.iter().zip(stack).enumerate() {
+        let offset = stack.len() - i;
+        if old != new || before.destination == Some(OR::STACK(offset)) {
+            items.push(format!("sp[-{}] <- {}", offset, print_value(vm, *new)));
+        }
+    }
+    for new in stack.iter().skip(before.stack.len()) {
+        items.push(format!("push {}", print_value(vm, *new)));
+    }
+
+    if before.ret != vm.return_value() {
+        items.push(format!("rv <- {}", print_value(vm, vm.return_value())));
+    }
+    items
+}
@@ -0,0 +1,139 @@
+use pineapple_error::RuntimeErrorKind;
+use pineapple_ir::{ValueTy, ValueWrapper};
+
+type Result = std::result::Result<ValueWrapper, RuntimeErrorKind>;
+
+/*
+    The arithmetic and comparison instructions carry their operand type, so each
+    one only has a single shape of operands to accept instead of dispatching on
+    whatever the two values happen to be. Anything else is a mistake in the
+    module that the verifier couldn't see (registers aren't typed).
+*/
+macro_rules! typed_ops {
+    ($wrap:ident, $tys:tt, $($name:ident => $op:tt),*) => {
+        $( typed_op!($wrap, $tys, $name, $op); )*
+    };
+}
+
+macro_rules! typed_op {
+    ($wrap:ident, [$($ty:ident),*], $name:ident, $op:tt) => {
+        pub fn $name(ty: ValueTy, a: ValueWrapper, b: ValueWrapper) -> Result {
+            match (ty, a, b) {
+                $(
+                    (ValueTy::$ty, ValueWrapper::$ty(a), ValueWrapper::$ty(b)) => Ok($wrap!($ty, a $op b)),
+                )*
+                (ty, a, b) => Err(mismatch(ty, a, b)),
+            }
+        }
+    };
+}
+
+// Integers wrap around on overflow (the same in debug and release builds), floats
+// go to inf
+macro_rules! arith_op {
+    ($name:ident, $method:ident, $op:tt) => {
+        pub fn $name(ty: ValueTy, a: ValueWrapper, b: ValueWrapper) -> Result {
+            arith_op!(@match ty, a, b, $method, $op, [I8, I16, I32, I64, U8, U16, U32, U64], [F64, F32])
+        }
+    };
+    (@match $ty:expr, $a:expr, $b:expr, $method:ident, $op:tt, [$($int:ident),*], [$($float:ident),*]) => {
+        match ($ty, $a, $b) {
+            $(
+                (ValueTy::$int, ValueWrapper::$int(a), ValueWrapper::$int(b)) => {
+                    Ok(ValueWrapper::$int(a.$method(b)))
+                }
+            )*
+            $(
+                (ValueTy::$float, ValueWrapper::$float(a), ValueWrapper::$float(b)) => {
+                    Ok(ValueWrapper::$float(a $op b))
+                }
+            )*
+            (ty, a, b) => Err(mismatch(ty, a, b)),
+        }
+    };
+}
+
+// Integer division by zero is an error instead of a panic, floats just give inf/NaN
+macro_rules! division_op {
+    ($name:ident, $method:ident, $op:tt) => {
+        pub fn $name(ty: ValueTy, a: ValueWrapper, b: ValueWrapper) -> Result {
+            division_op!(@match ty, a, b, $method, $op, [I8, I16, I32, I64, U8, U16, U32, U64], [F64, F32])
+        }
+    };
+    (@match $ty:expr, $a:expr, $b:expr, $method:ident, $op:tt, [$($int:ident),*], [$($float:ident),*]) => {
+        match ($ty, $a, $b) {
+            $(
+                (ValueTy::$int, ValueWrapper::$int(_), ValueWrapper::$int(0)) => {
+                    Err(RuntimeErrorKind::DivisionByZero)
+                }
+                (ValueTy::$int, ValueWrapper::$int(a), ValueWrapper::$int(b)) => {
+                    Ok(ValueWrapper::$int(a.$method(b)))
+                }
+            )*
+            $(
+                (ValueTy::$float, ValueWrapper::$float(a), ValueWrapper::$float(b)) => {
+                    Ok(ValueWrapper::$float(a $op b))
+                }
+            )*
+            (ty, a, b) => Err(mismatch(ty, a, b)),
+        }
+    };
+}
+
+// Integers wrap around on overflow and can't take a negative exponent, floats
+// take any exponent
+macro_rules! power_op {
+    ($name:ident, [$($int:ident),*], [$($float:ident),*]) => {
+        pub fn $name(ty: ValueTy, a: ValueWrapper, b: ValueWrapper) -> Result {
+            match (ty, a, b) {
+                $(
+                    (ValueTy::$int, ValueWrapper::$int(a), ValueWrapper::$int(b)) => {
+                        if (b as i128) < 0 {
+                            return Err(RuntimeErrorKind::NegativeExponent);
+                        }
+
+                        // Square and multiply, the exponent can be past u32::MAX
+                        let (mut base, mut exponent, mut result) = (a, b, a.wrapping_pow(0));
+                        while exponent > 0 {
+                            if exponent % 2 == 1 {
+                                result = result.wrapping_mul(base);
+                            }
+                            base = base.wrapping_mul(base);
+                            exponent /= 2;
+                        }
+                        Ok(ValueWrapper::$int(result))
+                    }
+                )*
+                $(
+                    (ValueTy::$float, ValueWrapper::$float(a), ValueWrapper::$float(b)) => {
+                        Ok(ValueWrapper::$float(a.powf(b)))
+                    }
+                )*
+                (ty, a, b) => Err(mismatch(ty, a, b)),
+            }
+        }
+    };
+}
+
+macro_rules! compare {
+    ($ty:ident, $res:expr) => {
+        ValueWrapper::BOOL($res)
+    };
+}
+
+arith_op!(add, wrapping_add, +);
+arith_op!(sub, wrapping_sub, -);
+arith_op!(mul, wrapping_mul, *);
+
+division_op!(div, wrapping_div, /);
+division_op!(rem, wrapping_rem, %);
+power_op!(pow, [I8, I16, I32, I64, U8, U16, U32, U64], [F64, F32]);
+
+typed_ops!(compare, [F64, F32, I8, I16, I32, I64, U8, U16, U32, U64, BOOL],
+    lt => <, lte => <=, gt => >, gte => >=, eq => ==, neq => !=);
+
+// Reports whichever operand isn't of the instruction's type
+fn mismatch(ty: ValueTy, a: ValueWrapper, b: ValueWrapper) -> RuntimeErrorKind {
+    let found = if a.fetch_ty() != ty { a } else { b };
+    RuntimeErrorKind::OperandTypeMismatch(ty, found.fetch_ty())
+}
@@ -1,16 +1,36 @@
 use crate::callframe::CallFrame;
 use crate::callframe::RegVal;
+use crate::debugger::Debugger;
+use crate::memory::{GcStats, Heap, Object};
+use crate::profiler::Profile;
+use crate::tracer::Tracer;
+use crate::typed;
+use crate::VMOptions;
 use pineapple_codegen_bytecode::bytecode::Instruction;
-use pineapple_codegen_bytecode::bytecode::{IR, OR};
+use pineapple_codegen_bytecode::bytecode::{IR, NUM_REGISTERS, OR};
 use pineapple_codegen_bytecode::module::Module;
-use pineapple_ir::mir::Label;
-use pineapple_ir::Value;
+use pineapple_error::{RuntimeError, RuntimeErrorKind, StackFrame};
+use pineapple_ir::mir::{print_label, Label};
+use pineapple_ir::ValueTy;
 use pineapple_ir::{value::ValueContainer, ValueWrapper};
+use pineapple_session::{InternIndex, Interner, NativeValue, Natives};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::Write;
+
+// What a CALL to a name ends up running
+#[derive(Debug, Copy, Clone)]
+enum Callee {
+    Chunk(usize),
+    Native(usize),
+}
 
-const NUM_REGISTERS: usize = 16;
+pub struct VM<'a> {
+    module: &'a Module,
 
-pub struct VM {
-    module: Module,
+    // The module's constant pool, unboxed once up front. String constants are
+    // moved to the heap, which is where every string the VM handles lives
+    constants: Vec<RegVal>,
 
     register: [RegVal; NUM_REGISTERS],
 
@@ -20,24 +40,107 @@ pub struct VM {
 
     stack: Vec<RegVal>,
 
-    memory: Vec<pineapple_ir::Value>,
+    memory: Heap,
 
     sp: usize,
+
+    debug: bool,
+
+    profiling: bool,
+
+    trace: Option<Option<String>>,
+
+    profile: Option<Profile>,
+
+    natives: &'a Natives,
+
+    // Resolves the names and string constants in the module
+    interner: &'a Interner,
+
+    // Every name a CALL can use, resolved once up front. Functions of the module
+    // shadow natives with the same name
+    calls: HashMap<InternIndex, Callee>,
+
+    out: &'a mut dyn Write,
 }
 
-impl VM {
-    pub fn new(module: Module) -> Self {
+impl<'a> VM<'a> {
+    pub fn new(
+        module: &'a Module,
+        natives: &'a Natives,
+        interner: &'a Interner,
+        options: VMOptions,
+        out: &'a mut dyn Write,
+    ) -> Self {
+        let mut memory = Heap::new(options.gc_stress);
+        let constants = module
+            .values
+            .iter()
+            .map(|value| match value.into_inner() {
+                ValueWrapper::STR(sym) => memory.allocate(Object::Str(interner.get_string(sym))),
+                value => RegVal::Value(value),
+            })
+            .collect();
+
+        // A native the program never names can't be called, so it needn't be interned
+        let mut calls: HashMap<InternIndex, Callee> = natives
+            .iter()
+            .enumerate()
+            .filter_map(|(index, native)| {
+                let name = interner.lookup(&native.name)?;
+                Some((name, Callee::Native(index)))
+            })
+            .collect();
+        for (label, location) in &module.labels {
+            if let Label::Named(name) = label {
+                calls.insert(*name, Callee::Chunk(location.chunk_index));
+            }
+        }
+
         VM {
             module,
+            constants,
             register: [RegVal::None; NUM_REGISTERS],
             frames: vec![],
             ret: RegVal::None,
             stack: vec![],
-            memory: vec![],
+            memory,
             sp: 0,
+            debug: options.debug_vm,
+            profiling: options.profile,
+            trace: options.trace,
+            profile: None,
+            natives,
+            interner,
+            calls,
+            out,
         }
     }
 
+    pub(crate) fn interner(&self) -> &Interner {
+        self.interner
+    }
+
+    pub(crate) fn module(&self) -> &Module {
+        self.module
+    }
+
+    pub(crate) fn frames(&self) -> &[CallFrame] {
+        &self.frames
+    }
+
+    pub(crate) fn registers(&self) -> &[RegVal] {
+        &self.register
+    }
+
+    pub(crate) fn stack(&self) -> &[RegVal] {
+        &self.stack
+    }
+
+    pub(crate) fn return_value(&self) -> RegVal {
+        self.ret
+    }
+
     fn current_frame(&self) -> &CallFrame {
         self.frames.last().expect("Expect &Callframe to exist")
     }
@@ -48,315 +151,524 @@ impl VM {
             .expect("Expect &mut Callframe to exist")
     }
 
+    // Only RETURN pops a frame, and the VM halts once the frame pushed by `call`
+    // has been popped, so there's always one left to pop
     fn pop_frame(&mut self) {
-        let frame = self.frames.pop().unwrap();
-        for (x, y) in frame.modified_registers {
-            self.register[x] = y;
+        let frame = self.frames.pop().expect("Expect Callframe to exist");
+        for (reg, saved) in frame.modified_registers.iter().enumerate() {
+            if let Some(saved) = saved {
+                self.register[reg] = *saved;
+            }
         }
     }
 
-    fn store_ir(&mut self, or: &OR, ir: &IR) {
-        let value = self.load_ir(ir);
+    // The operand accessors (and `execute`) are forced inline, with the Result
+    // plumbing rustc stops inlining them on its own and fibo(28) gets ~50% slower
+    #[inline(always)]
+    fn store_ir(&mut self, or: &OR, ir: &IR) -> Result<(), RuntimeErrorKind> {
+        let value = self.load_ir(ir)?;
+        self.store_reg(or, value)
+    }
+
+    #[inline(always)]
+    fn store_reg(&mut self, or: &OR, reg: RegVal) -> Result<(), RuntimeErrorKind> {
         match or {
             OR::REG(or) => {
                 let old = self.register[*or];
-                self.current_frame_mut().modified_registers.push((*or, old));
-                self.register[*or] = value;
+                self.current_frame_mut().save_register(*or, old);
+                self.register[*or] = reg;
+            }
+            OR::STACK(ptr) => {
+                let slot = self.stack_slot(*ptr)?;
+                self.stack[slot] = reg;
             }
-            OR::STACK(ptr) => self.stack[self.sp - *ptr] = value,
-            _ => unimplemented!(),
         }
+        Ok(())
     }
 
-    fn store_reg(&mut self, or: &OR, reg: RegVal) {
-        match or {
-            OR::REG(or) => self.register[*or] = reg,
-            OR::STACK(ptr) => self.stack[self.sp - *ptr] = reg,
-            _ => unimplemented!(),
+    #[inline(always)]
+    fn load_ir(&mut self, ir: &IR) -> Result<RegVal, RuntimeErrorKind> {
+        match ir {
+            IR::REG(reg) => Ok(self.register[*reg]),
+            IR::VALUE(ptr) => Ok(self.constants[*ptr]),
+            IR::STACK(ptr) => Ok(self.stack[self.stack_slot(*ptr)?]),
+            IR::MEMLOC(ptr) => Ok(RegVal::MemLoc(*ptr)),
+            IR::STACKPOP => self.stack_pop(),
+            IR::RETVAL => Ok(self.ret),
         }
     }
 
-    fn load_ir(&mut self, ir: &IR) -> RegVal {
+    // What load_ir would give without popping anything, for looking at operands
+    // before the instruction runs. `popped` is how many pops come before this one
+    pub(crate) fn peek_ir(&self, ir: &IR, popped: usize) -> Result<RegVal, RuntimeErrorKind> {
         match ir {
-            IR::REG(reg) => self.register[*reg],
-            IR::VALUE(ptr) => RegVal::ValueLoc(*ptr),
-            IR::STACK(ptr) => RegVal::StackLoc(self.sp - *ptr),
-            IR::MEMLOC(ptr) => RegVal::MemLoc(*ptr),
-            IR::STACKPOP => self.stack_pop(),
-            IR::RETVAL => self.ret,
+            IR::STACKPOP => self
+                .stack
+                .iter()
+                .rev()
+                .nth(popped)
+                .copied()
+                .ok_or(RuntimeErrorKind::StackUnderflow),
+            IR::REG(reg) => Ok(self.register[*reg]),
+            IR::VALUE(ptr) => Ok(self.constants[*ptr]),
+            IR::STACK(ptr) => Ok(self.stack[self.stack_slot(*ptr)?]),
+            IR::MEMLOC(ptr) => Ok(RegVal::MemLoc(*ptr)),
+            IR::RETVAL => Ok(self.ret),
         }
     }
 
-    fn load_reg(&self, reg: RegVal) -> &Value {
+    #[inline(always)]
+    fn load(&mut self, ir: &IR) -> Result<ValueWrapper, RuntimeErrorKind> {
+        let reg = self.load_ir(ir)?;
+        self.value_of(reg)
+    }
+
+    #[inline(always)]
+    pub(crate) fn value_of(&self, reg: RegVal) -> Result<ValueWrapper, RuntimeErrorKind> {
         match reg {
-            RegVal::ValueLoc(ptr) => &self.module.values[ptr],
-            RegVal::StackLoc(ptr) => self.load_reg(self.stack[ptr]),
-            RegVal::MemLoc(ptr) => &self.memory[ptr],
-            _ => panic!(""),
+            RegVal::Value(value) => Ok(value),
+            RegVal::MemLoc(ptr) => match self.memory.get(ptr) {
+                Some(Object::Str(_)) => Err(RuntimeErrorKind::UnexpectedString),
+                None => Err(RuntimeErrorKind::MemoryOutOfBounds(ptr)),
+            },
+            RegVal::None => Err(RuntimeErrorKind::UninitializedValue),
+        }
+    }
+
+    pub(crate) fn string_of(&self, reg: RegVal) -> Result<&str, RuntimeErrorKind> {
+        match reg {
+            RegVal::MemLoc(ptr) => match self.memory.get(ptr) {
+                Some(Object::Str(s)) => Ok(s),
+                None => Err(RuntimeErrorKind::MemoryOutOfBounds(ptr)),
+            },
+            RegVal::Value(value) => Err(RuntimeErrorKind::OperandTypeMismatch(
+                ValueTy::STR,
+                value.fetch_ty(),
+            )),
+            RegVal::None => Err(RuntimeErrorKind::UninitializedValue),
+        }
+    }
+
+    // How a native (or the host) sees a value
+    fn native_value(&self, reg: RegVal) -> Result<NativeValue, RuntimeErrorKind> {
+        match self.string_of(reg) {
+            Ok(s) => Ok(NativeValue::Str(s.to_string())),
+            Err(_) => self.value_of(reg).map(NativeValue::Value),
+        }
+    }
+
+    // The other way around, strings coming from outside of the VM get allocated
+    fn import(&mut self, value: NativeValue) -> RegVal {
+        match value {
+            NativeValue::Str(s) => self.allocate(Object::Str(s)),
+            NativeValue::Value(ValueWrapper::STR(sym)) => {
+                self.allocate(Object::Str(self.interner.get_string(sym)))
+            }
+            NativeValue::Value(value) => RegVal::Value(value),
         }
     }
 
+    // Collects before handing out the slot, so the new value can't be swept
+    // before it's stored anywhere
+    fn allocate(&mut self, object: Object) -> RegVal {
+        if self.memory.should_collect() {
+            let saved = self
+                .frames
+                .iter()
+                .flat_map(|frame| frame.modified_registers.iter().flatten());
+
+            let roots = self
+                .register
+                .iter()
+                .chain(saved)
+                .chain(self.stack.iter())
+                .chain(std::iter::once(&self.ret))
+                .chain(self.constants.iter());
+
+            self.memory.collect(roots);
+        }
+        self.memory.allocate(object)
+    }
+
+    pub fn gc_stats(&self) -> GcStats {
+        self.memory.stats
+    }
+
     fn stack_push(&mut self, value: RegVal) {
         self.stack.push(value);
         self.sp += 1;
     }
 
-    fn stack_pop(&mut self) -> RegVal {
-        let value = self.stack.pop().unwrap();
+    #[inline(always)]
+    fn stack_pop(&mut self) -> Result<RegVal, RuntimeErrorKind> {
+        let value = self.stack.pop().ok_or(RuntimeErrorKind::StackUnderflow)?;
         self.sp -= 1;
-        value
+        Ok(value)
+    }
+
+    // Index of sp[-offset] in the stack
+    #[inline(always)]
+    pub(crate) fn stack_slot(&self, offset: usize) -> Result<usize, RuntimeErrorKind> {
+        if offset == 0 || offset > self.sp {
+            return Err(RuntimeErrorKind::StackOutOfBounds(offset, self.sp));
+        }
+        Ok(self.sp - offset)
     }
 
-    pub fn run_module(&mut self) {
-        let main_chunk_index = self
+    // The verifier (`ChunkVerifier::target`) rejects a jump to an undefined label
+    fn jump(&mut self, label: &Label) {
+        let location = self
             .module
-            .chunks
-            .iter()
-            .position(|chunk| match chunk.label {
-                Label::Marker(_) => false,
-                Label::Named(name) => {
-                    if name == pineapple_session::intern_string("main".to_string()) {
-                        true
-                    } else {
-                        false
-                    }
-                }
-            })
-            .unwrap();
+            .labels
+            .get(label)
+            .expect("Expect jump target to exist");
+        self.current_frame_mut().ip = location.instruction_index;
+    }
+
+    pub fn run_module(&mut self) -> Result<(), RuntimeError> {
+        match self.interner.lookup("main") {
+            Some(main) => self.call(main, &[]).map(|_| ()),
+            None => Err(RuntimeError {
+                kind: RuntimeErrorKind::UndefinedFunction("main".to_string()),
+                trace: vec![],
+            }),
+        }
+    }
 
-        let frame = CallFrame::new(0, self.sp, main_chunk_index);
+    // Runs the function to completion with the arguments pushed the same way a CALL
+    // passes them, and gives back what it returned (if anything)
+    pub fn call(
+        &mut self,
+        name: InternIndex,
+        args: &[NativeValue],
+    ) -> Result<Option<NativeValue>, RuntimeError> {
+        let chunk_index = match self.module.labels.get(&Label::Named(name)) {
+            Some(location) => location.chunk_index,
+            None => {
+                return Err(RuntimeError {
+                    kind: RuntimeErrorKind::UndefinedFunction(self.interner.get_string(name)),
+                    trace: vec![],
+                })
+            }
+        };
+
+        // Pushed one at a time, so a collection can't free the earlier ones
+        for arg in args {
+            let arg = self.import(arg.clone());
+            self.stack_push(arg);
+        }
+        let frame = CallFrame::new(0, self.sp, chunk_index);
         self.frames.push(frame);
 
-        self.dispatch();
+        self.ret = RegVal::None;
+        self.run()?;
+        match self.ret {
+            RegVal::None => Ok(None),
+            ret => match self.native_value(ret) {
+                Ok(value) => Ok(Some(value)),
+                Err(kind) => Err(self.runtime_error(kind)),
+            },
+        }
+    }
+
+    fn run(&mut self) -> Result<(), RuntimeError> {
+        if self.debug {
+            self.hooked_dispatch(&mut Debugger::new())
+        } else if self.profiling {
+            let mut profile = Profile::new(self);
+            let result = self.hooked_dispatch(&mut profile);
+            self.profile = Some(profile);
+            result
+        } else if let Some(only) = self.trace.clone() {
+            self.hooked_dispatch(&mut Tracer::new(only))
+        } else {
+            self.dispatch()
+        }
+    }
+
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profile.take()
     }
 
-    fn dispatch(&mut self) {
+    #[inline(always)]
+    fn current_instruction(&self) -> Instruction {
+        let frame = self.current_frame();
+        self.module.chunks[frame.chunk_index].instructions[frame.ip]
+    }
+
+    fn dispatch(&mut self) -> Result<(), RuntimeError> {
         loop {
-            let instruction: Instruction = {
-                let frame = self.current_frame();
-                let chunk_index = frame.chunk_index;
-                self.module.chunks[chunk_index].instructions[frame.ip]
-            };
-
-            match &instruction {
-                Instruction::LABEL(_) => (),
-                Instruction::MOV(or, ir) => {
-                    self.store_ir(or, ir);
-                }
+            let instruction = self.current_instruction();
 
-                Instruction::ADD(or, ir1, ir2) => {
-                    let ir1 = self.load_ir(ir1);
-                    let ir2 = self.load_ir(ir2);
-                    let a = self.load_reg(ir1);
-                    let b = self.load_reg(ir2);
+            match self.execute(&instruction) {
+                Ok(Control::Next) => self.current_frame_mut().ip += 1,
+                Ok(Control::Halt) => return Ok(()),
+                Err(kind) => return Err(self.runtime_error(kind)),
+            }
+        }
+    }
 
-                    let res: Value = *a + *b;
+    // Same loop as dispatch with a hook (debugger, profiler or tracer) looking at
+    // every instruction first, kept apart so that a normal run doesn't pay for it
+    fn hooked_dispatch<H: Hook>(&mut self, hook: &mut H) -> Result<(), RuntimeError> {
+        let mut depth = 0;
+        let result = loop {
+            let entered = self.frames.len() > depth;
+            depth = self.frames.len();
+            if !hook.before_instruction(self, entered) {
+                break Ok(());
+            }
 
-                    // Need to implement GC (or not? :thinking:)
-                    let vmreg = RegVal::MemLoc(self.memory.len());
-                    self.memory.push(res);
+            let instruction = self.current_instruction();
 
-                    self.store_reg(or, vmreg);
+            match self.execute(&instruction) {
+                Ok(Control::Next) => self.current_frame_mut().ip += 1,
+                Ok(Control::Halt) => break Ok(()),
+                Err(kind) => break Err(self.runtime_error(kind)),
+            }
+        };
+        hook.finish(self);
+        result
+    }
+
+    // Innermost frame first, every other frame is sitting on the CALL it made
+    fn runtime_error(&self, kind: RuntimeErrorKind) -> RuntimeError {
+        let trace = self
+            .frames
+            .iter()
+            .rev()
+            .map(|frame| {
+                let chunk = &self.module.chunks[frame.chunk_index];
+                StackFrame {
+                    chunk: print_label(&chunk.label, &|sym| self.interner.get_string(sym)),
+                    offset: frame.ip,
+                    line: chunk.line(frame.ip),
                 }
+            })
+            .collect();
 
-                Instruction::SUB(or, ir1, ir2) => {
-                    let ir1 = self.load_ir(ir1);
-                    let ir2 = self.load_ir(ir2);
-                    let a = self.load_reg(ir1);
-                    let b = self.load_reg(ir2);
+        RuntimeError { kind, trace }
+    }
 
-                    let res: Value = *a - *b;
+    #[inline(always)]
+    fn execute(&mut self, instruction: &Instruction) -> Result<Control, RuntimeErrorKind> {
+        match instruction {
+            Instruction::LABEL(_) => (),
+            Instruction::MOV(or, ir) => {
+                self.store_ir(or, ir)?;
+            }
 
-                    // Need to implement GC (or not? :thinking:)
-                    let vmreg = RegVal::MemLoc(self.memory.len());
-                    self.memory.push(res);
+            Instruction::ADD(ValueTy::STR, or, ir1, ir2) => {
+                let a = self.load_ir(ir1)?;
+                let b = self.load_ir(ir2)?;
 
-                    self.store_reg(or, vmreg);
-                }
+                let s = [self.string_of(a)?, self.string_of(b)?].concat();
+                let reg = self.allocate(Object::Str(s));
+                self.store_reg(or, reg)?;
+            }
 
-                Instruction::MUL(or, ir1, ir2) => {
-                    let ir1 = self.load_ir(ir1);
-                    let ir2 = self.load_ir(ir2);
-                    let a = self.load_reg(ir1);
-                    let b = self.load_reg(ir2);
+            Instruction::ADD(ty, or, ir1, ir2) => {
+                let a = self.load(ir1)?;
+                let b = self.load(ir2)?;
 
-                    let res: Value = *a * *b;
+                self.store_reg(or, RegVal::Value(typed::add(*ty, a, b)?))?;
+            }
 
-                    // Need to implement GC (or not? :thinking:)
-                    let vmreg = RegVal::MemLoc(self.memory.len());
-                    self.memory.push(res);
+            Instruction::SUB(ty, or, ir1, ir2) => {
+                let a = self.load(ir1)?;
+                let b = self.load(ir2)?;
 
-                    self.store_reg(or, vmreg);
-                }
+                self.store_reg(or, RegVal::Value(typed::sub(*ty, a, b)?))?;
+            }
 
-                Instruction::DIV(or, ir1, ir2) => {
-                    let ir1 = self.load_ir(ir1);
-                    let ir2 = self.load_ir(ir2);
-                    let a = self.load_reg(ir1);
-                    let b = self.load_reg(ir2);
+            Instruction::MUL(ty, or, ir1, ir2) => {
+                let a = self.load(ir1)?;
+                let b = self.load(ir2)?;
 
-                    let res: Value = *a / *b;
+                self.store_reg(or, RegVal::Value(typed::mul(*ty, a, b)?))?;
+            }
 
-                    // Need to implement GC (or not? :thinking:)
-                    let vmreg = RegVal::MemLoc(self.memory.len());
-                    self.memory.push(res);
+            Instruction::DIV(ty, or, ir1, ir2) => {
+                let a = self.load(ir1)?;
+                let b = self.load(ir2)?;
 
-                    self.store_reg(or, vmreg);
-                }
+                self.store_reg(or, RegVal::Value(typed::div(*ty, a, b)?))?;
+            }
 
-                Instruction::MOD(or, ir1, ir2) => {
-                    let ir1 = self.load_ir(ir1);
-                    let ir2 = self.load_ir(ir2);
-                    let a = self.load_reg(ir1);
-                    let b = self.load_reg(ir2);
+            Instruction::MOD(ty, or, ir1, ir2) => {
+                let a = self.load(ir1)?;
+                let b = self.load(ir2)?;
 
-                    let res: Value = *a % *b;
+                self.store_reg(or, RegVal::Value(typed::rem(*ty, a, b)?))?;
+            }
 
-                    // Need to implement GC (or not? :thinking:)
-                    let vmreg = RegVal::MemLoc(self.memory.len());
-                    self.memory.push(res);
+            Instruction::POW(ty, or, ir1, ir2) => {
+                let a = self.load(ir1)?;
+                let b = self.load(ir2)?;
 
-                    self.store_reg(or, vmreg);
-                }
+                self.store_reg(or, RegVal::Value(typed::pow(*ty, a, b)?))?;
+            }
 
-                Instruction::LT(or, ir1, ir2) => {
-                    let ir1 = self.load_ir(ir1);
-                    let ir2 = self.load_ir(ir2);
-                    let a = self.load_reg(ir1);
-                    let b = self.load_reg(ir2);
+            // Strings compare by their contents, not by where they are
+            Instruction::LT(ValueTy::STR, or, ir1, ir2)
+            | Instruction::LTE(ValueTy::STR, or, ir1, ir2)
+            | Instruction::GT(ValueTy::STR, or, ir1, ir2)
+            | Instruction::GTE(ValueTy::STR, or, ir1, ir2)
+            | Instruction::EQ(ValueTy::STR, or, ir1, ir2)
+            | Instruction::NEQ(ValueTy::STR, or, ir1, ir2) => {
+                let a = self.load_ir(ir1)?;
+                let b = self.load_ir(ir2)?;
+
+                let ordering = self.string_of(a)?.cmp(self.string_of(b)?);
+                let result = match instruction {
+                    Instruction::LT(..) => ordering == Ordering::Less,
+                    Instruction::LTE(..) => ordering != Ordering::Greater,
+                    Instruction::GT(..) => ordering == Ordering::Greater,
+                    Instruction::GTE(..) => ordering != Ordering::Less,
+                    Instruction::EQ(..) => ordering == Ordering::Equal,
+                    _ => ordering != Ordering::Equal,
+                };
+                self.store_reg(or, RegVal::Value(ValueWrapper::BOOL(result)))?;
+            }
 
-                    let res: Value = Value::from(*a < *b);
+            Instruction::LT(ty, or, ir1, ir2) => {
+                let a = self.load(ir1)?;
+                let b = self.load(ir2)?;
 
-                    let vmreg = RegVal::MemLoc(self.memory.len());
-                    self.memory.push(res);
+                self.store_reg(or, RegVal::Value(typed::lt(*ty, a, b)?))?;
+            }
 
-                    self.store_reg(or, vmreg);
-                }
+            Instruction::LTE(ty, or, ir1, ir2) => {
+                let a = self.load(ir1)?;
+                let b = self.load(ir2)?;
 
-                Instruction::LTE(or, ir1, ir2) => {
-                    let ir1 = self.load_ir(ir1);
-                    let ir2 = self.load_ir(ir2);
-                    let a = self.load_reg(ir1);
-                    let b = self.load_reg(ir2);
+                self.store_reg(or, RegVal::Value(typed::lte(*ty, a, b)?))?;
+            }
 
-                    let res: Value = Value::from(*a <= *b);
+            Instruction::GT(ty, or, ir1, ir2) => {
+                let a = self.load(ir1)?;
+                let b = self.load(ir2)?;
 
-                    let vmreg = RegVal::MemLoc(self.memory.len());
-                    self.memory.push(res);
+                self.store_reg(or, RegVal::Value(typed::gt(*ty, a, b)?))?;
+            }
 
-                    self.store_reg(or, vmreg);
-                }
+            Instruction::GTE(ty, or, ir1, ir2) => {
+                let a = self.load(ir1)?;
+                let b = self.load(ir2)?;
 
-                Instruction::GT(or, ir1, ir2) => {
-                    let ir1 = self.load_ir(ir1);
-                    let ir2 = self.load_ir(ir2);
-                    let a = self.load_reg(ir1);
-                    let b = self.load_reg(ir2);
+                self.store_reg(or, RegVal::Value(typed::gte(*ty, a, b)?))?;
+            }
 
-                    let res: Value = Value::from(*a > *b);
+            Instruction::EQ(ty, or, ir1, ir2) => {
+                let a = self.load(ir1)?;
+                let b = self.load(ir2)?;
 
-                    let vmreg = RegVal::MemLoc(self.memory.len());
-                    self.memory.push(res);
+                self.store_reg(or, RegVal::Value(typed::eq(*ty, a, b)?))?;
+            }
 
-                    self.store_reg(or, vmreg);
-                }
+            Instruction::NEQ(ty, or, ir1, ir2) => {
+                let a = self.load(ir1)?;
+                let b = self.load(ir2)?;
 
-                Instruction::GTE(or, ir1, ir2) => {
-                    let ir1 = self.load_ir(ir1);
-                    let ir2 = self.load_ir(ir2);
-                    let a = self.load_reg(ir1);
-                    let b = self.load_reg(ir2);
+                self.store_reg(or, RegVal::Value(typed::neq(*ty, a, b)?))?;
+            }
 
-                    let res: Value = Value::from(*a >= *b);
+            Instruction::CAST(or, ty) => {
+                let ir = match or {
+                    OR::REG(reg) => IR::REG(*reg),
+                    OR::STACK(ptr) => IR::STACK(*ptr),
+                };
+                let value = self.load(&ir)?;
+                let res = value
+                    .try_explicit_cast(*ty)
+                    .map_err(|_| RuntimeErrorKind::InvalidCast(value.fetch_ty(), *ty))?;
+
+                self.store_reg(or, RegVal::Value(res))?;
+            }
 
-                    let vmreg = RegVal::MemLoc(self.memory.len());
-                    self.memory.push(res);
+            Instruction::POP(or) => {
+                let vmreg = self.stack_pop()?;
+                self.store_reg(or, vmreg)?;
+            }
 
-                    self.store_reg(or, vmreg);
-                }
+            Instruction::JUMP(label) => self.jump(label),
 
-                Instruction::JUMP(label) => match label {
-                    Label::Marker(_) => {
-                        self.current_frame_mut().ip =
-                            self.module.labels.get(label).unwrap().instruction_index;
-                    }
-                    _ => (),
-                },
-
-                Instruction::BT(ir, label) => {
-                    // We'll prob need to do "local" chunk labels and "global" named labels in Module
-                    let vmreg = self.load_ir(ir);
-                    let val = self.load_reg(vmreg);
-                    let res = val.into_inner() == ValueWrapper::BOOL(true);
-
-                    if res {
-                        match label {
-                            Label::Marker(_) => {
-                                self.current_frame_mut().ip =
-                                    self.module.labels.get(label).unwrap().instruction_index;
-                            }
-                            _ => panic!(""),
-                        }
-                    }
+            Instruction::BT(ir, label) => {
+                // We'll prob need to do "local" chunk labels and "global" named labels in Module
+                if self.load(ir)? == ValueWrapper::BOOL(true) {
+                    self.jump(label);
                 }
+            }
 
-                Instruction::PUSH(ir) => {
-                    let res = self.load_ir(ir);
-                    self.stack_push(res);
+            Instruction::BF(ir, label) => {
+                if self.load(ir)? == ValueWrapper::BOOL(false) {
+                    self.jump(label);
                 }
+            }
 
-                Instruction::RETURN(ir) => {
-                    let return_value = self.load_ir(ir);
-                    self.pop_frame();
+            Instruction::PUSH(ir) => {
+                let res = self.load_ir(ir)?;
+                self.stack_push(res);
+            }
 
-                    if self.frames.is_empty() {
-                        return;
-                    }
+            Instruction::RETURN(ir) => {
+                let return_value = self.load_ir(ir)?;
+                self.pop_frame();
+                self.ret = return_value;
 
-                    self.ret = return_value;
+                if self.frames.is_empty() {
+                    return Ok(Control::Halt);
                 }
+            }
 
-                Instruction::CALL(intern, arity) => {
-                    if pineapple_session::get_string(*intern) == "print" {
-                        let mut values = vec![RegVal::None; *arity];
-                        for i in 0..*arity {
-                            let value = self
-                                .stack
-                                .pop()
-                                .expect("The stack should have a value here!");
-                            values[i] = value;
-                        }
+            Instruction::CALL(intern, arity) => match self.calls.get(intern).copied() {
+                Some(Callee::Chunk(chunk_index)) => {
+                    // We can do tail recursion optimization here
+                    let callframe = CallFrame::new(0, self.sp, chunk_index);
+                    self.frames.push(callframe);
+                }
+                Some(Callee::Native(index)) => {
+                    let mut args = vec![NativeValue::none(); *arity];
+                    for i in (0..*arity).rev() {
+                        let reg = self.stack_pop()?;
+                        args[i] = self.native_value(reg)?;
+                    }
 
-                        for vmreg in values.iter().rev() {
-                            print!("{:?} ", self.load_reg(*vmreg));
+                    let native = self.natives.get(index);
+                    match (native.function)(&args, &mut *self.out) {
+                        Ok(NativeValue::Value(ValueWrapper::NONE(_))) => (),
+                        Ok(value) => self.ret = self.import(value),
+                        Err(message) => {
+                            return Err(RuntimeErrorKind::NativeError(native.name.clone(), message))
                         }
-
-                        println!("");
-                    } else if self.module.labels.contains_key(&Label::Named(*intern)) {
-                        // We can do tail recursion optimization here
-
-                        let chunk_index = self
-                            .module
-                            .labels
-                            .get(&Label::Named(*intern))
-                            .unwrap()
-                            .chunk_index;
-
-                        let callframe = CallFrame::new(0, self.sp, chunk_index);
-                        self.frames.push(callframe);
-                    } else {
-                        // This "should" get found during static analysis
-                        panic!();
                     }
                 }
-                Instruction::NOP => (),
-                Instruction::HLT => {
-                    break;
+                // This "should" get found during static analysis
+                None => {
+                    let name = self.interner.get_string(*intern);
+                    return Err(RuntimeErrorKind::UndefinedFunction(name));
                 }
-                _ => unimplemented!("{:?} not implemented", instruction),
+            },
+            Instruction::NOP => (),
+            Instruction::HLT => {
+                return Ok(Control::Halt);
             }
-
-            self.current_frame_mut().ip += 1;
         }
+
+        Ok(Control::Next)
     }
 }
+
+enum Control {
+    Next,
+    Halt,
+}
+
+// Something that watches the VM run an instruction at a time, see hooked_dispatch
+pub(crate) trait Hook {
+    // `entered` is set on the first instruction of a call, false means quit
+    fn before_instruction(&mut self, vm: &VM, entered: bool) -> bool;
+
+    // The program has halted, failed or been quit
+    fn finish(&mut self, _vm: &VM) {}
+}